@@ -0,0 +1,74 @@
+struct DrawUniforms {
+    color: vec4<f32>,
+    model_transform: mat4x4<f32>,
+    camera_transform: mat4x4<f32>,
+}
+
+struct ChunkUniforms {
+    time: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> uniforms: DrawUniforms;
+
+@group(1) @binding(0)
+var t: texture_2d<f32>;
+
+@group(1) @binding(1)
+var s: sampler;
+
+@group(3) @binding(0)
+var<uniform> chunk: ChunkUniforms;
+
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    // Atlas rect of the face as x, y, width, height. Zero when tex_coords are already in the atlas
+    @location(2) rect: vec4<f32>,
+    // Frame count (negative to blend frames), seconds per frame and uv offset between frames
+    @location(3) animation: vec3<f32>,
+}
+
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coord: vec2<f32>,
+    @location(1) rect: vec4<f32>,
+    @location(2) animation: vec3<f32>,
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coord = model.tex_coords;
+    out.clip_position = uniforms.camera_transform * uniforms.model_transform * vec4<f32>(model.position, 1.0);
+    out.rect = model.rect;
+    out.animation = model.animation;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Greedy meshed faces span several blocks so wrap the uvs to repeat the tile across them
+    var uv = in.tex_coord;
+    if in.rect.z > 0.0 {
+        uv = in.rect.xy + fract(in.tex_coord) * in.rect.zw;
+    }
+
+    // Frames are stacked down the atlas so animating is just moving down by whole frames
+    let frames = max(abs(in.animation.x), 1.0);
+    let progress = chunk.time / max(in.animation.y, 0.001);
+    let frame = floor(progress % frames);
+    let next = (frame + 1.0) % frames;
+    let current_tex = textureSample(t, s, uv + vec2<f32>(0.0, frame * in.animation.z));
+    let next_tex = textureSample(t, s, uv + vec2<f32>(0.0, next * in.animation.z));
+    let blend = select(0.0, fract(progress), in.animation.x < 0.0);
+    let tex = mix(current_tex, next_tex, blend);
+    if tex.a < 0.1 {
+        discard;
+    }
+    return tex;
+}
@@ -14,6 +14,14 @@ var t: texture_2d<f32>;
 @group(1) @binding(1)
 var s: sampler;
 
+struct MaterialUniforms {
+    alpha_cutoff: f32,
+    blend: f32,
+}
+
+@group(3) @binding(0)
+var<uniform> material: MaterialUniforms;
+
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
@@ -43,9 +51,12 @@ fn vs_main(
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let tex = textureSample(t, s, in.tex_coord);
-    if tex.a < 0.1 {
+    let color = textureSample(t, s, in.tex_coord) * in.vertex_color;
+    if color.a < material.alpha_cutoff {
         discard;
     }
-    return tex;
+    if material.blend > 0.5 {
+        return color;
+    }
+    return vec4<f32>(color.rgb, 1.0);
 }
\ No newline at end of file
@@ -0,0 +1,137 @@
+//! Settings files the client keeps next to the executable as RON
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        source: ron::error::SpannedError,
+    },
+    Serialize {
+        path: PathBuf,
+        source: ron::Error,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            ConfigError::Parse { path, source } => write!(f, "{}: {source}", path.display()),
+            ConfigError::Serialize { path, source } => {
+                write!(f, "failed to write {}: {source}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Read a config file writing out the defaults first if there is none
+pub fn load_or_create<T: Serialize + DeserializeOwned + Default>(
+    path: &Path,
+) -> Result<T, ConfigError> {
+    match fs::read_to_string(path) {
+        Ok(source) => ron::from_str(&source).map_err(|source| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source,
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let config = T::default();
+            save(&config, path)?;
+            Ok(config)
+        }
+        Err(source) => Err(ConfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        }),
+    }
+}
+
+pub fn save<T: Serialize>(config: &T, path: &Path) -> Result<(), ConfigError> {
+    let source = ron::ser::to_string_pretty(config, ron::ser::PrettyConfig::default()).map_err(
+        |source| ConfigError::Serialize {
+            path: path.to_path_buf(),
+            source,
+        },
+    )?;
+    fs::write(path, source).map_err(|source| ConfigError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    #[serde(default)]
+    struct Test {
+        name: String,
+        count: u32,
+    }
+
+    impl Default for Test {
+        fn default() -> Self {
+            Self {
+                name: "default".to_string(),
+                count: 3,
+            }
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("{name}_{}.ron", std::process::id()))
+    }
+
+    #[test]
+    fn missing_file_writes_defaults() {
+        let path = temp_path("config_missing");
+        let _ = fs::remove_file(&path);
+        let config: Test = load_or_create(&path).unwrap();
+        assert_eq!(config, Test::default());
+        assert!(path.exists());
+
+        // What was written reads back the same, missing fields come from the defaults
+        assert_eq!(load_or_create::<Test>(&path).unwrap(), Test::default());
+        fs::write(&path, "(count: 5)").unwrap();
+        let config: Test = load_or_create(&path).unwrap();
+        assert_eq!(config.name, "default");
+        assert_eq!(config.count, 5);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn parse_error_keeps_file() {
+        let path = temp_path("config_parse");
+        fs::write(&path, "(count: \"five\")").unwrap();
+        let error = load_or_create::<Test>(&path).unwrap_err();
+        assert!(matches!(&error, ConfigError::Parse { path: x, .. } if *x == path));
+        assert!(error.to_string().starts_with(&path.display().to_string()));
+
+        // A broken file is left alone for the player to fix, not overwritten with defaults
+        assert_eq!(fs::read_to_string(&path).unwrap(), "(count: \"five\")");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn unreadable_path() {
+        // A directory can't be read as a file and isn't missing either
+        let path = std::env::temp_dir();
+        let error = load_or_create::<Test>(&path).unwrap_err();
+        assert!(matches!(error, ConfigError::Io { .. }));
+    }
+}
@@ -1,38 +1,100 @@
+use clap::Parser;
 use glam::Quat;
+use vinox_formats::{block::BlockRegistry, model::load_models_dir};
 
 use crate::{
-    input::InputState,
+    input::{Bindings, InputEvent, InputState, BINDINGS_PATH},
     network::state::NetworkState,
     render::{
+        atlas::AtlasBuilder,
+        mesher::ChunkMesher,
+        meshing::MeshPool,
         model::Model,
         state::{ConvertModel, RenderState},
     },
-    scene::{menu::MenuScene, SceneEvents, SceneStack, SceneSwitch},
+    scene::{error::ErrorScene, menu::MenuScene, SceneError, SceneEvents, SceneStack, SceneSwitch},
+    world::chunks::ChunkStore,
 };
 use std::{
-    path::Path,
+    collections::HashMap,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
     time::Duration,
 };
 
+/// Ids handed out to blocks, kept so they stay the same as blocks are added
+pub const BLOCK_IDS_PATH: &str = "block_ids.ron";
+
+/// Command line flags
+#[derive(Parser, Debug)]
+#[command(about = "Vinox client")]
+pub struct Cli {
+    /// Connect token for a secure server, made with the server's `token` command
+    #[arg(long)]
+    pub token: Option<PathBuf>,
+}
+
 pub struct Context<S, M: ConvertModel<S>> {
-    pub network: NetworkState,
+    /// Set once the player picks a server in the menu
+    pub network: Option<NetworkState>,
+    /// Connect token given with `--token <file>`, offered in the menu for secure servers
+    pub token: Option<PathBuf>,
     pub render: RenderState<S, M>,
     pub last_duration: Duration,
     pub input_state: InputState,
+    pub chunks: ChunkStore,
+    pub meshing: MeshPool,
 }
 impl<S, M: ConvertModel<S>> Context<S, M> {
-    pub fn new(state: &mut S) -> Self {
+    pub fn new(state: &mut S, cli: Cli) -> Self {
         let mut render = RenderState::<S, M>::default();
-        render.asset_registry.models.push(M::to_mesh(
-            Model::from_gltf(Path::new("vinox_client/assets/player.glb")).unwrap(),
-            state,
-        ));
+        match Model::from_gltf(Path::new("vinox_client/assets/player.glb")) {
+            Ok(model) => {
+                render.asset_registry.add_model(model, state);
+            }
+            Err(e) => println!("Failed to load player model: {e}"),
+        }
+        let mut atlas = AtlasBuilder::new();
+        if let Err(e) = atlas.add_dir("vinox_client/assets/textures/blocks") {
+            println!("Failed to load block textures: {e}");
+        }
+        match atlas.build() {
+            Ok(atlas) => {
+                render.asset_registry.block_atlas = Some(atlas.image);
+                render.asset_registry.block_uvs = atlas.rects;
+                render.asset_registry.block_animations = atlas.animations;
+            }
+            Err(e) => println!("Failed to build block atlas: {e}"),
+        }
+        let blocks =
+            BlockRegistry::load_dir_with_table("vinox_client/assets/blocks", BLOCK_IDS_PATH)
+                .unwrap_or_else(|e| {
+                    println!("Failed to load blocks: {e}");
+                    BlockRegistry::new()
+                });
+        let models = load_models_dir("vinox_client/assets/models").unwrap_or_else(|e| {
+            println!("Failed to load block models: {e}");
+            HashMap::new()
+        });
+        let mesher = ChunkMesher::new(
+            &blocks,
+            models,
+            render.asset_registry.block_uvs.clone(),
+            &render.asset_registry.block_animations,
+        );
+        let meshing = MeshPool::new(mesher);
+        let bindings = Bindings::load_or_create(Path::new(BINDINGS_PATH)).unwrap_or_else(|e| {
+            println!("Failed to load bindings, using the defaults: {e}");
+            Bindings::default()
+        });
         Self {
-            network: NetworkState::new("127.0.0.1:56552".to_string()).unwrap(),
+            network: None,
+            token: cli.token,
             render,
             last_duration: Duration::default(),
-            input_state: InputState {},
+            input_state: InputState::new(bindings),
+            chunks: ChunkStore::default(),
+            meshing,
         }
     }
 }
@@ -45,57 +107,87 @@ pub struct VinoxClient<S, M: ConvertModel<S>> {
 }
 
 impl<S: 'static, M: ConvertModel<S> + 'static> VinoxClient<S, M> {
-    pub fn new(state: &mut S) -> Self {
-        let mut render = RenderState::<S, M>::default();
-        render.asset_registry.models.push(M::to_mesh(
-            Model::from_gltf(Path::new("vinox_client/assets/player.glb")).unwrap(),
-            state,
-        ));
-
-        let mut context = Context::new(state);
+    pub fn new(state: &mut S, cli: Cli) -> Self {
+        let mut context = Context::new(state, cli);
         let mut game = SceneStack::new(&mut context, SharedState {});
-        game.switch(SceneSwitch::push(MenuScene::new()));
+        game.switch(SceneSwitch::push(MenuScene::new()), &mut context);
         Self { game, context }
     }
 
     pub fn update(&mut self, duration: Duration) -> Result<(), String> {
         // Uncapped or vsync frame rate
         self.context.last_duration = duration;
-        self.context.network.update(duration).ok();
-        self.context.render.camera.position = [0.0, 0.0, -5.0].into();
-        self.context.render.camera.rotation = Quat::from_rotation_x(90.0_f32.to_radians());
-        self.game.update(&mut self.context);
-        Ok(())
+        self.context.render.time += duration;
+        if let Some(network) = &mut self.context.network {
+            network.update(duration).ok();
+        }
+        // Menus look at the model preview, in game the camera is the player's
+        if self.context.network.is_none() {
+            self.context.render.camera.position = [0.0, 0.0, -5.0].into();
+            self.context.render.camera.rotation = Quat::from_rotation_x(90.0_f32.to_radians());
+        }
+        let result = self.game.update(&mut self.context);
+        self.scene_result(result)
         // self.render.camera.rotation = Quat::from_euler(EulerRot)
     }
 
     // Maybe return a vec of items that implement a trait? Ie something similiar to ggez drawable
     pub fn render(&mut self) -> Result<&mut RenderState<S, M>, String> {
         // Do non renderer specific rendering things here ie build chunk meshes, entity meshes/models, etc
-        self.game.render(&mut self.context);
+        let result = self.game.render(&mut self.context);
+        self.scene_result(result)?;
+        // Rendering is the last thing each frame so every scene has seen this frame's input
+        self.context.input_state.end_frame();
         Ok(&mut self.context.render)
     }
 
     pub fn tick(&mut self) -> Result<(), String> {
-        self.game.tick(&mut self.context);
-        Ok(())
         // Fixed tick update function should be 30ticks per second
+        let result = self.game.tick(&mut self.context);
+        self.scene_result(result)
     }
 
     pub fn ui(&mut self, gui: &mut egui::Context) -> Result<(), String> {
-        self.game.ui(gui, &mut self.context);
-        Ok(())
+        let result = self.game.ui(gui, &mut self.context);
+        self.scene_result(result)
     }
 
-    pub fn input(&mut self, input: &InputState) -> Result<(), String> {
-        self.context.input_state = input.clone();
-        // Provide input state that is needed to vinox
-        Ok(())
+    /// Events come in before the frame's update so scenes see them this frame
+    pub fn input(&mut self, event: SceneEvents) -> Result<(), String> {
+        if let Some(input) = InputEvent::from_scene_event(&event) {
+            self.context.input_state.handle(input);
+        }
+        let started = event.started();
+        let result = self.game.input(event, &mut self.context, started);
+        self.scene_result(result)
+    }
+
+    /// Set once the last scene has been popped
+    pub fn should_quit(&self) -> bool {
+        self.game.is_empty()
+    }
+
+    /// A failing scene takes the whole stack down with it and leaves an error screen, an
+    /// empty stack is left alone for `should_quit()` to report
+    fn scene_result(&mut self, result: Result<(), SceneError>) -> Result<(), String> {
+        match result {
+            Ok(()) | Err(SceneError::Empty) => Ok(()),
+            Err(e) => {
+                println!("Scene failed: {e}");
+                self.game.switch(
+                    SceneSwitch::replace_all(ErrorScene::new(e.to_string())),
+                    &mut self.context,
+                );
+                Ok(())
+            }
+        }
     }
 
     pub fn exit(&mut self) -> Result<(), String> {
         // self.game.()?;
-        self.context.network.exit();
+        if let Some(network) = &mut self.context.network {
+            network.exit();
+        }
         Ok(())
     }
 }
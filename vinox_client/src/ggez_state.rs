@@ -1,87 +1,190 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
 
 use crate::{
     glam::Vec2,
     render::{
-        model::Animation,
-        state::{ConvertModel, RenderState},
+        model::{AlphaMode, Model},
+        state::ConvertModel,
     },
 };
+use crevice::std140::AsStd140;
 use ggegui::Gui;
 use ggez::{
     graphics::{
-        DrawParam, DrawParam3d, Drawable3d, ImageFormat, Mesh3d, Mesh3dBuilder, Sampler, Shader,
-        Vertex3d,
+        DrawParam, DrawParam3d, ImageFormat, Mesh3d, Mesh3dBuilder, Sampler, Shader, Vertex3d,
     },
     *,
 };
-use glam::Vec3;
+use glam::{Mat4, Vec3};
+use vinox_common::prelude::ChunkPos;
+
+use crate::{
+    game::{Cli, VinoxClient},
+    input::MouseButton,
+    render::model::Vertex,
+    scene::{Modifiers, SceneEvents, TouchPhase},
+};
 
-use crate::{game::VinoxClient, input::InputState};
+/// Uniforms for `chunk.wgsl`
+#[derive(AsStd140)]
+struct ChunkUniforms {
+    /// Seconds since the client started
+    time: f32,
+}
+
+/// Chunk vertices don't need normals so the slot carries the texture animation as frame
+/// count, seconds per frame and uv offset between frames. A negative frame count blends
+/// between frames.
+fn chunk_vertices3d(vertices: Vec<Vertex>) -> Vec<Vertex3d> {
+    vertices
+        .into_iter()
+        .map(|x| {
+            let [frames, frame_time, frame_stride, interpolate] = x.animation;
+            let frames = if interpolate > 0.0 { -frames } else { frames };
+            Vertex3d::new(
+                x.pos,
+                x.tex_coord,
+                Some(graphics::Color::from(x.color)),
+                [frames, frame_time, frame_stride],
+            )
+        })
+        .collect()
+}
+
+fn to_vertices3d(vertices: Vec<Vertex>) -> Vec<Vertex3d> {
+    vertices
+        .into_iter()
+        .map(|x| {
+            Vertex3d::new(
+                x.pos,
+                x.tex_coord,
+                Some(graphics::Color::from(x.color)),
+                x.normals,
+            )
+        })
+        .collect()
+}
+
+/// Uniforms for `shader.wgsl`
+#[derive(AsStd140)]
+struct MaterialUniforms {
+    /// Fragments less opaque than this are discarded
+    alpha_cutoff: f32,
+    /// 1 to keep the alpha of the fragment, 0 to draw it fully opaque
+    blend: f32,
+}
+
+struct GgezMesh {
+    mesh: Mesh3d,
+    image: Option<graphics::Image>,
+    alpha_mode: AlphaMode,
+    params: graphics::ShaderParams<MaterialUniforms>,
+    /// Middle of the mesh in its rest pose, blended meshes are sorted by it
+    center: Vec3,
+}
 
 pub struct GgezModel {
-    model: graphics::Model,
-    animations: Vec<Animation>,
+    meshes: Vec<GgezMesh>,
+    /// The loaded model without its textures, only kept for models that can be posed
+    source: Option<Model>,
+}
+
+fn build_mesh(
+    ctx: &Context,
+    vertices: Vec<Vertex>,
+    indices: &[u32],
+    image: Option<graphics::Image>,
+) -> Mesh3d {
+    Mesh3dBuilder::new()
+        .from_data(to_vertices3d(vertices), indices.to_vec(), image)
+        .build(ctx)
+}
+
+impl GgezModel {
+    /// Posed on the cpu and rebuilt every frame, which is fine for the few hundred vertices
+    /// an entity has. `None` if the model can't be posed
+    fn posed(&self, ctx: &Context, pose: &[Mat4]) -> Option<Vec<Mesh3d>> {
+        let source = self.source.as_ref()?;
+        Some(
+            source
+                .posed_vertices(pose)
+                .into_iter()
+                .zip(source.meshes.iter().zip(self.meshes.iter()))
+                .map(|(vertices, (mesh, ggez_mesh))| {
+                    build_mesh(ctx, vertices, &mesh.indices, ggez_mesh.image.clone())
+                })
+                .collect(),
+        )
+    }
 }
 
 impl ConvertModel<Context> for GgezModel {
-    fn to_mesh(model: crate::render::model::Model, state: &mut Context) -> Self {
-        Self {
-            model: graphics::Model {
-                meshes: model
-                    .meshes
-                    .into_iter()
-                    .map(|x| {
-                        Mesh3dBuilder::new()
-                            .from_data(
-                                x.vertices
-                                    .into_iter()
-                                    .map(|x| {
-                                        Vertex3d::new(
-                                            x.pos,
-                                            x.tex_coord,
-                                            Some(graphics::Color::from(x.color)),
-                                            x.normals,
-                                        )
-                                    })
-                                    .collect(),
-                                x.indices,
-                                x.texture.map(|x| {
-                                    graphics::Image::from_pixels(
-                                        state,
-                                        x.to_vec().as_slice(),
-                                        ImageFormat::Rgba8UnormSrgb,
-                                        x.width(),
-                                        x.height(),
-                                    )
-                                }),
-                            )
-                            .build(state)
+    fn to_mesh(mut model: Model, state: &mut Context) -> Self {
+        let rest = model.posed_vertices(&model.global_transforms(&model.rest_pose()));
+        let meshes = model
+            .meshes
+            .iter_mut()
+            .zip(rest)
+            .map(|(mesh, vertices)| {
+                let image = mesh.material.base_color_texture.take().map(|x| {
+                    graphics::Image::from_pixels(
+                        state,
+                        x.to_vec().as_slice(),
+                        ImageFormat::Rgba8UnormSrgb,
+                        x.width(),
+                        x.height(),
+                    )
+                });
+                let alpha_mode = mesh.material.alpha_mode;
+                let (alpha_cutoff, blend) = match alpha_mode {
+                    AlphaMode::Opaque => (0.0, 0.0),
+                    AlphaMode::Mask(cutoff) => (cutoff, 0.0),
+                    AlphaMode::Blend => (0.0, 1.0),
+                };
+                let center = vertices.iter().map(|x| Vec3::from(x.pos)).sum::<Vec3>()
+                    / vertices.len().max(1) as f32;
+                GgezMesh {
+                    mesh: build_mesh(state, vertices, &mesh.indices, image.clone()),
+                    image,
+                    alpha_mode,
+                    params: graphics::ShaderParamsBuilder::new(&MaterialUniforms {
+                        alpha_cutoff,
+                        blend,
                     })
-                    .collect(),
-                aabb: None,
-            },
-            animations: vec![],
+                    .build(state),
+                    center,
+                }
+            })
+            .collect();
+        Self {
+            meshes,
+            source: (!model.animations.is_empty()).then_some(model),
         }
     }
 }
 
 pub struct GgezState {
     game: VinoxClient<Context, GgezModel>,
-    input: InputState,
     gui: Gui,
     shader: Shader,
     psx_shader: Shader,
     crt_shader: Shader,
+    chunk_shader: Shader,
+    /// Uploaded from `AssetRegistry::block_atlas` the first time it is set
+    block_atlas: Option<graphics::Image>,
+    chunk_meshes: HashMap<ChunkPos, Mesh3d>,
+    gamepads: Vec<input::gamepad::GamepadId>,
 }
 
 impl GgezState {
-    pub fn new(ctx: &mut Context) -> GameResult<GgezState> {
+    pub fn new(ctx: &mut Context, cli: Cli) -> GameResult<GgezState> {
         let mut gui = Gui::new(ctx);
         gui.input.set_scale_factor(1.5, ctx.gfx.drawable_size());
         Ok(GgezState {
-            game: VinoxClient::new(ctx),
-            input: InputState::default(),
+            game: VinoxClient::new(ctx, cli),
             gui,
             shader: graphics::ShaderBuilder::from_path("/shaders/shader.wgsl")
                 .build(&ctx.gfx)
@@ -93,12 +196,64 @@ impl GgezState {
                 .fragment_path("/shaders/crt.wgsl")
                 .build(&ctx.gfx)
                 .unwrap(),
+            chunk_shader: graphics::ShaderBuilder::from_path("/shaders/chunk.wgsl")
+                .build(&ctx.gfx)
+                .unwrap(),
+            block_atlas: None,
+            chunk_meshes: HashMap::new(),
+            gamepads: Vec::new(),
         })
     }
+
+    /// Hand an event to the game unless egui is using it. Releases always go through so
+    /// nothing stays held because it was let go over the ui
+    fn input(&mut self, event: SceneEvents) -> GameResult {
+        let gui = &self.gui.ctx().context;
+        let consumed = event.started()
+            && (event.is_pointer() && gui.wants_pointer_input()
+                || event.is_keyboard() && gui.wants_keyboard_input());
+        if consumed {
+            return Ok(());
+        }
+        self.game
+            .input(event)
+            .map_err(|x| GameError::CustomError(x.to_string()))
+    }
+
+    /// Small stable ids for gamepads in the order they're first seen
+    fn gamepad_id(&mut self, id: input::gamepad::GamepadId) -> usize {
+        match self.gamepads.iter().position(|x| *x == id) {
+            Some(index) => index,
+            None => {
+                self.gamepads.push(id);
+                self.gamepads.len() - 1
+            }
+        }
+    }
+}
+
+fn mouse_button(button: event::MouseButton) -> MouseButton {
+    match button {
+        event::MouseButton::Left => MouseButton::Left,
+        event::MouseButton::Right => MouseButton::Right,
+        event::MouseButton::Middle => MouseButton::Middle,
+        event::MouseButton::Other(x) => MouseButton::Other(x),
+    }
+}
+
+fn modifiers(mods: input::keyboard::KeyMods) -> Modifiers {
+    use input::keyboard::KeyMods;
+    Modifiers {
+        shift: mods.contains(KeyMods::SHIFT),
+        ctrl: mods.contains(KeyMods::CTRL),
+        alt: mods.contains(KeyMods::ALT),
+        logo: mods.contains(KeyMods::LOGO),
+    }
 }
 
 impl event::EventHandler for GgezState {
     fn quit_event(&mut self, ctx: &mut Context) -> Result<bool, GameError> {
+        self.input(SceneEvents::QuitEvent)?;
         self.game
             .exit()
             .map_err(|x| GameError::CustomError(x.to_string()))?;
@@ -108,13 +263,166 @@ impl event::EventHandler for GgezState {
         Ok(false)
     }
 
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        key: input::keyboard::KeyInput,
+        repeated: bool,
+    ) -> GameResult {
+        self.input(SceneEvents::KeyDownEvent {
+            key: key.keycode.map(|x| format!("{x:?}")),
+            scancode: key.scancode,
+            modifiers: modifiers(key.mods),
+            repeated,
+        })
+    }
+
+    fn key_up_event(&mut self, _ctx: &mut Context, key: input::keyboard::KeyInput) -> GameResult {
+        self.input(SceneEvents::KeyUpEvent {
+            key: key.keycode.map(|x| format!("{x:?}")),
+            scancode: key.scancode,
+            modifiers: modifiers(key.mods),
+        })
+    }
+
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) -> GameResult {
+        self.gui.input.text_input_event(character);
+        self.input(SceneEvents::TextInputEvent { character })
+    }
+
+    fn mouse_button_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        button: event::MouseButton,
+        x: f32,
+        y: f32,
+    ) -> GameResult {
+        self.input(SceneEvents::MouseButtonDownEvent {
+            button: mouse_button(button),
+            position: Vec2::new(x, y),
+        })
+    }
+
+    fn mouse_button_up_event(
+        &mut self,
+        _ctx: &mut Context,
+        button: event::MouseButton,
+        x: f32,
+        y: f32,
+    ) -> GameResult {
+        self.input(SceneEvents::MouseButtonUpEvent {
+            button: mouse_button(button),
+            position: Vec2::new(x, y),
+        })
+    }
+
+    fn mouse_motion_event(
+        &mut self,
+        _ctx: &mut Context,
+        x: f32,
+        y: f32,
+        dx: f32,
+        dy: f32,
+    ) -> GameResult {
+        self.input(SceneEvents::MouseMotionEvent {
+            position: Vec2::new(x, y),
+            delta: Vec2::new(dx, dy),
+        })
+    }
+
+    fn raw_mouse_motion_event(&mut self, _ctx: &mut Context, dx: f64, dy: f64) -> GameResult {
+        self.input(SceneEvents::RawMouseMotionEvent {
+            delta: Vec2::new(dx as f32, dy as f32),
+        })
+    }
+
+    fn mouse_enter_or_leave(&mut self, _ctx: &mut Context, entered: bool) -> GameResult {
+        self.input(SceneEvents::MouseEnterOrLeave { entered })
+    }
+
+    fn mouse_wheel_event(&mut self, _ctx: &mut Context, x: f32, y: f32) -> GameResult {
+        self.gui.input.mouse_wheel_event(x, y);
+        self.input(SceneEvents::MouseWheelEvent {
+            delta: Vec2::new(x, y),
+        })
+    }
+
+    fn touch_event(
+        &mut self,
+        _ctx: &mut Context,
+        phase: event::TouchPhase,
+        x: f64,
+        y: f64,
+    ) -> GameResult {
+        let phase = match phase {
+            event::TouchPhase::Started => TouchPhase::Started,
+            event::TouchPhase::Moved => TouchPhase::Moved,
+            event::TouchPhase::Ended => TouchPhase::Ended,
+            event::TouchPhase::Cancelled => TouchPhase::Cancelled,
+        };
+        self.input(SceneEvents::TouchEvent {
+            phase,
+            position: Vec2::new(x as f32, y as f32),
+        })
+    }
+
+    fn gamepad_button_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        btn: input::gamepad::gilrs::Button,
+        id: input::gamepad::GamepadId,
+    ) -> GameResult {
+        let id = self.gamepad_id(id);
+        self.input(SceneEvents::GamepadButtonDownEvent {
+            button: format!("{btn:?}"),
+            id,
+        })
+    }
+
+    fn gamepad_button_up_event(
+        &mut self,
+        _ctx: &mut Context,
+        btn: input::gamepad::gilrs::Button,
+        id: input::gamepad::GamepadId,
+    ) -> GameResult {
+        let id = self.gamepad_id(id);
+        self.input(SceneEvents::GamepadButtonUpEvent {
+            button: format!("{btn:?}"),
+            id,
+        })
+    }
+
+    fn gamepad_axis_event(
+        &mut self,
+        _ctx: &mut Context,
+        axis: input::gamepad::gilrs::Axis,
+        value: f32,
+        id: input::gamepad::GamepadId,
+    ) -> GameResult {
+        let id = self.gamepad_id(id);
+        self.input(SceneEvents::GamepadAxisEvent {
+            axis: format!("{axis:?}"),
+            value,
+            id,
+        })
+    }
+
+    fn focus_event(&mut self, _ctx: &mut Context, gained: bool) -> GameResult {
+        self.input(SceneEvents::FocusEvent { gained })
+    }
+
+    fn resize_event(&mut self, _ctx: &mut Context, width: f32, height: f32) -> GameResult {
+        self.input(SceneEvents::ResizeEvent { width, height })
+    }
+
     fn update(&mut self, ctx: &mut Context) -> GameResult {
         self.game
             .update(ctx.time.delta())
             .map_err(|x| GameError::CustomError(x.to_string()))?;
-        self.game
-            .input(&self.input)
-            .map_err(|x| GameError::CustomError(x.to_string()))?;
+        if self.game.should_quit() {
+            ctx.request_quit();
+            return Ok(());
+        }
         while ctx.time.check_update_time(30) {
             self.game
                 .tick()
@@ -141,6 +449,35 @@ impl event::EventHandler for GgezState {
 
         render_state.camera.resize(width as u32, height as u32);
 
+        if self.block_atlas.is_none() {
+            if let Some(atlas) = &render_state.asset_registry.block_atlas {
+                self.block_atlas = Some(graphics::Image::from_pixels(
+                    ctx,
+                    atlas.as_raw(),
+                    ImageFormat::Rgba8UnormSrgb,
+                    atlas.width(),
+                    atlas.height(),
+                ));
+            }
+        }
+        for (pos, mesh) in render_state.chunk_uploads.drain(..) {
+            match mesh {
+                Some(mesh) => {
+                    let mesh = Mesh3dBuilder::new()
+                        .from_data(
+                            chunk_vertices3d(mesh.vertices),
+                            mesh.indices,
+                            self.block_atlas.clone(),
+                        )
+                        .build(ctx);
+                    self.chunk_meshes.insert(pos, mesh);
+                }
+                None => {
+                    self.chunk_meshes.remove(&pos);
+                }
+            }
+        }
+
         let mut canvas3d =
             graphics::Canvas3d::from_screen_image(ctx, &mut scene_image, graphics::Color::BLACK);
         canvas3d.set_sampler(Sampler::nearest_clamp());
@@ -148,7 +485,50 @@ impl event::EventHandler for GgezState {
 
         canvas3d.set_projection(render_state.camera.to_matrix());
 
-        canvas3d.draw(render_state, DrawParam3d::default());
+        let models = &render_state.asset_registry.models;
+        let posed: Vec<Option<Vec<Mesh3d>>> = render_state
+            .draws
+            .iter()
+            .map(|draw| {
+                models
+                    .get(draw.model_id as usize)?
+                    .posed(ctx, draw.pose.as_ref()?)
+            })
+            .collect();
+        // Everything opaque goes first so blended meshes can be drawn over it furthest first
+        let mut blended = Vec::new();
+        for (draw, posed) in render_state.draws.iter().zip(posed.iter()) {
+            let Some(model) = models.get(draw.model_id as usize) else {
+                continue;
+            };
+            for (index, mesh) in model.meshes.iter().enumerate() {
+                let drawn = posed.as_ref().map_or(&mesh.mesh, |x| &x[index]);
+                if mesh.alpha_mode == AlphaMode::Blend {
+                    let distance = mesh.center.distance_squared(render_state.camera.position);
+                    blended.push((distance, drawn, mesh));
+                } else {
+                    canvas3d.set_shader_params(&mesh.params);
+                    canvas3d.draw(drawn, DrawParam3d::default());
+                }
+            }
+        }
+
+        let chunk_uniforms = graphics::ShaderParamsBuilder::new(&ChunkUniforms {
+            time: render_state.time.as_secs_f32(),
+        })
+        .build(ctx);
+        canvas3d.set_shader(&self.chunk_shader);
+        canvas3d.set_shader_params(&chunk_uniforms);
+        for mesh in self.chunk_meshes.values() {
+            canvas3d.draw(mesh, DrawParam3d::default());
+        }
+
+        blended.sort_by(|a, b| b.0.total_cmp(&a.0));
+        canvas3d.set_shader(&self.shader);
+        for (_, drawn, mesh) in blended {
+            canvas3d.set_shader_params(&mesh.params);
+            canvas3d.draw(drawn, DrawParam3d::default());
+        }
 
         canvas3d.finish(ctx)?;
 
@@ -168,14 +548,3 @@ impl event::EventHandler for GgezState {
         Ok(())
     }
 }
-
-impl Drawable3d for RenderState<Context, GgezModel> {
-    fn draw(&self, canvas: &mut graphics::Canvas3d, param: impl Into<DrawParam3d>) {
-        let param: DrawParam3d = param.into();
-        for draw in self.draws.iter() {
-            if let Some(model) = self.asset_registry.models.get(draw.model_id as usize) {
-                canvas.draw(&model.model, param);
-            }
-        }
-    }
-}
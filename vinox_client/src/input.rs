@@ -1,4 +1,483 @@
-#[derive(Default, Clone, Copy)]
-pub struct InputState {}
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::Path,
+    time::Duration,
+};
 
-impl InputState {}
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{self, ConfigError},
+    scene::SceneEvents,
+};
+
+/// Where the binding table is kept, written with the defaults if it doesn't exist
+pub const BINDINGS_PATH: &str = "bindings.ron";
+
+/// Number of hotbar slots, each has its own `Action::Hotbar`
+pub const HOTBAR_SLOTS: u8 = 9;
+
+/// Something the player can do. Scenes ask `InputState` about these instead of raw keys so
+/// anything can be rebound
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Action {
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Sneak,
+    Attack,
+    Use,
+    /// Open or close the pause menu
+    Pause,
+    /// Select a hotbar slot from 0 to `HOTBAR_SLOTS - 1`
+    Hotbar(u8),
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    Other(u16),
+}
+
+/// A button on some device. Keys and gamepad buttons go by the name the backend gives them
+/// ie `W`, `Space`, `LShift` or `South` so the binding table doesn't depend on a backend.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(String),
+    Mouse(MouseButton),
+    Gamepad(String),
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GamepadAxis {
+    LeftX,
+    LeftY,
+    RightX,
+    RightY,
+}
+
+/// What the backend feeds into `InputState` as it gets events
+#[derive(Clone, Debug, PartialEq)]
+pub enum InputEvent {
+    Pressed(Binding),
+    Released(Binding),
+    /// Mouse movement in pixels since the last event
+    MouseMotion(Vec2),
+    Scroll(Vec2),
+    /// Stick position from -1 to 1, up and right are positive
+    Axis(GamepadAxis, f32),
+    /// The window lost focus so releases won't reach it until it's back
+    FocusLost,
+}
+
+impl InputEvent {
+    /// The part of a window event `InputState` cares about, if any
+    pub fn from_scene_event(event: &SceneEvents) -> Option<Self> {
+        Some(match event {
+            SceneEvents::KeyDownEvent { key: Some(key), .. } => {
+                InputEvent::Pressed(Binding::Key(key.clone()))
+            }
+            SceneEvents::KeyUpEvent { key: Some(key), .. } => {
+                InputEvent::Released(Binding::Key(key.clone()))
+            }
+            SceneEvents::MouseButtonDownEvent { button, .. } => {
+                InputEvent::Pressed(Binding::Mouse(*button))
+            }
+            SceneEvents::MouseButtonUpEvent { button, .. } => {
+                InputEvent::Released(Binding::Mouse(*button))
+            }
+            SceneEvents::MouseMotionEvent { delta, .. } => InputEvent::MouseMotion(*delta),
+            SceneEvents::MouseWheelEvent { delta } => InputEvent::Scroll(*delta),
+            SceneEvents::GamepadButtonDownEvent { button, .. } => {
+                InputEvent::Pressed(Binding::Gamepad(button.clone()))
+            }
+            SceneEvents::GamepadButtonUpEvent { button, .. } => {
+                InputEvent::Released(Binding::Gamepad(button.clone()))
+            }
+            SceneEvents::GamepadAxisEvent { axis, value, .. } => {
+                let axis = match axis.as_str() {
+                    "LeftStickX" => GamepadAxis::LeftX,
+                    "LeftStickY" => GamepadAxis::LeftY,
+                    "RightStickX" => GamepadAxis::RightX,
+                    "RightStickY" => GamepadAxis::RightY,
+                    _ => return None,
+                };
+                InputEvent::Axis(axis, *value)
+            }
+            SceneEvents::FocusEvent { gained: false } => InputEvent::FocusLost,
+            _ => return None,
+        })
+    }
+}
+
+/// The rebindable table of which buttons trigger which actions, saved as RON ie
+/// ```ron
+/// (actions: {Jump: [Key("Space"), Gamepad("South")]}, mouse_sensitivity: 0.002)
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Bindings {
+    pub actions: BTreeMap<Action, Vec<Binding>>,
+    /// Radians turned per pixel of mouse movement
+    pub mouse_sensitivity: f32,
+    /// Radians turned per second with the right stick all the way over
+    pub gamepad_look_speed: f32,
+    /// Stick positions closer to the center than this count as centered
+    pub gamepad_deadzone: f32,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let key = |name: &str| Binding::Key(name.to_string());
+        let gamepad = |name: &str| Binding::Gamepad(name.to_string());
+        let mut actions = BTreeMap::from([
+            (Action::MoveForward, vec![key("W")]),
+            (Action::MoveBack, vec![key("S")]),
+            (Action::MoveLeft, vec![key("A")]),
+            (Action::MoveRight, vec![key("D")]),
+            (Action::Jump, vec![key("Space"), gamepad("South")]),
+            (Action::Sneak, vec![key("LShift"), gamepad("East")]),
+            (
+                Action::Attack,
+                vec![Binding::Mouse(MouseButton::Left), gamepad("RightTrigger2")],
+            ),
+            (
+                Action::Use,
+                vec![Binding::Mouse(MouseButton::Right), gamepad("LeftTrigger2")],
+            ),
+            (Action::Pause, vec![key("Escape"), gamepad("Start")]),
+        ]);
+        for slot in 0..HOTBAR_SLOTS {
+            actions.insert(Action::Hotbar(slot), vec![key(&format!("Key{}", slot + 1))]);
+        }
+        Self {
+            actions,
+            mouse_sensitivity: 0.002,
+            gamepad_look_speed: 3.0,
+            gamepad_deadzone: 0.15,
+        }
+    }
+}
+
+impl Bindings {
+    /// Read a bindings file writing out the defaults first if there is none. Actions added
+    /// since the file was written get their default bindings
+    pub fn load_or_create(path: &Path) -> Result<Self, ConfigError> {
+        let mut bindings: Bindings = config::load_or_create(path)?;
+        for (action, defaults) in Bindings::default().actions {
+            bindings.actions.entry(action).or_insert(defaults);
+        }
+        Ok(bindings)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ConfigError> {
+        config::save(self, path)
+    }
+
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.actions.get(&action).map_or(&[], |x| x.as_slice())
+    }
+
+    /// Make `binding` the only thing that triggers `action`, taking it away from any other
+    /// action so one button never does two things
+    pub fn rebind(&mut self, action: Action, binding: Binding) {
+        for bindings in self.actions.values_mut() {
+            bindings.retain(|x| *x != binding);
+        }
+        self.actions.insert(action, vec![binding]);
+    }
+
+    /// Trigger `action` with `binding` as well as whatever already triggers it
+    pub fn add(&mut self, action: Action, binding: Binding) {
+        let bindings = self.actions.entry(action).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+}
+
+/// Every button held down plus what changed this frame. Filled by the backend with
+/// `handle` and cleared of per frame changes with `end_frame` once everything has run
+#[derive(Default, Clone, Debug)]
+pub struct InputState {
+    pub bindings: Bindings,
+    held: HashSet<Binding>,
+    /// Went down this frame
+    pressed: Vec<Binding>,
+    /// Went up this frame
+    released: HashSet<Binding>,
+    mouse_delta: Vec2,
+    scroll: Vec2,
+    left_stick: Vec2,
+    right_stick: Vec2,
+}
+
+impl InputState {
+    pub fn new(bindings: Bindings) -> Self {
+        Self {
+            bindings,
+            ..Default::default()
+        }
+    }
+
+    pub fn handle(&mut self, event: InputEvent) {
+        match event {
+            InputEvent::Pressed(binding) => {
+                // Key repeat sends more presses without a release in between
+                if self.held.insert(binding.clone()) {
+                    self.pressed.push(binding);
+                }
+            }
+            InputEvent::Released(binding) => {
+                if self.held.remove(&binding) {
+                    self.released.insert(binding);
+                }
+            }
+            InputEvent::MouseMotion(delta) => self.mouse_delta += delta,
+            InputEvent::Scroll(delta) => self.scroll += delta,
+            InputEvent::Axis(axis, value) => match axis {
+                GamepadAxis::LeftX => self.left_stick.x = value,
+                GamepadAxis::LeftY => self.left_stick.y = value,
+                GamepadAxis::RightX => self.right_stick.x = value,
+                GamepadAxis::RightY => self.right_stick.y = value,
+            },
+            InputEvent::FocusLost => {
+                // Count everything as let go so the player doesn't keep walking after alt tab
+                self.released.extend(self.held.drain());
+                self.left_stick = Vec2::ZERO;
+                self.right_stick = Vec2::ZERO;
+            }
+        }
+    }
+
+    /// Forget what changed this frame, buttons stay held until they're released
+    pub fn end_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+        self.mouse_delta = Vec2::ZERO;
+        self.scroll = Vec2::ZERO;
+    }
+
+    /// Is any binding of `action` held down
+    pub fn held(&self, action: Action) -> bool {
+        self.bindings
+            .get(action)
+            .iter()
+            .any(|x| self.held.contains(x))
+    }
+
+    /// Did any binding of `action` go down this frame
+    pub fn pressed(&self, action: Action) -> bool {
+        self.bindings
+            .get(action)
+            .iter()
+            .any(|x| self.pressed.contains(x))
+    }
+
+    /// Did any binding of `action` go up this frame
+    pub fn released(&self, action: Action) -> bool {
+        self.bindings
+            .get(action)
+            .iter()
+            .any(|x| self.released.contains(x))
+    }
+
+    /// The first button pressed this frame whatever it's bound to, for rebinding menus
+    pub fn pressed_binding(&self) -> Option<&Binding> {
+        self.pressed.first()
+    }
+
+    /// Hotbar slot picked this frame
+    pub fn hotbar_pressed(&self) -> Option<u8> {
+        (0..HOTBAR_SLOTS).find(|x| self.pressed(Action::Hotbar(*x)))
+    }
+
+    fn deadzone(&self, stick: Vec2) -> Vec2 {
+        if stick.length() < self.bindings.gamepad_deadzone {
+            Vec2::ZERO
+        } else {
+            stick
+        }
+    }
+
+    /// Direction to walk in with right and forward positive, at most 1 long
+    pub fn movement(&self) -> Vec2 {
+        let axis = |positive, negative| {
+            self.held(positive) as u8 as f32 - self.held(negative) as u8 as f32
+        };
+        let keys = Vec2::new(
+            axis(Action::MoveRight, Action::MoveLeft),
+            axis(Action::MoveForward, Action::MoveBack),
+        );
+        (keys + self.deadzone(self.left_stick)).clamp_length_max(1.0)
+    }
+
+    /// Radians to turn the camera this frame as yaw and pitch, `delta` is the frame time
+    pub fn look(&self, delta: Duration) -> Vec2 {
+        self.mouse_delta * self.bindings.mouse_sensitivity
+            + self.deadzone(self.right_stick)
+                * Vec2::new(1.0, -1.0)
+                * self.bindings.gamepad_look_speed
+                * delta.as_secs_f32()
+    }
+
+    /// Mouse movement in pixels this frame
+    pub fn mouse_delta(&self) -> Vec2 {
+        self.mouse_delta
+    }
+
+    /// Scroll wheel movement this frame
+    pub fn scroll(&self) -> Vec2 {
+        self.scroll
+    }
+
+    /// Is a button held whether or not it's bound to anything
+    pub fn is_held(&self, binding: &Binding) -> bool {
+        self.held.contains(binding)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn key(name: &str) -> Binding {
+        Binding::Key(name.to_string())
+    }
+
+    #[test]
+    fn press_held_release() {
+        let mut input = InputState::new(Bindings::default());
+        input.handle(InputEvent::Pressed(key("W")));
+        assert!(input.pressed(Action::MoveForward));
+        assert!(input.held(Action::MoveForward));
+        assert!(!input.released(Action::MoveForward));
+        assert_eq!(input.pressed_binding(), Some(&key("W")));
+
+        // Still held on the next frame but no longer just pressed
+        input.end_frame();
+        assert!(!input.pressed(Action::MoveForward));
+        assert!(input.held(Action::MoveForward));
+
+        // Key repeat doesn't count as another press
+        input.handle(InputEvent::Pressed(key("W")));
+        assert!(!input.pressed(Action::MoveForward));
+
+        input.handle(InputEvent::Released(key("W")));
+        assert!(input.released(Action::MoveForward));
+        assert!(!input.held(Action::MoveForward));
+
+        input.end_frame();
+        assert!(!input.released(Action::MoveForward));
+
+        // A release without a press, ie the key went down before the window had focus
+        input.handle(InputEvent::Released(key("S")));
+        assert!(!input.released(Action::MoveBack));
+    }
+
+    #[test]
+    fn end_frame_clears_changes() {
+        let mut input = InputState::new(Bindings::default());
+        input.handle(InputEvent::Pressed(key("Space")));
+        input.handle(InputEvent::Pressed(key("A")));
+        input.handle(InputEvent::Released(key("A")));
+        input.handle(InputEvent::MouseMotion(Vec2::new(3.0, -2.0)));
+        input.handle(InputEvent::MouseMotion(Vec2::new(1.0, 1.0)));
+        input.handle(InputEvent::Scroll(Vec2::new(0.0, 1.0)));
+        input.handle(InputEvent::Axis(GamepadAxis::LeftY, 1.0));
+        assert_eq!(input.mouse_delta(), Vec2::new(4.0, -1.0));
+        assert_eq!(input.scroll(), Vec2::new(0.0, 1.0));
+
+        input.end_frame();
+        assert_eq!(input.pressed_binding(), None);
+        assert!(!input.pressed(Action::Jump));
+        assert!(!input.released(Action::MoveLeft));
+        assert_eq!(input.mouse_delta(), Vec2::ZERO);
+        assert_eq!(input.scroll(), Vec2::ZERO);
+        // Held buttons and sticks are state, not changes
+        assert!(input.held(Action::Jump));
+        assert_eq!(input.movement(), Vec2::new(0.0, 1.0));
+    }
+
+    #[test]
+    fn focus_lost_releases_everything() {
+        let mut input = InputState::new(Bindings::default());
+        input.handle(InputEvent::Pressed(key("W")));
+        input.handle(InputEvent::Axis(GamepadAxis::LeftX, 1.0));
+        input.handle(InputEvent::Axis(GamepadAxis::RightY, 1.0));
+        input.end_frame();
+
+        let event = InputEvent::from_scene_event(&SceneEvents::FocusEvent { gained: false });
+        assert_eq!(event, Some(InputEvent::FocusLost));
+        input.handle(InputEvent::FocusLost);
+        assert!(!input.held(Action::MoveForward));
+        assert!(input.released(Action::MoveForward));
+        assert_eq!(input.movement(), Vec2::ZERO);
+        assert_eq!(input.look(Duration::from_secs(1)), Vec2::ZERO);
+
+        // The release that comes in after focus is back doesn't count twice
+        input.end_frame();
+        input.handle(InputEvent::Released(key("W")));
+        assert!(!input.released(Action::MoveForward));
+        assert_eq!(
+            InputEvent::from_scene_event(&SceneEvents::FocusEvent { gained: true }),
+            None
+        );
+    }
+
+    #[test]
+    fn rebind_replaces() {
+        let mut bindings = Bindings::default();
+        bindings.rebind(Action::Jump, key("J"));
+        assert_eq!(bindings.get(Action::Jump), &[key("J")]);
+
+        // Taking a button from another action leaves that action without it
+        bindings.rebind(Action::Jump, key("W"));
+        assert_eq!(bindings.get(Action::Jump), &[key("W")]);
+        assert!(bindings.get(Action::MoveForward).is_empty());
+
+        bindings.add(Action::Jump, key("Space"));
+        bindings.add(Action::Jump, key("Space"));
+        assert_eq!(bindings.get(Action::Jump), &[key("W"), key("Space")]);
+
+        let mut input = InputState::new(bindings);
+        input.handle(InputEvent::Pressed(key("W")));
+        assert!(input.pressed(Action::Jump));
+        assert!(!input.held(Action::MoveForward));
+    }
+
+    #[test]
+    fn old_files_get_new_defaults() {
+        let path = std::env::temp_dir().join(format!("bindings_{}.ron", std::process::id()));
+        fs::write(
+            &path,
+            r#"(actions: {Jump: [Key("J")]}, mouse_sensitivity: 0.01)"#,
+        )
+        .unwrap();
+        let bindings = Bindings::load_or_create(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let defaults = Bindings::default();
+        assert_eq!(bindings.get(Action::Jump), &[key("J")]);
+        assert_eq!(bindings.mouse_sensitivity, 0.01);
+        assert_eq!(bindings.gamepad_deadzone, defaults.gamepad_deadzone);
+        for (action, binding) in &defaults.actions {
+            if *action != Action::Jump {
+                assert_eq!(bindings.get(*action), binding.as_slice(), "{action:?}");
+            }
+        }
+
+        // No file writes out the defaults
+        let bindings = Bindings::load_or_create(&path).unwrap();
+        assert_eq!(bindings, defaults);
+        assert_eq!(Bindings::load_or_create(&path).unwrap(), defaults);
+        fs::remove_file(&path).unwrap();
+    }
+}
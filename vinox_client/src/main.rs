@@ -1,9 +1,12 @@
 use std::{env, path};
 
+use clap::Parser;
+use game::Cli;
 use ggez::*;
 use ggez_state::GgezState;
 
 mod commands;
+mod config;
 mod game;
 mod ggez_state;
 mod input;
@@ -11,8 +14,11 @@ mod network;
 mod render;
 mod scene;
 mod ui;
+mod world;
 
 fn main() -> GameResult {
+    // Parsed before the window opens so `--help` and bad flags don't flash one up
+    let cli = Cli::parse();
     let resource_dir = if let Ok(manifest_dir) = env::var("CARGO_MANIFEST_DIR") {
         let mut path = path::PathBuf::from(manifest_dir);
         path.push("assets");
@@ -32,6 +38,6 @@ fn main() -> GameResult {
 
     let (mut ctx, events_loop) = cb.build()?;
 
-    let game = GgezState::new(&mut ctx)?;
+    let game = GgezState::new(&mut ctx, cli)?;
     event::run(ctx, events_loop, game)
 }
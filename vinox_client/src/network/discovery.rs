@@ -0,0 +1,96 @@
+use std::{
+    io,
+    net::{SocketAddr, UdpSocket},
+    time::Duration,
+};
+
+use vinox_common::prelude::{DiscoveryPacket, ServerInfo, DISCOVERY_PORT};
+
+/// How often to ping again while browsing so servers started later show up
+const PING_INTERVAL: Duration = Duration::from_secs(2);
+/// Servers that haven't answered in this long are dropped from the list
+const SERVER_TIMEOUT: Duration = Duration::from_secs(6);
+
+#[derive(Clone, Debug)]
+pub struct LanServer {
+    /// Where to connect, the address the reply came from with the game port
+    pub address: SocketAddr,
+    pub info: ServerInfo,
+    /// Time since the server last answered
+    age: Duration,
+}
+
+/// Looks for servers on the local network by broadcasting discovery pings
+pub struct LanSearch {
+    socket: UdpSocket,
+    since_ping: Duration,
+    servers: Vec<LanServer>,
+}
+
+impl LanSearch {
+    pub fn new() -> io::Result<Self> {
+        let socket = UdpSocket::bind(SocketAddr::from(([0, 0, 0, 0], 0)))?;
+        socket.set_broadcast(true)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            // Ping on the first update
+            since_ping: PING_INTERVAL,
+            servers: Vec::new(),
+        })
+    }
+
+    pub fn update(&mut self, duration: Duration) {
+        self.since_ping += duration;
+        if self.since_ping >= PING_INTERVAL {
+            self.since_ping = Duration::ZERO;
+            let broadcast = SocketAddr::from(([255, 255, 255, 255], DISCOVERY_PORT));
+            if let Err(e) = self
+                .socket
+                .send_to(&DiscoveryPacket::Ping.to_bytes(), broadcast)
+            {
+                println!("Failed to send discovery ping: {e}");
+            }
+        }
+
+        for server in &mut self.servers {
+            server.age += duration;
+        }
+        self.servers.retain(|x| x.age < SERVER_TIMEOUT);
+
+        let mut buffer = [0; 1024];
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((len, from)) => {
+                    if let Some(DiscoveryPacket::Pong(info)) =
+                        DiscoveryPacket::from_bytes(&buffer[..len])
+                    {
+                        self.insert(SocketAddr::new(from.ip(), info.port), info);
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    println!("Discovery error: {e}");
+                    break;
+                }
+            }
+        }
+    }
+
+    fn insert(&mut self, address: SocketAddr, info: ServerInfo) {
+        let server = LanServer {
+            address,
+            info,
+            age: Duration::ZERO,
+        };
+        match self.servers.iter_mut().find(|x| x.address == address) {
+            Some(existing) => *existing = server,
+            None => self.servers.push(server),
+        }
+    }
+
+    /// Servers that answered recently in the order they were first found
+    pub fn servers(&self) -> &[LanServer] {
+        &self.servers
+    }
+}
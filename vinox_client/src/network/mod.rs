@@ -1 +1,3 @@
+pub mod discovery;
+pub mod servers;
 pub mod state;
@@ -0,0 +1,43 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{self, ConfigError};
+
+/// Where the saved server list is kept, written with the defaults if it doesn't exist
+pub const SERVERS_PATH: &str = "servers.ron";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SavedServer {
+    pub name: String,
+    /// `host` or `host:port`, resolved when connecting
+    pub address: String,
+}
+
+/// The servers the player saved in the menu along with the name they join as
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct ServerList {
+    pub username: String,
+    pub servers: Vec<SavedServer>,
+}
+
+impl Default for ServerList {
+    fn default() -> Self {
+        Self {
+            username: "player".to_string(),
+            servers: Vec::new(),
+        }
+    }
+}
+
+impl ServerList {
+    /// Read a server list writing out an empty one first if there is none
+    pub fn load_or_create(path: &Path) -> Result<Self, ConfigError> {
+        config::load_or_create(path)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ConfigError> {
+        config::save(self, path)
+    }
+}
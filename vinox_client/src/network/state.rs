@@ -1,51 +1,195 @@
 use renet::{
-    transport::{ClientAuthentication, NetcodeClientTransport},
-    ConnectionConfig, RenetClient,
+    transport::{ClientAuthentication, ConnectToken, NetcodeClientTransport},
+    RenetClient,
 };
 use std::{
-    net::UdpSocket,
+    fmt, fs, io,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    path::Path,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
     time::{Duration, SystemTime},
 };
-use vinox_common::prelude::PROTOCOL_ID;
+use vinox_common::prelude::{
+    connection_config, Channel, ClientMessage, ConnectionData, HandshakeError, ServerMessage,
+    DEFAULT_PORT, PROTOCOL_ID,
+};
+
+#[derive(Debug)]
+pub enum NetworkError {
+    Io(io::Error),
+    /// An address that didn't resolve to anything
+    InvalidAddress(String),
+    InvalidToken(String),
+    Transport(String),
+    Handshake(HandshakeError),
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkError::Io(e) => write!(f, "{e}"),
+            NetworkError::InvalidAddress(address) => write!(f, "can't find server {address:?}"),
+            NetworkError::InvalidToken(e) => write!(f, "invalid connect token: {e}"),
+            NetworkError::Transport(e) => write!(f, "failed to start transport: {e}"),
+            NetworkError::Handshake(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for NetworkError {}
+
+impl From<io::Error> for NetworkError {
+    fn from(e: io::Error) -> Self {
+        NetworkError::Io(e)
+    }
+}
+
+/// Resolve a `host` or `host:port` typed by the player, using `DEFAULT_PORT` if there's no
+/// port. This may block on a DNS lookup
+pub fn resolve_address(address: &str) -> Result<SocketAddr, NetworkError> {
+    let address = address.trim();
+    address
+        .to_socket_addrs()
+        .or_else(|_| (address, DEFAULT_PORT).to_socket_addrs())
+        .ok()
+        .and_then(|mut x| x.next())
+        .ok_or_else(|| NetworkError::InvalidAddress(address.to_string()))
+}
+
+/// `resolve_address` on its own thread so the menu keeps drawing during a slow DNS lookup
+pub struct PendingAddress {
+    address: String,
+    receiver: Receiver<Result<SocketAddr, NetworkError>>,
+}
+
+impl PendingAddress {
+    pub fn new(address: &str) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let lookup = address.to_string();
+        thread::spawn(move || {
+            // Nobody to tell if the lookup was cancelled
+            sender.send(resolve_address(&lookup)).ok();
+        });
+        Self {
+            address: address.trim().to_string(),
+            receiver,
+        }
+    }
+
+    /// What is being looked up
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// The result once the lookup is done, `None` until then
+    pub fn poll(&self) -> Option<Result<SocketAddr, NetworkError>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                Some(Err(NetworkError::InvalidAddress(self.address.clone())))
+            }
+        }
+    }
+}
 
 pub struct NetworkState {
     pub client: RenetClient,
     pub transport: NetcodeClientTransport,
+    messages: Vec<ServerMessage>,
+    /// Reason the server gave when it kicked us
+    kick_reason: Option<String>,
 }
 
 impl NetworkState {
-    pub fn new(server_addr: String) -> Option<Self> {
-        if let Ok(server_addr) = server_addr.parse() {
-            let connection_config = ConnectionConfig::default();
-            let client = RenetClient::new(connection_config);
-
-            let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
-            let current_time = SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap();
-            let client_id = current_time.as_millis() as u64;
-            let authentication = ClientAuthentication::Unsecure {
-                server_addr,
-                client_id,
-                user_data: None,
-                protocol_id: PROTOCOL_ID,
-            };
-
-            let transport =
-                NetcodeClientTransport::new(current_time, authentication, socket).unwrap();
-
-            return Some(Self { client, transport });
-        }
-        None
+    /// Connect to a server that isn't in secure mode
+    pub fn new(server_addr: SocketAddr, username: &str) -> Result<Self, NetworkError> {
+        let user_data = ConnectionData::new(username)
+            .to_user_data()
+            .map_err(NetworkError::Handshake)?;
+        NetworkState::connect(ClientAuthentication::Unsecure {
+            server_addr,
+            // Random instead of the time so two clients starting together don't collide
+            client_id: rand::random(),
+            user_data: Some(user_data),
+            protocol_id: PROTOCOL_ID,
+        })
+    }
+
+    /// Connect using a token issued by the server's `token` command
+    pub fn with_token(token: ConnectToken) -> Result<Self, NetworkError> {
+        NetworkState::connect(ClientAuthentication::Secure {
+            connect_token: token,
+        })
+    }
+
+    /// Read a connect token from a file and connect with it
+    pub fn with_token_file(path: impl AsRef<Path>) -> Result<Self, NetworkError> {
+        let bytes = fs::read(path)?;
+        let token = ConnectToken::read(&mut bytes.as_slice())
+            .map_err(|e| NetworkError::InvalidToken(e.to_string()))?;
+        NetworkState::with_token(token)
+    }
+
+    fn connect(authentication: ClientAuthentication) -> Result<Self, NetworkError> {
+        let client = RenetClient::new(connection_config());
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        let current_time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_err(|e| NetworkError::Transport(e.to_string()))?;
+        let transport = NetcodeClientTransport::new(current_time, authentication, socket)
+            .map_err(|e| NetworkError::Transport(e.to_string()))?;
+        Ok(Self {
+            client,
+            transport,
+            messages: Vec::new(),
+            kick_reason: None,
+        })
     }
 
     pub fn update(&mut self, duration: Duration) -> Result<(), Box<dyn std::error::Error>> {
         // Uncapped or vsync frame rate
         self.client.update(duration);
         self.transport.update(duration, &mut self.client)?;
+
+        for channel in Channel::ALL {
+            while let Some(bytes) = self.client.receive_message(channel) {
+                match ServerMessage::from_bytes(&bytes) {
+                    Ok(ServerMessage::Disconnect { reason }) => {
+                        self.kick_reason = Some(reason.to_string())
+                    }
+                    Ok(message) => self.messages.push(message),
+                    Err(e) => println!("Bad message from server: {e}"),
+                }
+            }
+        }
+
+        self.transport.send_packets(&mut self.client)?;
         Ok(())
     }
 
+    pub fn send(&mut self, message: &ClientMessage) {
+        match message.to_bytes() {
+            Ok(bytes) => self.client.send_message(message.channel(), bytes),
+            Err(e) => println!("Failed to send message: {e}"),
+        }
+    }
+
+    /// Every message received since the last call
+    pub fn drain_messages(&mut self) -> Vec<ServerMessage> {
+        std::mem::take(&mut self.messages)
+    }
+
+    /// Why we were disconnected, preferring the reason the server sent over renet's
+    pub fn disconnect_reason(&self) -> Option<String> {
+        self.kick_reason.clone().or_else(|| {
+            self.client
+                .disconnect_reason()
+                .map(|reason| reason.to_string())
+        })
+    }
+
     pub fn exit(&mut self) {
         self.client.disconnect();
     }
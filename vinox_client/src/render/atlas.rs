@@ -0,0 +1,544 @@
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use image::{imageops, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+/// Name of the texture used for faces whose texture couldn't be found. Always in the atlas
+pub const MISSING_TEXTURE: &str = "missing";
+
+/// Seconds each frame of an animated texture is shown for
+pub const DEFAULT_FRAME_TIME: f32 = 0.1;
+
+/// How an animated texture's frames are laid out in the atlas. A texture is animated when it
+/// is a vertical strip of square frames ie a 16x64 image is 4 frames of 16x16.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextureAnimation {
+    pub frames: u32,
+    pub frame_time: f32,
+    /// Distance in uvs from one frame to the next. The rect of a texture is its first frame
+    pub frame_stride: f32,
+    /// Blend into the next frame instead of snapping to it
+    pub interpolate: bool,
+}
+
+/// Optional `.ron` file next to a texture with the same name ie `water.ron` for `water.png`
+/// ```ron
+/// (frame_time: 0.25, interpolate: true)
+/// ```
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct AnimationMeta {
+    /// Number of frames in the strip, needed when the frames aren't square
+    pub frames: Option<u32>,
+    pub frame_time: f32,
+    pub interpolate: bool,
+}
+
+impl Default for AnimationMeta {
+    fn default() -> Self {
+        Self {
+            frames: None,
+            frame_time: DEFAULT_FRAME_TIME,
+            interpolate: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum AtlasError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Image {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+    Animation {
+        path: PathBuf,
+        source: ron::error::SpannedError,
+    },
+    /// The textures don't fit in the largest atlas allowed
+    TooLarge {
+        max_size: u32,
+    },
+}
+
+impl fmt::Display for AtlasError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AtlasError::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            AtlasError::Image { path, source } => write!(f, "{}: {source}", path.display()),
+            AtlasError::Animation { path, source } => write!(f, "{}: {source}", path.display()),
+            AtlasError::TooLarge { max_size } => {
+                write!(f, "textures don't fit in a {max_size}x{max_size} atlas")
+            }
+        }
+    }
+}
+
+impl std::error::Error for AtlasError {}
+
+/// Every texture packed into one power of two image
+#[derive(Debug, Clone)]
+pub struct TextureAtlas {
+    pub image: RgbaImage,
+    /// Where each texture is as `[x, y, width, height]` from 0 to 1
+    pub rects: HashMap<String, [f32; 4]>,
+    pub animations: HashMap<String, TextureAnimation>,
+    /// Each level half the size of the one before, starting at half of `image`
+    pub mips: Vec<RgbaImage>,
+}
+
+struct Texture {
+    name: String,
+    image: RgbaImage,
+    frames: u32,
+    meta: AnimationMeta,
+}
+
+impl Texture {
+    fn frame_height(&self) -> u32 {
+        self.image.height() / self.frames
+    }
+}
+
+/// Packs textures into a `TextureAtlas`. Textures get a border of their own edge pixels so
+/// filtering and mipmapping don't pull in their neighbors
+pub struct AtlasBuilder {
+    textures: Vec<Texture>,
+    padding: u32,
+    mip_levels: u32,
+    max_size: u32,
+}
+
+impl Default for AtlasBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AtlasBuilder {
+    pub fn new() -> Self {
+        let mut builder = Self {
+            textures: Vec::new(),
+            padding: 2,
+            mip_levels: 0,
+            max_size: 8192,
+        };
+        builder.add(MISSING_TEXTURE, missing_texture());
+        builder
+    }
+
+    /// Pixels of bleed around every frame. Mip level `n` only stays clean with at least
+    /// `2^n` pixels
+    pub fn padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// How many mip levels to generate below the full size atlas
+    pub fn mip_levels(mut self, mip_levels: u32) -> Self {
+        self.mip_levels = mip_levels;
+        self
+    }
+
+    pub fn max_size(mut self, max_size: u32) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Add a texture replacing any other with the same name. Empty images are ignored
+    pub fn add(&mut self, name: &str, image: RgbaImage) {
+        self.add_animated(name, image, AnimationMeta::default());
+    }
+
+    /// Add a texture with animation settings. A frame count in `meta` that doesn't divide
+    /// the image height is ignored
+    pub fn add_animated(&mut self, name: &str, image: RgbaImage, meta: AnimationMeta) {
+        let (width, height) = image.dimensions();
+        if width == 0 || height == 0 {
+            return;
+        }
+        let frames = match meta.frames {
+            Some(frames) if frames > 0 && height % frames == 0 => frames,
+            _ if height > width && height % width == 0 => height / width,
+            _ => 1,
+        };
+        self.textures.retain(|x| x.name != name);
+        self.textures.push(Texture {
+            name: name.to_string(),
+            image,
+            frames,
+            meta,
+        });
+    }
+
+    /// Add every image in a directory and its subdirectories. Textures are named by their
+    /// path without the extension ie `blocks/stone.png` in `textures` is `blocks/stone`
+    pub fn add_dir(&mut self, path: impl AsRef<Path>) -> Result<(), AtlasError> {
+        let path = path.as_ref();
+        self.add_dir_inner(path, path)
+    }
+
+    fn add_dir_inner(&mut self, root: &Path, dir: &Path) -> Result<(), AtlasError> {
+        let io_error = |source| AtlasError::Io {
+            path: dir.to_path_buf(),
+            source,
+        };
+        let mut paths = fs::read_dir(dir)
+            .map_err(io_error)?
+            .map(|x| x.map(|x| x.path()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(io_error)?;
+        // Sorted so the same textures always pack the same way
+        paths.sort();
+        for path in paths {
+            if path.is_dir() {
+                self.add_dir_inner(root, &path)?;
+                continue;
+            }
+            if image::ImageFormat::from_path(&path).is_err() {
+                continue;
+            }
+            let image = image::open(&path)
+                .map_err(|source| AtlasError::Image {
+                    path: path.clone(),
+                    source,
+                })?
+                .into_rgba8();
+            let meta_path = path.with_extension("ron");
+            let meta = match fs::read_to_string(&meta_path) {
+                Ok(source) => ron::from_str(&source).map_err(|source| AtlasError::Animation {
+                    path: meta_path,
+                    source,
+                })?,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => AnimationMeta::default(),
+                Err(source) => {
+                    return Err(AtlasError::Io {
+                        path: meta_path,
+                        source,
+                    })
+                }
+            };
+            let name = path
+                .strip_prefix(root)
+                .unwrap_or(&path)
+                .with_extension("")
+                .components()
+                .map(|x| x.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            self.add_animated(&name, image, meta);
+        }
+        Ok(())
+    }
+
+    /// Size of a texture including the padding around each of its frames
+    fn packed_size(&self, texture: &Texture) -> (u32, u32) {
+        (
+            texture.image.width() + self.padding * 2,
+            (texture.frame_height() + self.padding * 2) * texture.frames,
+        )
+    }
+
+    /// Shelf pack into a `size` square, giving the top left corner of each texture
+    fn pack(&self, order: &[usize], size: u32) -> Option<Vec<(u32, u32)>> {
+        let mut positions = vec![(0, 0); self.textures.len()];
+        let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+        for &index in order {
+            let (width, height) = self.packed_size(&self.textures[index]);
+            if x + width > size {
+                x = 0;
+                y += shelf_height;
+                shelf_height = 0;
+            }
+            if x + width > size || y + height > size {
+                return None;
+            }
+            positions[index] = (x, y);
+            x += width;
+            shelf_height = shelf_height.max(height);
+        }
+        Some(positions)
+    }
+
+    pub fn build(self) -> Result<TextureAtlas, AtlasError> {
+        let mut order: Vec<usize> = (0..self.textures.len()).collect();
+        order.sort_by_key(|&x| {
+            let (width, height) = self.packed_size(&self.textures[x]);
+            std::cmp::Reverse((height, width))
+        });
+
+        let area: u32 = self
+            .textures
+            .iter()
+            .map(|x| {
+                let (width, height) = self.packed_size(x);
+                width * height
+            })
+            .sum();
+        let mut size = (area as f32).sqrt().ceil().max(1.0) as u32;
+        size = size.next_power_of_two();
+        let positions = loop {
+            if size > self.max_size {
+                return Err(AtlasError::TooLarge {
+                    max_size: self.max_size,
+                });
+            }
+            match self.pack(&order, size) {
+                Some(positions) => break positions,
+                None => size *= 2,
+            }
+        };
+
+        let mut image = RgbaImage::new(size, size);
+        let mut rects = HashMap::new();
+        let mut animations = HashMap::new();
+        let scale = size as f32;
+        for (texture, (x, y)) in self.textures.iter().zip(positions) {
+            let width = texture.image.width();
+            let frame_height = texture.frame_height();
+            let stride = frame_height + self.padding * 2;
+            for frame in 0..texture.frames {
+                self.blit_frame(
+                    &mut image,
+                    texture,
+                    frame,
+                    x + self.padding,
+                    y + frame * stride + self.padding,
+                );
+            }
+            rects.insert(
+                texture.name.clone(),
+                [
+                    (x + self.padding) as f32 / scale,
+                    (y + self.padding) as f32 / scale,
+                    width as f32 / scale,
+                    frame_height as f32 / scale,
+                ],
+            );
+            if texture.frames > 1 {
+                animations.insert(
+                    texture.name.clone(),
+                    TextureAnimation {
+                        frames: texture.frames,
+                        frame_time: texture.meta.frame_time,
+                        frame_stride: stride as f32 / scale,
+                        interpolate: texture.meta.interpolate,
+                    },
+                );
+            }
+        }
+
+        let mut mips: Vec<RgbaImage> = Vec::new();
+        for _ in 0..self.mip_levels {
+            let previous = mips.last().unwrap_or(&image);
+            if previous.width() <= 1 {
+                break;
+            }
+            let half = previous.width() / 2;
+            mips.push(imageops::resize(
+                previous,
+                half,
+                half,
+                imageops::FilterType::Triangle,
+            ));
+        }
+
+        Ok(TextureAtlas {
+            image,
+            rects,
+            animations,
+            mips,
+        })
+    }
+
+    /// Copy one frame to `x, y` extending its edge pixels out into the padding
+    fn blit_frame(&self, atlas: &mut RgbaImage, texture: &Texture, frame: u32, x: u32, y: u32) {
+        let width = texture.image.width() as i64;
+        let height = texture.frame_height() as i64;
+        let padding = self.padding as i64;
+        for dy in -padding..height + padding {
+            for dx in -padding..width + padding {
+                let source_x = dx.clamp(0, width - 1) as u32;
+                let source_y = dy.clamp(0, height - 1) as u32 + frame * height as u32;
+                atlas.put_pixel(
+                    (x as i64 + dx) as u32,
+                    (y as i64 + dy) as u32,
+                    *texture.image.get_pixel(source_x, source_y),
+                );
+            }
+        }
+    }
+}
+
+/// Magenta and black checkers so missing textures stand out
+fn missing_texture() -> RgbaImage {
+    RgbaImage::from_fn(16, 16, |x, y| {
+        if (x / 8 + y / 8) % 2 == 0 {
+            Rgba([255, 0, 255, 255])
+        } else {
+            Rgba([0, 0, 0, 255])
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every pixel different so copies can be traced back to where they came from
+    fn texture(width: u32, height: u32, id: u8) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| Rgba([x as u8, y as u8, id, 255]))
+    }
+
+    /// Top left pixel and size in pixels of a texture's rect
+    fn pixel_rect(atlas: &TextureAtlas, name: &str) -> (u32, u32, u32, u32) {
+        let size = atlas.image.width() as f32;
+        let [x, y, width, height] = atlas.rects[name].map(|x| (x * size).round() as u32);
+        (x, y, width, height)
+    }
+
+    #[test]
+    fn power_of_two() {
+        let mut builder = AtlasBuilder::new();
+        builder.add("a", texture(5, 7, 1));
+        builder.add("b", texture(20, 3, 2));
+        builder.add("c", texture(9, 9, 3));
+        let atlas = builder.build().unwrap();
+        let size = atlas.image.width();
+        assert_eq!(atlas.image.height(), size);
+        assert!(size.is_power_of_two());
+        for rect in atlas.rects.values() {
+            assert!(rect.iter().all(|x| (0.0..=1.0).contains(x)));
+            assert!(rect[0] + rect[2] <= 1.0 && rect[1] + rect[3] <= 1.0);
+        }
+
+        let mut builder = AtlasBuilder::new().max_size(32);
+        builder.add("big", texture(40, 40, 1));
+        assert!(matches!(
+            builder.build(),
+            Err(AtlasError::TooLarge { max_size: 32 })
+        ));
+    }
+
+    #[test]
+    fn rects_and_bleed() {
+        let mut builder = AtlasBuilder::new().padding(2);
+        builder.add("a", texture(4, 4, 1));
+        builder.add("b", texture(6, 3, 2));
+        let atlas = builder.build().unwrap();
+
+        for (name, id, width, height) in [("a", 1, 4, 4), ("b", 2, 6, 3)] {
+            let (x, y, rect_width, rect_height) = pixel_rect(&atlas, name);
+            assert_eq!((rect_width, rect_height), (width, height));
+            // The rect covers exactly the texture
+            for dy in 0..height {
+                for dx in 0..width {
+                    assert_eq!(
+                        atlas.image.get_pixel(x + dx, y + dy).0,
+                        [dx as u8, dy as u8, id, 255]
+                    );
+                }
+            }
+            // The padding repeats the nearest edge pixel
+            let edge = |dx: i64, dy: i64| {
+                atlas
+                    .image
+                    .get_pixel((x as i64 + dx) as u32, (y as i64 + dy) as u32)
+                    .0
+            };
+            let (w, h) = (width as i64, height as i64);
+            assert_eq!(edge(-2, -2), [0, 0, id, 255]);
+            assert_eq!(edge(-1, 1), [0, 1, id, 255]);
+            assert_eq!(edge(w + 1, 0), [width as u8 - 1, 0, id, 255]);
+            assert_eq!(edge(1, h + 1), [1, height as u8 - 1, id, 255]);
+            assert_eq!(
+                edge(w + 1, h + 1),
+                [width as u8 - 1, height as u8 - 1, id, 255]
+            );
+        }
+    }
+
+    #[test]
+    fn mip_chain() {
+        let mut builder = AtlasBuilder::new().mip_levels(3);
+        builder.add("a", texture(16, 16, 1));
+        let atlas = builder.build().unwrap();
+        let size = atlas.image.width();
+        let sizes: Vec<_> = atlas.mips.iter().map(|x| x.dimensions()).collect();
+        assert_eq!(
+            sizes,
+            [
+                (size / 2, size / 2),
+                (size / 4, size / 4),
+                (size / 8, size / 8)
+            ]
+        );
+
+        // Asking for more levels than there are stops at 1x1
+        let atlas = AtlasBuilder::new().mip_levels(100).build().unwrap();
+        assert_eq!(atlas.mips.len() as u32, atlas.image.width().ilog2());
+        assert_eq!(atlas.mips.last().unwrap().dimensions(), (1, 1));
+        assert!(AtlasBuilder::new().build().unwrap().mips.is_empty());
+    }
+
+    #[test]
+    fn animated_strips() {
+        let mut builder = AtlasBuilder::new().padding(1);
+        builder.add("water", texture(4, 16, 1));
+        builder.add_animated(
+            "lava",
+            texture(4, 8, 2),
+            AnimationMeta {
+                frames: Some(2),
+                frame_time: 0.25,
+                interpolate: true,
+            },
+        );
+        // 3 frames don't divide 8 so it falls back to square frames
+        builder.add_animated(
+            "fire",
+            texture(4, 8, 3),
+            AnimationMeta {
+                frames: Some(3),
+                ..Default::default()
+            },
+        );
+        builder.add("stone", texture(4, 4, 4));
+        let atlas = builder.build().unwrap();
+        let size = atlas.image.width() as f32;
+
+        let water = atlas.animations["water"];
+        assert_eq!(water.frames, 4);
+        assert_eq!(water.frame_time, DEFAULT_FRAME_TIME);
+        assert!(!water.interpolate);
+        assert_eq!(water.frame_stride, 6.0 / size);
+        assert_eq!(pixel_rect(&atlas, "water").3, 4);
+
+        let lava = atlas.animations["lava"];
+        assert_eq!(
+            (lava.frames, lava.frame_time, lava.interpolate),
+            (2, 0.25, true)
+        );
+        assert_eq!(pixel_rect(&atlas, "lava").3, 4);
+
+        assert_eq!(atlas.animations["fire"].frames, 2);
+        assert!(!atlas.animations.contains_key("stone"));
+
+        // Each frame sits one stride below the last
+        let (x, y, _, _) = pixel_rect(&atlas, "water");
+        for frame in 0..4 {
+            let frame_y = y + frame * 6;
+            assert_eq!(
+                atlas.image.get_pixel(x + 2, frame_y + 1).0,
+                [2, (frame * 4 + 1) as u8, 1, 255]
+            );
+        }
+    }
+}
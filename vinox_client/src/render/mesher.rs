@@ -0,0 +1,481 @@
+use std::collections::HashMap;
+
+use glam::{IVec3, Vec3};
+use vinox_common::prelude::{Chunk, ChunkPos, LocalPos, CHUNK_SIZE, FACE_NEIGHBORS};
+use vinox_formats::{
+    block::{BlockId, BlockRegistry, Face},
+    model::{ModelMesh, VoxelModel},
+};
+
+use super::{
+    atlas::{TextureAnimation, MISSING_TEXTURE},
+    model::{Aabb, Mesh, Vertex},
+};
+
+const SIZE: i32 = CHUNK_SIZE as i32;
+/// Chunk plus a one block border taken from the neighbors
+const PADDED: i32 = SIZE + 2;
+
+/// Where a face's texture is in the atlas and how it animates, as it goes in the vertices
+#[derive(Debug, Clone, Copy)]
+struct FaceTexture {
+    rect: [f32; 4],
+    animation: [f32; 4],
+}
+
+/// How a block shows up in a chunk mesh
+#[derive(Debug, Clone)]
+enum BlockMesh {
+    Air,
+    Cube {
+        /// Whether blocks behind this one are hidden by it
+        opaque: bool,
+        /// Texture of each face in `Face::ALL` order
+        faces: [FaceTexture; 6],
+    },
+    Model(String),
+}
+
+/// Turns chunks into meshes textured from the block atlas. Full cubes are greedy meshed so
+/// their vertices have tile local uvs that repeat across a merged face and the atlas rect
+/// `[x, y, width, height]` in the color. `chunk.wgsl` maps them into the atlas. Voxel models
+/// are already in atlas space and have a color of zero to tell the shader so. Animated
+/// textures are stepped through in the shader so they never need a remesh.
+#[derive(Debug, Clone)]
+pub struct ChunkMesher {
+    ids: HashMap<String, BlockId>,
+    blocks: Vec<BlockMesh>,
+    models: HashMap<String, VoxelModel>,
+    /// Where each texture is in the block atlas
+    uvs: HashMap<String, [f32; 4]>,
+}
+
+impl ChunkMesher {
+    pub fn new(
+        registry: &BlockRegistry,
+        models: HashMap<String, VoxelModel>,
+        uvs: HashMap<String, [f32; 4]>,
+        animations: &HashMap<String, TextureAnimation>,
+    ) -> Self {
+        let texture = |texture: Option<&str>| {
+            let texture = texture
+                .filter(|x| uvs.contains_key(*x))
+                .unwrap_or(MISSING_TEXTURE);
+            FaceTexture {
+                rect: uvs.get(texture).copied().unwrap_or([0.0, 0.0, 1.0, 1.0]),
+                animation: animations.get(texture).map_or([0.0; 4], |x| {
+                    [
+                        x.frames as f32,
+                        x.frame_time,
+                        x.frame_stride,
+                        x.interpolate as u8 as f32,
+                    ]
+                }),
+            }
+        };
+        let mut ids = HashMap::new();
+        let mut blocks = Vec::new();
+        for (id, definition) in registry.iter() {
+            ids.insert(definition.identifier.clone(), id);
+            let block = if id == 0 {
+                BlockMesh::Air
+            } else if let Some(model) = definition
+                .model
+                .as_ref()
+                .filter(|x| models.contains_key(*x))
+            {
+                BlockMesh::Model(model.clone())
+            } else {
+                BlockMesh::Cube {
+                    opaque: !definition.transparent,
+                    faces: Face::ALL.map(|face| texture(definition.textures.face(face))),
+                }
+            };
+            let index = id as usize;
+            if blocks.len() <= index {
+                blocks.resize(index + 1, BlockMesh::Air);
+            }
+            blocks[index] = block;
+        }
+        Self {
+            ids,
+            blocks,
+            models,
+            uvs,
+        }
+    }
+
+    fn block(&self, id: BlockId) -> &BlockMesh {
+        self.blocks.get(id as usize).unwrap_or(&BlockMesh::Air)
+    }
+
+    fn is_opaque(&self, id: BlockId) -> bool {
+        matches!(self.block(id), BlockMesh::Cube { opaque: true, .. })
+    }
+
+    /// Is the face of `id` against `neighbor` visible
+    fn face_visible(&self, id: BlockId, neighbor: BlockId) -> bool {
+        match self.block(id) {
+            BlockMesh::Cube { opaque, .. } => {
+                // Transparent blocks like water don't draw faces inside of themselves
+                !self.is_opaque(neighbor) && (*opaque || id != neighbor)
+            }
+            _ => false,
+        }
+    }
+
+    /// Build the mesh of the chunk at `pos` in world space. `neighbors` are in
+    /// `FACE_NEIGHBORS` order and are used to hide faces against the next chunk over, so the
+    /// border of a chunk has to be remeshed when a neighbor arrives or changes.
+    pub fn mesh(&self, pos: ChunkPos, chunk: &Chunk, neighbors: [Option<&Chunk>; 6]) -> Mesh {
+        let grid = self.padded_grid(chunk, neighbors);
+        let origin = pos.origin().as_vec3();
+        let mut mesh = Mesh::default();
+
+        for (face_index, face) in Face::ALL.into_iter().enumerate() {
+            self.greedy_face(&grid, face_index, face, origin, &mut mesh);
+        }
+        self.model_blocks(&grid, origin, &mut mesh);
+
+        if !mesh.indices.is_empty() {
+            mesh.aabb = Some(Aabb::from_min_max(
+                origin,
+                origin + Vec3::splat(SIZE as f32),
+            ));
+        }
+        mesh
+    }
+
+    /// Block ids of the chunk with a one block border. Missing neighbors count as air
+    fn padded_grid(&self, chunk: &Chunk, neighbors: [Option<&Chunk>; 6]) -> Vec<BlockId> {
+        let mut grid = vec![0; (PADDED * PADDED * PADDED) as usize];
+        // A chunk only has a handful of block types so look them up once each
+        let mut cache: HashMap<&str, BlockId> = HashMap::new();
+        let mut id = |identifier| {
+            *cache
+                .entry(identifier)
+                .or_insert_with(|| self.ids.get(identifier).copied().unwrap_or(0))
+        };
+
+        for local in LocalPos::all() {
+            let cell = local.as_ivec3() + IVec3::ONE;
+            grid[padded_index(cell)] = id(chunk.get(local));
+        }
+        for (offset, neighbor) in FACE_NEIGHBORS.into_iter().zip(neighbors) {
+            let Some(neighbor) = neighbor else {
+                continue;
+            };
+            // Walk the layer of the neighbor touching this chunk
+            for a in 0..SIZE {
+                for b in 0..SIZE {
+                    let local = border_cell(offset, a, b);
+                    let cell = local + IVec3::ONE + offset * SIZE;
+                    grid[padded_index(cell)] = id(neighbor.get(LocalPos(local.as_uvec3())));
+                }
+            }
+        }
+        grid
+    }
+
+    /// Merge the visible faces pointing towards `face` slice by slice into as few quads as
+    /// possible. `Face::ALL` and `FACE_NEIGHBORS` share an order so `face_index` is both
+    fn greedy_face(
+        &self,
+        grid: &[BlockId],
+        face_index: usize,
+        face: Face,
+        origin: Vec3,
+        mesh: &mut Mesh,
+    ) {
+        let normal = FACE_NEIGHBORS[face_index];
+        let axis = normal
+            .abs()
+            .to_array()
+            .iter()
+            .position(|x| *x != 0)
+            .unwrap();
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        let mut mask = vec![0 as BlockId; (SIZE * SIZE) as usize];
+
+        for slice in 0..SIZE {
+            for j in 0..SIZE {
+                for i in 0..SIZE {
+                    let mut cell = IVec3::ZERO;
+                    cell[axis] = slice;
+                    cell[u] = i;
+                    cell[v] = j;
+                    let cell = cell + IVec3::ONE;
+                    let id = grid[padded_index(cell)];
+                    let neighbor = grid[padded_index(cell + normal)];
+                    mask[(j * SIZE + i) as usize] = if self.face_visible(id, neighbor) {
+                        id
+                    } else {
+                        0
+                    };
+                }
+            }
+
+            for j in 0..SIZE {
+                let mut i = 0;
+                while i < SIZE {
+                    let id = mask[(j * SIZE + i) as usize];
+                    if id == 0 {
+                        i += 1;
+                        continue;
+                    }
+                    let mut width = 1;
+                    while i + width < SIZE && mask[(j * SIZE + i + width) as usize] == id {
+                        width += 1;
+                    }
+                    let mut height = 1;
+                    'grow: while j + height < SIZE {
+                        for k in i..i + width {
+                            if mask[((j + height) * SIZE + k) as usize] != id {
+                                break 'grow;
+                            }
+                        }
+                        height += 1;
+                    }
+                    for row in j..j + height {
+                        for k in i..i + width {
+                            mask[(row * SIZE + k) as usize] = 0;
+                        }
+                    }
+
+                    let mut min = Vec3::ZERO;
+                    min[axis] = slice as f32;
+                    min[u] = i as f32;
+                    min[v] = j as f32;
+                    let mut max = min;
+                    max[axis] += 1.0;
+                    max[u] += width as f32;
+                    max[v] += height as f32;
+                    if let BlockMesh::Cube { faces, .. } = self.block(id) {
+                        push_quad(mesh, face, origin + min, origin + max, faces[face_index]);
+                    }
+                    i += width;
+                }
+            }
+        }
+    }
+
+    /// Blocks with a voxel model aren't merged, each one appends its model
+    fn model_blocks(&self, grid: &[BlockId], origin: Vec3, mesh: &mut Mesh) {
+        let mut models = ModelMesh::default();
+        // A broken model fails on every block using it, so log each once per chunk
+        let mut failed: HashMap<&str, usize> = HashMap::new();
+        for local in LocalPos::all() {
+            let cell = local.as_ivec3() + IVec3::ONE;
+            let BlockMesh::Model(name) = self.block(grid[padded_index(cell)]) else {
+                continue;
+            };
+            let culled = |face: Face| {
+                let index = Face::ALL.iter().position(|x| *x == face).unwrap();
+                self.is_opaque(grid[padded_index(cell + FACE_NEIGHBORS[index])])
+            };
+            let offset = origin + local.as_vec3();
+            if let Err(e) = self.models[name].append_mesh(
+                &mut models,
+                offset.to_array(),
+                |texture| {
+                    self.uvs
+                        .get(texture)
+                        .or_else(|| self.uvs.get(MISSING_TEXTURE))
+                        .copied()
+                },
+                culled,
+            ) {
+                let count = failed.entry(name).or_default();
+                if *count == 0 {
+                    println!("Failed to mesh model {name}: {e}");
+                }
+                *count += 1;
+            }
+        }
+        for (name, count) in failed {
+            if count > 1 {
+                println!("Model {name} failed for {count} blocks in this chunk");
+            }
+        }
+
+        let start = mesh.vertices.len() as u32;
+        mesh.vertices
+            .extend(models.vertices.into_iter().map(|x| Vertex {
+                pos: x.pos,
+                tex_coord: x.tex_coord,
+                color: [0.0; 4],
+                normals: x.normals,
+                animation: [0.0; 4],
+                joints: [0; 4],
+                weights: [0.0; 4],
+            }));
+        mesh.indices
+            .extend(models.indices.into_iter().map(|x| start + x));
+    }
+}
+
+fn padded_index(cell: IVec3) -> usize {
+    ((cell.y * PADDED + cell.z) * PADDED + cell.x) as usize
+}
+
+/// Cell `a, b` of the layer of a neighbor chunk that touches the chunk in direction `offset`,
+/// in the neighbor's local coordinates
+fn border_cell(offset: IVec3, a: i32, b: i32) -> IVec3 {
+    let axis = offset
+        .abs()
+        .to_array()
+        .iter()
+        .position(|x| *x != 0)
+        .unwrap();
+    let mut cell = IVec3::ZERO;
+    cell[axis] = if offset[axis] > 0 { 0 } else { SIZE - 1 };
+    cell[(axis + 1) % 3] = a;
+    cell[(axis + 2) % 3] = b;
+    cell
+}
+
+/// Add one face of the box from `min` to `max` with its texture repeating once per block
+fn push_quad(mesh: &mut Mesh, face: Face, min: Vec3, max: Vec3, texture: FaceTexture) {
+    let corners = face
+        .corners(min.to_array(), max.to_array())
+        .map(Vec3::from_array);
+    let width = corners[0].distance(corners[3]);
+    let height = corners[0].distance(corners[1]);
+    let uvs = [[0.0, 0.0], [0.0, height], [width, height], [width, 0.0]];
+
+    let start = mesh.vertices.len() as u32;
+    for (corner, uv) in corners.into_iter().zip(uvs) {
+        mesh.vertices.push(Vertex {
+            pos: corner.to_array(),
+            tex_coord: uv,
+            color: texture.rect,
+            normals: face.normal(),
+            animation: texture.animation,
+            joints: [0; 4],
+            weights: [0.0; 4],
+        });
+    }
+    mesh.indices
+        .extend([0, 1, 2, 0, 2, 3].into_iter().map(|x| start + x));
+}
+
+#[cfg(test)]
+mod tests {
+    use vinox_formats::block::BlockDefinition;
+
+    use super::*;
+
+    const STONE: &str = "test:stone";
+    const GLASS: &str = "test:glass";
+
+    fn mesher() -> ChunkMesher {
+        let block = |identifier: &str, transparent| BlockDefinition {
+            identifier: identifier.to_string(),
+            display_name: identifier.to_string(),
+            transparent,
+            solid: true,
+            ..BlockDefinition::air()
+        };
+        let registry =
+            BlockRegistry::from_definitions(vec![block(STONE, false), block(GLASS, true)]).unwrap();
+        ChunkMesher::new(&registry, HashMap::new(), HashMap::new(), &HashMap::new())
+    }
+
+    fn quads(mesh: &Mesh) -> usize {
+        assert_eq!(mesh.indices.len() % 6, 0);
+        mesh.indices.len() / 6
+    }
+
+    /// Total area of every quad, ie how many block faces are drawn
+    fn area(mesh: &Mesh) -> f32 {
+        mesh.vertices
+            .chunks(4)
+            .map(|x| {
+                let corner = Vec3::from_array(x[0].pos);
+                corner.distance(Vec3::from_array(x[1].pos))
+                    * corner.distance(Vec3::from_array(x[3].pos))
+            })
+            .sum()
+    }
+
+    fn chunk(blocks: &[((u32, u32, u32), &str)]) -> Chunk {
+        let mut chunk = Chunk::new();
+        for ((x, y, z), block) in blocks {
+            chunk.set(LocalPos::new(*x, *y, *z).unwrap(), block);
+        }
+        chunk
+    }
+
+    #[test]
+    fn single_block() {
+        let pos = ChunkPos::new(1, 0, -1);
+        let mesh = mesher().mesh(pos, &chunk(&[((1, 2, 3), STONE)]), [None; 6]);
+        assert_eq!(quads(&mesh), 6);
+        assert_eq!(area(&mesh), 6.0);
+
+        // In world space around the block
+        let min = pos.origin().as_vec3() + Vec3::new(1.0, 2.0, 3.0);
+        for vertex in &mesh.vertices {
+            let pos = Vec3::from_array(vertex.pos);
+            assert!(pos.cmpge(min).all() && pos.cmple(min + Vec3::ONE).all());
+        }
+        assert!(mesh.aabb.is_some());
+
+        let empty = mesher().mesh(pos, &Chunk::new(), [None; 6]);
+        assert_eq!(quads(&empty), 0);
+        assert!(empty.aabb.is_none());
+    }
+
+    #[test]
+    fn solid_chunk_merges() {
+        let mesh = mesher().mesh(ChunkPos::new(0, 0, 0), &Chunk::filled(STONE), [None; 6]);
+        assert_eq!(quads(&mesh), 6);
+        assert_eq!(area(&mesh), 6.0 * (SIZE * SIZE) as f32);
+    }
+
+    #[test]
+    fn neighbors_cull_faces() {
+        let mesher = mesher();
+        let solid = Chunk::filled(STONE);
+        let glass = Chunk::filled(GLASS);
+        let pos = ChunkPos::new(0, 0, 0);
+
+        let mesh = mesher.mesh(pos, &solid, [Some(&solid); 6]);
+        assert_eq!(quads(&mesh), 0);
+
+        // Only the side against the opaque neighbor is hidden
+        let mut neighbors = [None; 6];
+        neighbors[2] = Some(&solid);
+        assert_eq!(quads(&mesher.mesh(pos, &solid, neighbors)), 5);
+
+        // Transparent neighbors don't hide anything
+        assert_eq!(quads(&mesher.mesh(pos, &solid, [Some(&glass); 6])), 6);
+    }
+
+    #[test]
+    fn transparent_faces() {
+        let mesher = mesher();
+        let pos = ChunkPos::new(0, 0, 0);
+
+        // No faces between two glass blocks, 10 faces of the outside only
+        let mesh = mesher.mesh(
+            pos,
+            &chunk(&[((1, 1, 1), GLASS), ((2, 1, 1), GLASS)]),
+            [None; 6],
+        );
+        assert_eq!(area(&mesh), 10.0);
+
+        // Stone still shows through glass but glass is hidden by stone
+        let mesh = mesher.mesh(
+            pos,
+            &chunk(&[((1, 1, 1), GLASS), ((2, 1, 1), STONE)]),
+            [None; 6],
+        );
+        assert_eq!(area(&mesh), 11.0);
+
+        // Same for glass across a chunk border
+        let glass = Chunk::filled(GLASS);
+        let mesh = mesher.mesh(pos, &glass, [Some(&glass); 6]);
+        assert_eq!(quads(&mesh), 0);
+    }
+}
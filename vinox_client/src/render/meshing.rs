@@ -0,0 +1,214 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use glam::Vec3;
+use vinox_common::prelude::{Chunk, ChunkPos, CHUNK_SIZE};
+
+use super::{mesher::ChunkMesher, model::Mesh};
+
+/// A chunk and its neighbors in `FACE_NEIGHBORS` order as they were when the job was queued
+pub struct ChunkSnapshot {
+    pub chunk: Arc<Chunk>,
+    pub neighbors: [Option<Arc<Chunk>>; 6],
+}
+
+struct Job {
+    pos: ChunkPos,
+    generation: u64,
+    /// Squared distance to the camera, closest is meshed first
+    distance: f32,
+    snapshot: ChunkSnapshot,
+}
+
+impl PartialEq for Job {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Job {}
+
+impl PartialOrd for Job {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Job {
+    // `BinaryHeap` is a max heap so the nearest job has to compare as the greatest
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.distance.total_cmp(&self.distance)
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    jobs: BinaryHeap<Job>,
+    shutdown: bool,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    available: Condvar,
+}
+
+struct MeshResult {
+    pos: ChunkPos,
+    generation: u64,
+    mesh: Mesh,
+}
+
+/// Meshes chunks on worker threads so the render thread only has to upload the results.
+/// Every job for a chunk gets a new generation so results for chunks that were remeshed
+/// again or unloaded while a worker had them are thrown away.
+pub struct MeshPool {
+    shared: Arc<Shared>,
+    results: Receiver<MeshResult>,
+    workers: Vec<JoinHandle<()>>,
+    /// Latest generation queued for each chunk waiting on a mesh
+    generations: HashMap<ChunkPos, u64>,
+    next_generation: u64,
+    focus: Vec3,
+}
+
+impl MeshPool {
+    /// Start the workers. Leaves a core for the render thread so meshing can't starve it
+    pub fn new(mesher: ChunkMesher) -> Self {
+        let threads = thread::available_parallelism()
+            .map(|x| x.get().saturating_sub(1))
+            .unwrap_or(1)
+            .max(1);
+        let mesher = Arc::new(mesher);
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue::default()),
+            available: Condvar::new(),
+        });
+        let (sender, results) = mpsc::channel();
+        let workers = (0..threads)
+            .map(|index| {
+                let mesher = mesher.clone();
+                let shared = shared.clone();
+                let sender = sender.clone();
+                thread::Builder::new()
+                    .name(format!("mesher {index}"))
+                    .spawn(move || worker(&mesher, &shared, &sender))
+                    .expect("Failed to spawn meshing thread")
+            })
+            .collect();
+        Self {
+            shared,
+            results,
+            workers,
+            generations: HashMap::new(),
+            next_generation: 0,
+            focus: Vec3::ZERO,
+        }
+    }
+
+    fn distance(&self, pos: ChunkPos) -> f32 {
+        let center = pos.origin().as_vec3() + Vec3::splat(CHUNK_SIZE as f32 / 2.0);
+        center.distance_squared(self.focus)
+    }
+
+    /// Move the point jobs are prioritized around, usually `Camera::position`
+    pub fn set_focus(&mut self, focus: Vec3) {
+        let moved_chunk = ChunkPos::from(focus) != ChunkPos::from(self.focus);
+        self.focus = focus;
+        // Reordering every frame would be wasted work for the tiny camera movements
+        if moved_chunk {
+            let mut queue = self.shared.queue.lock().unwrap();
+            let mut jobs = std::mem::take(&mut queue.jobs).into_vec();
+            for job in jobs.iter_mut() {
+                job.distance = self.distance(job.pos);
+            }
+            queue.jobs = BinaryHeap::from(jobs);
+        }
+    }
+
+    /// Queue a chunk to be meshed replacing any job already waiting for it
+    pub fn submit(&mut self, pos: ChunkPos, snapshot: ChunkSnapshot) {
+        self.next_generation += 1;
+        let generation = self.next_generation;
+        self.generations.insert(pos, generation);
+        let job = Job {
+            pos,
+            generation,
+            distance: self.distance(pos),
+            snapshot,
+        };
+
+        let mut queue = self.shared.queue.lock().unwrap();
+        queue.jobs.retain(|x| x.pos != pos);
+        queue.jobs.push(job);
+        drop(queue);
+        self.shared.available.notify_one();
+    }
+
+    /// Drop a chunk's pending job and ignore any mesh already being built for it
+    pub fn cancel(&mut self, pos: ChunkPos) {
+        if self.generations.remove(&pos).is_some() {
+            let mut queue = self.shared.queue.lock().unwrap();
+            queue.jobs.retain(|x| x.pos != pos);
+        }
+    }
+
+    /// Finished meshes, at most `limit` of them so a burst of chunks is uploaded over
+    /// several frames instead of hitching one
+    pub fn drain(&mut self, limit: usize) -> Vec<(ChunkPos, Mesh)> {
+        let mut meshes = Vec::new();
+        while meshes.len() < limit {
+            let Ok(result) = self.results.try_recv() else {
+                break;
+            };
+            if self.generations.get(&result.pos) == Some(&result.generation) {
+                self.generations.remove(&result.pos);
+                meshes.push((result.pos, result.mesh));
+            }
+        }
+        meshes
+    }
+}
+
+impl Drop for MeshPool {
+    fn drop(&mut self) {
+        self.shared.queue.lock().unwrap().shutdown = true;
+        self.shared.available.notify_all();
+        for worker in self.workers.drain(..) {
+            worker.join().ok();
+        }
+    }
+}
+
+fn worker(mesher: &ChunkMesher, shared: &Shared, results: &Sender<MeshResult>) {
+    loop {
+        let job = {
+            let mut queue = shared.queue.lock().unwrap();
+            loop {
+                if queue.shutdown {
+                    return;
+                }
+                if let Some(job) = queue.jobs.pop() {
+                    break job;
+                }
+                queue = shared.available.wait(queue).unwrap();
+            }
+        };
+        let neighbors = job.snapshot.neighbors.each_ref().map(|x| x.as_deref());
+        let mesh = mesher.mesh(job.pos, &job.snapshot.chunk, neighbors);
+        let result = MeshResult {
+            pos: job.pos,
+            generation: job.generation,
+            mesh,
+        };
+        if results.send(result).is_err() {
+            return;
+        }
+    }
+}
@@ -1,2 +1,5 @@
+pub mod atlas;
+pub mod mesher;
+pub mod meshing;
 pub mod model;
 pub mod state;
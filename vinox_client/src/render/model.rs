@@ -1,12 +1,122 @@
 use base64::Engine;
 use glam::*;
-use gltf::scene::Transform;
-use image::{EncodableLayout, ImageBuffer, RgbaImage};
-use std::{fs::File, io::BufReader, path::Path};
+use gltf::{animation::util::ReadOutputs, scene::Transform};
+use image::{EncodableLayout, RgbaImage};
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-fn transform_to_matrix(transform: Transform) -> Mat4 {
-    let tr = transform.matrix();
-    Mat4::from_cols_array_2d(&tr)
+#[derive(Debug)]
+pub enum ModelError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Gltf {
+        path: Option<PathBuf>,
+        source: gltf::Error,
+    },
+    /// A uri that isn't an embedded `data:` uri or couldn't be decoded
+    InvalidUri {
+        path: Option<PathBuf>,
+        uri: String,
+    },
+    UnsupportedBuffer {
+        path: Option<PathBuf>,
+        buffer: usize,
+        mime_type: String,
+    },
+    /// A glb file refers to its binary chunk but doesn't have one
+    MissingBlob {
+        path: Option<PathBuf>,
+    },
+    /// A buffer has less data than the file says it does or a view reaches past its end
+    BufferTooShort {
+        path: Option<PathBuf>,
+        buffer: usize,
+        expected: usize,
+        actual: usize,
+    },
+    Image {
+        path: Option<PathBuf>,
+        node: usize,
+        primitive: usize,
+        source: image::ImageError,
+    },
+}
+
+impl ModelError {
+    /// The file being loaded, `None` when loading from bytes
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            ModelError::Io { path, .. } => Some(path),
+            ModelError::Gltf { path, .. }
+            | ModelError::InvalidUri { path, .. }
+            | ModelError::UnsupportedBuffer { path, .. }
+            | ModelError::MissingBlob { path }
+            | ModelError::BufferTooShort { path, .. }
+            | ModelError::Image { path, .. } => path.as_deref(),
+        }
+    }
+
+    fn with_path(mut self, file: &Path) -> Self {
+        match &mut self {
+            ModelError::Io { .. } => {}
+            ModelError::Gltf { path, .. }
+            | ModelError::InvalidUri { path, .. }
+            | ModelError::UnsupportedBuffer { path, .. }
+            | ModelError::MissingBlob { path }
+            | ModelError::BufferTooShort { path, .. }
+            | ModelError::Image { path, .. } => *path = Some(file.to_path_buf()),
+        }
+        self
+    }
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(path) = self.path() {
+            write!(f, "{}: ", path.display())?;
+        }
+        match self {
+            ModelError::Io { source, .. } => write!(f, "{source}"),
+            ModelError::Gltf { source, .. } => write!(f, "{source}"),
+            // Data uris can be megabytes long
+            ModelError::InvalidUri { uri, .. } => write!(f, "can't read uri {uri:.64}"),
+            ModelError::UnsupportedBuffer {
+                buffer, mime_type, ..
+            } => write!(f, "buffer {buffer} has unsupported type {mime_type}"),
+            ModelError::MissingBlob { .. } => write!(f, "missing binary chunk"),
+            ModelError::BufferTooShort {
+                buffer,
+                expected,
+                actual,
+                ..
+            } => write!(f, "buffer {buffer} needs {expected} bytes but has {actual}"),
+            ModelError::Image {
+                node,
+                primitive,
+                source,
+                ..
+            } => write!(f, "node {node} primitive {primitive}: {source}"),
+        }
+    }
+}
+
+impl std::error::Error for ModelError {}
+
+/// Cubic spline outputs are an in tangent, value and out tangent per keyframe
+fn keyframe_values<T>(values: impl Iterator<Item = T>, cubic: bool) -> Vec<T> {
+    if cubic {
+        values.skip(1).step_by(3).collect()
+    } else {
+        values.collect()
+    }
 }
 
 // This is needed to handle ascii gltf files
@@ -38,19 +148,31 @@ impl<'a> DataUri<'a> {
         })
     }
 
-    fn decode(&self) -> Result<Vec<u8>, String> {
+    fn decode(&self) -> Option<Vec<u8>> {
         if self.base64 {
-            if let Ok(vec) = base64::engine::general_purpose::STANDARD_NO_PAD.decode(self.data) {
-                Ok(vec)
-            } else {
-                Err("Failed to decode base64".to_string())
-            }
+            base64::engine::general_purpose::STANDARD_NO_PAD
+                .decode(self.data)
+                .ok()
         } else {
-            Ok(self.data.as_bytes().to_owned())
+            Some(self.data.as_bytes().to_owned())
         }
     }
 }
 
+/// Mime type and contents of a percent encoded data uri
+fn read_data_uri(uri: &str) -> Result<(String, Vec<u8>), ModelError> {
+    let invalid = || ModelError::InvalidUri {
+        path: None,
+        uri: uri.to_string(),
+    };
+    let decoded = percent_encoding::percent_decode_str(uri)
+        .decode_utf8()
+        .map_err(|_| invalid())?;
+    let data_uri = DataUri::parse(&decoded).map_err(|_| invalid())?;
+    let bytes = data_uri.decode().ok_or_else(invalid)?;
+    Ok((data_uri.mime_type.to_string(), bytes))
+}
+
 // Implementation tooken from bevy
 /// An aabb stands for axis aligned bounding box. This is basically a cube that can't rotate.
 #[derive(Debug, Copy, Clone)]
@@ -85,7 +207,7 @@ impl Aabb {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Default)]
 #[repr(C)]
 pub struct Vertex {
     /// The position of this vertex
@@ -96,6 +218,13 @@ pub struct Vertex {
     pub color: [f32; 4],
     /// Normal of this vertex (the direction it faces)
     pub normals: [f32; 3],
+    /// Texture animation as frame count, seconds per frame, uv offset between frames and 1 to
+    /// blend between frames. All zero for textures that aren't animated
+    pub animation: [f32; 4],
+    /// Indices into `Skin::joints` of the joints moving this vertex
+    pub joints: [u16; 4],
+    /// How much each of `joints` moves this vertex, all zero when it isn't skinned
+    pub weights: [f32; 4],
 }
 
 impl Vertex {
@@ -114,32 +243,477 @@ impl Vertex {
             tex_coord: uv.into(),
             color,
             normals: normals.into(),
+            animation: [0.0; 4],
+            joints: [0; 4],
+            weights: [0.0; 4],
+        }
+    }
+
+    /// This vertex moved by the weighted joint matrices of a pose
+    pub fn skinned(&self, joint_matrices: &[Mat4]) -> Vertex {
+        let mut matrix = Mat4::ZERO;
+        for (joint, weight) in self.joints.iter().zip(self.weights) {
+            if let Some(joint) = joint_matrices.get(*joint as usize) {
+                matrix += *joint * weight;
+            }
+        }
+        if matrix == Mat4::ZERO {
+            return *self;
+        }
+        self.transformed(matrix)
+    }
+
+    pub fn transformed(&self, matrix: Mat4) -> Vertex {
+        Vertex {
+            pos: matrix.transform_point3(self.pos.into()).into(),
+            normals: matrix
+                .transform_vector3(self.normals.into())
+                .normalize_or_zero()
+                .into(),
+            ..*self
         }
     }
 }
 
+/// How the alpha of a material's base color is used
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AlphaMode {
+    /// Alpha is ignored
+    #[default]
+    Opaque,
+    /// Fully transparent below the cutoff and fully opaque otherwise
+    Mask(f32),
+    /// Blended with what's behind it so it has to be drawn after everything opaque
+    Blend,
+}
+
+/// Everything a glTF material describes. Textures are kept as they were loaded, the base
+/// color factor is already multiplied into the vertex colors of meshes from glTF files
+#[derive(Debug, Clone)]
+pub struct Material {
+    pub name: Option<String>,
+    pub base_color: [f32; 4],
+    pub base_color_texture: Option<RgbaImage>,
+    pub metallic: f32,
+    pub roughness: f32,
+    /// Metalness in the blue channel and roughness in the green channel
+    pub metallic_roughness_texture: Option<RgbaImage>,
+    pub normal_texture: Option<RgbaImage>,
+    pub normal_scale: f32,
+    pub occlusion_texture: Option<RgbaImage>,
+    pub occlusion_strength: f32,
+    pub emissive: [f32; 3],
+    pub emissive_texture: Option<RgbaImage>,
+    pub alpha_mode: AlphaMode,
+    pub double_sided: bool,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            name: None,
+            base_color: [1.0; 4],
+            base_color_texture: None,
+            metallic: 1.0,
+            roughness: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            emissive: [0.0; 3],
+            emissive_texture: None,
+            alpha_mode: AlphaMode::Opaque,
+            double_sided: false,
+        }
+    }
+}
+
+impl Material {
+    /// `node` and `primitive` are only used to say where a broken texture is
+    fn from_gltf(
+        material: &gltf::Material,
+        buffer_data: &[Vec<u8>],
+        node: usize,
+        primitive: usize,
+    ) -> Result<Self, ModelError> {
+        let texture = |texture: Option<gltf::Texture>| -> Result<Option<RgbaImage>, ModelError> {
+            texture
+                .map(|x| read_texture(&x, buffer_data))
+                .transpose()
+                .map_err(|e| match e {
+                    ModelError::Image { path, source, .. } => ModelError::Image {
+                        path,
+                        node,
+                        primitive,
+                        source,
+                    },
+                    e => e,
+                })
+        };
+        let pbr = material.pbr_metallic_roughness();
+        let normal = material.normal_texture();
+        let occlusion = material.occlusion_texture();
+        Ok(Self {
+            name: material.name().map(str::to_string),
+            base_color: pbr.base_color_factor(),
+            base_color_texture: texture(pbr.base_color_texture().map(|x| x.texture()))?,
+            metallic: pbr.metallic_factor(),
+            roughness: pbr.roughness_factor(),
+            metallic_roughness_texture: texture(
+                pbr.metallic_roughness_texture().map(|x| x.texture()),
+            )?,
+            normal_scale: normal.as_ref().map_or(1.0, |x| x.scale()),
+            normal_texture: texture(normal.map(|x| x.texture()))?,
+            occlusion_strength: occlusion.as_ref().map_or(1.0, |x| x.strength()),
+            occlusion_texture: texture(occlusion.map(|x| x.texture()))?,
+            emissive: material.emissive_factor(),
+            emissive_texture: texture(material.emissive_texture().map(|x| x.texture()))?,
+            alpha_mode: match material.alpha_mode() {
+                gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+                gltf::material::AlphaMode::Mask => {
+                    AlphaMode::Mask(material.alpha_cutoff().unwrap_or(0.5))
+                }
+                gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+            },
+            double_sided: material.double_sided(),
+        })
+    }
+}
+
+/// The node and primitive of image errors are filled in by `Material::from_gltf`
+fn read_texture(texture: &gltf::Texture, buffer_data: &[Vec<u8>]) -> Result<RgbaImage, ModelError> {
+    let image = match texture.source().source() {
+        gltf::image::Source::View { view, mime_type } => {
+            let buffer = view.buffer().index();
+            let parent_buffer_data = buffer_data.get(buffer).map_or(&[][..], |x| x.as_slice());
+            let end = view.offset().saturating_add(view.length());
+            let data =
+                parent_buffer_data
+                    .get(view.offset()..end)
+                    .ok_or(ModelError::BufferTooShort {
+                        path: None,
+                        buffer,
+                        expected: end,
+                        actual: parent_buffer_data.len(),
+                    })?;
+            let mime_type = mime_type.replace('/', ".");
+            image::load_from_memory_with_format(
+                data,
+                image::ImageFormat::from_path(mime_type).unwrap_or(image::ImageFormat::Png),
+            )
+        }
+        gltf::image::Source::Uri { uri, mime_type } => {
+            let (_, bytes) = read_data_uri(uri)?;
+            image::load_from_memory_with_format(
+                bytes.as_bytes(),
+                image::ImageFormat::from_path(mime_type.unwrap_or_default())
+                    .unwrap_or(image::ImageFormat::Png),
+            )
+        }
+    };
+    image
+        .map(|x| x.into_rgba8())
+        .map_err(|source| ModelError::Image {
+            path: None,
+            node: 0,
+            primitive: 0,
+            source,
+        })
+}
+
 #[derive(Debug, Default)]
 pub struct Mesh {
-    pub texture: Option<ImageBuffer<image::Rgba<u8>, Vec<u8>>>,
+    pub material: Material,
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u32>,
     pub aabb: Option<Aabb>,
     // pub transform: Transform,
 }
 
+impl From<vinox_formats::model::ModelMesh> for Mesh {
+    /// Voxel models are textured through an atlas so the mesh has no texture of its own
+    fn from(mesh: vinox_formats::model::ModelMesh) -> Self {
+        Self {
+            material: Material::default(),
+            vertices: mesh
+                .vertices
+                .into_iter()
+                .map(|x| Vertex {
+                    pos: x.pos,
+                    tex_coord: x.tex_coord,
+                    color: x.color,
+                    normals: x.normals,
+                    animation: [0.0; 4],
+                    joints: [0; 4],
+                    weights: [0.0; 4],
+                })
+                .collect(),
+            indices: mesh.indices,
+            aabb: None,
+        }
+    }
+}
+
 /// These models are for entities
 #[derive(Debug, Default)]
 pub struct Model {
     // pub id: u64,
-    /// The meshes that make up the model
+    /// Name of the top level node this model was loaded from, if it was loaded on its own
+    pub name: Option<String>,
+    /// Every node of the model, parents always come before their children
+    pub nodes: Vec<ModelNode>,
+    /// The meshes that make up the model, each relative to the node it belongs to
     pub meshes: Vec<Mesh>,
     /// The bounding box of the model
     pub aabb: Option<Aabb>,
+    /// The joints skinned meshes follow
+    pub skin: Option<Skin>,
     pub animations: Vec<Animation>,
 }
 
-#[derive(Debug, Default)]
-pub struct Animation {}
+/// Local transform of a node kept in parts so animations can replace each one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NodeTransform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Default for NodeTransform {
+    fn default() -> Self {
+        Self {
+            translation: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            scale: Vec3::ONE,
+        }
+    }
+}
+
+impl From<Transform> for NodeTransform {
+    fn from(transform: Transform) -> Self {
+        let (translation, rotation, scale) = transform.decomposed();
+        Self {
+            translation: translation.into(),
+            rotation: Quat::from_array(rotation),
+            scale: scale.into(),
+        }
+    }
+}
+
+impl NodeTransform {
+    pub fn to_matrix(self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ModelNode {
+    pub name: Option<String>,
+    /// Index of the parent in `Model::nodes`
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    /// Transform relative to the parent when no animation is playing
+    pub transform: NodeTransform,
+    /// Indices into `Model::meshes` of the meshes attached to this node
+    pub meshes: Vec<usize>,
+    /// Meshes of skinned nodes ignore the node's transform and follow the joints instead
+    pub skinned: bool,
+}
+
+/// Local transform of every node with nothing playing
+pub fn rest_pose(nodes: &[ModelNode]) -> Vec<NodeTransform> {
+    nodes.iter().map(|x| x.transform).collect()
+}
+
+/// Turn a local transform for every node into its transform relative to the model
+pub fn global_transforms(nodes: &[ModelNode], pose: &[NodeTransform]) -> Vec<Mat4> {
+    let mut globals: Vec<Mat4> = Vec::with_capacity(nodes.len());
+    for (node, transform) in nodes.iter().zip(pose) {
+        let local = transform.to_matrix();
+        let global = match node.parent {
+            Some(parent) => globals[parent] * local,
+            None => local,
+        };
+        globals.push(global);
+    }
+    globals
+}
+
+/// Which nodes skinned vertices follow
+#[derive(Debug, Clone, Default)]
+pub struct Skin {
+    /// Index into `Model::nodes` of each joint, `Vertex::joints` index into this
+    pub joints: Vec<usize>,
+    /// Moves vertices from model space into the space of each joint
+    pub inverse_bind_matrices: Vec<Mat4>,
+}
+
+impl Skin {
+    /// Matrix for every joint from the output of `global_transforms`
+    pub fn joint_matrices(&self, globals: &[Mat4]) -> Vec<Mat4> {
+        self.joints
+            .iter()
+            .zip(self.inverse_bind_matrices.iter())
+            // A pose for fewer nodes than the model has leaves the missing joints at rest
+            .map(|(joint, inverse_bind)| {
+                globals.get(*joint).copied().unwrap_or(Mat4::IDENTITY) * *inverse_bind
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    Linear,
+    Step,
+}
+
+#[derive(Debug, Clone)]
+pub enum Keyframes {
+    Translation(Vec<Vec3>),
+    Rotation(Vec<Quat>),
+    Scale(Vec<Vec3>),
+}
+
+/// Keyframes for one property of one node
+#[derive(Debug, Clone)]
+pub struct AnimationChannel {
+    /// Index into `Model::nodes`
+    pub node: usize,
+    pub interpolation: Interpolation,
+    /// Time in seconds of each keyframe, in order
+    pub times: Vec<f32>,
+    pub keyframes: Keyframes,
+}
+
+impl AnimationChannel {
+    /// The two keyframes around `time` and how far between them it is
+    fn keyframe(&self, time: f32) -> (usize, usize, f32) {
+        let last = self.times.len().saturating_sub(1);
+        let next = self.times.partition_point(|x| *x <= time);
+        if next == 0 {
+            return (0, 0, 0.0);
+        }
+        if next > last {
+            return (last, last, 0.0);
+        }
+        let (start, end) = (self.times[next - 1], self.times[next]);
+        let factor = match self.interpolation {
+            Interpolation::Linear if end > start => (time - start) / (end - start),
+            _ => 0.0,
+        };
+        (next - 1, next, factor)
+    }
+
+    fn sample(&self, time: f32, transform: &mut NodeTransform) {
+        if self.times.is_empty() {
+            return;
+        }
+        let (from, to, factor) = self.keyframe(time);
+        match &self.keyframes {
+            Keyframes::Translation(values) => {
+                transform.translation = values[from].lerp(values[to], factor)
+            }
+            Keyframes::Rotation(values) => {
+                transform.rotation = values[from].slerp(values[to], factor)
+            }
+            Keyframes::Scale(values) => transform.scale = values[from].lerp(values[to], factor),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Animation {
+    pub name: String,
+    /// Time of the last keyframe in seconds
+    pub duration: f32,
+    pub channels: Vec<AnimationChannel>,
+}
+
+impl Animation {
+    /// Overwrite the animated parts of `pose` with their values at `time` seconds
+    pub fn sample(&self, time: f32, pose: &mut [NodeTransform]) {
+        for channel in self.channels.iter() {
+            if let Some(transform) = pose.get_mut(channel.node) {
+                channel.sample(time, transform);
+            }
+        }
+    }
+}
+
+/// What scenes need to pose an animated model, kept when the model is handed to the renderer
+#[derive(Debug, Clone)]
+pub struct Rig {
+    pub nodes: Vec<ModelNode>,
+    pub animations: Vec<Animation>,
+}
+
+impl Rig {
+    /// Index into `nodes` of the first node called `name` ie a hand to attach held items to
+    pub fn find_node(&self, name: &str) -> Option<usize> {
+        self.nodes
+            .iter()
+            .position(|x| x.name.as_deref() == Some(name))
+    }
+}
+
+/// Plays one of a model's animations by name
+#[derive(Debug, Clone)]
+pub struct AnimationPlayer {
+    pub animation: String,
+    /// Seconds into the animation
+    pub time: f32,
+    pub speed: f32,
+    pub looping: bool,
+}
+
+impl AnimationPlayer {
+    pub fn new(animation: &str) -> Self {
+        Self {
+            animation: animation.to_string(),
+            time: 0.0,
+            speed: 1.0,
+            looping: true,
+        }
+    }
+
+    /// Switch to another animation from its start, keeps going if it is already playing
+    pub fn play(&mut self, animation: &str) {
+        if self.animation != animation {
+            self.animation = animation.to_string();
+            self.time = 0.0;
+        }
+    }
+
+    fn current<'a>(&self, animations: &'a [Animation]) -> Option<&'a Animation> {
+        animations.iter().find(|x| x.name == self.animation)
+    }
+
+    pub fn update(&mut self, delta: Duration, animations: &[Animation]) {
+        let Some(animation) = self.current(animations) else {
+            return;
+        };
+        self.time += delta.as_secs_f32() * self.speed;
+        if animation.duration <= 0.0 {
+            self.time = 0.0;
+        } else if self.looping {
+            self.time = self.time.rem_euclid(animation.duration);
+        } else {
+            self.time = self.time.clamp(0.0, animation.duration);
+        }
+    }
+
+    /// Transform of every node relative to the model for the current frame, the rest pose if
+    /// the animation doesn't exist
+    pub fn pose(&self, rig: &Rig) -> Vec<Mat4> {
+        let mut pose = rest_pose(&rig.nodes);
+        if let Some(animation) = self.current(&rig.animations) {
+            animation.sample(self.time, &mut pose);
+        }
+        global_transforms(&rig.nodes, &pose)
+    }
+}
 
 // impl std::hash::Hash for Model {
 //     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
@@ -148,112 +722,68 @@ pub struct Animation {}
 // }
 
 impl Model {
+    /// Read the meshes of a node returning their indices in `meshes`
     fn read_node(
         meshes: &mut Vec<Mesh>,
         node: &gltf::Node,
-        parent_transform: Mat4,
-        buffer_data: &mut Vec<Vec<u8>>,
+        buffer_data: &[Vec<u8>],
         // gfx: &mut GraphicsContext,
-    ) -> Result<(), String> {
-        let transform = parent_transform * transform_to_matrix(node.transform());
-        for child in node.children() {
-            Model::read_node(meshes, &child, transform, buffer_data)?;
-        }
+    ) -> Result<Vec<usize>, ModelError> {
+        let mut indices_read = Vec::new();
+        let skinned = node.skin().is_some();
         if let Some(mesh) = node.mesh() {
             for primitive in mesh.primitives() {
                 let reader =
                     primitive.reader(|buffer| Some(buffer_data[buffer.index()].as_slice()));
-                let texture_source = &primitive
-                    .material()
-                    .pbr_metallic_roughness()
-                    .base_color_texture()
-                    .map(|tex| tex.texture().source().source());
-                let image = if let Some(source) = texture_source {
-                    match source {
-                        gltf::image::Source::View { view, mime_type } => {
-                            let parent_buffer_data = &buffer_data[view.buffer().index()];
-                            let data =
-                                &parent_buffer_data[view.offset()..view.offset() + view.length()];
-                            let mime_type = mime_type.replace('/', ".");
-                            let dynamic_img = image::load_from_memory_with_format(
-                                data,
-                                image::ImageFormat::from_path(mime_type)
-                                    .unwrap_or(image::ImageFormat::Png),
-                            )
-                            .unwrap_or_default()
-                            .into_rgba8();
-                            dynamic_img
-                            // Image::from_pixels(
-                            //     gfx,
-                            //     dynamic_img.as_bytes(),
-                            //     wgpu::TextureFormat::Rgba8UnormSrgb,
-                            //     dynamic_img.width(),
-                            //     dynamic_img.height(),
-                            // )
-                        }
-                        gltf::image::Source::Uri { uri, mime_type } => {
-                            let uri = percent_encoding::percent_decode_str(uri)
-                                .decode_utf8()
-                                .unwrap();
-                            let uri = uri.as_ref();
-                            let bytes = match DataUri::parse(uri) {
-                                Ok(data_uri) => data_uri.decode()?,
-                                Err(()) => return Err("Failed to decode".to_string()),
-                            };
-                            let dynamic_img = image::load_from_memory_with_format(
-                                bytes.as_bytes(),
-                                image::ImageFormat::from_path(mime_type.unwrap_or_default())
-                                    .unwrap_or(image::ImageFormat::Png),
-                            )
-                            .unwrap_or_default()
-                            .into_rgba8();
-                            dynamic_img
-                            // Image::from_pixels(
-                            //     gfx,
-                            //     dynamic_img.as_bytes(),
-                            //     wgpu::TextureFormat::Rgba8UnormSrgb,
-                            //     dynamic_img.width(),
-                            //     dynamic_img.height(),
-                            // )
-                        }
-                    }
-                } else {
-                    ImageBuffer::default()
-                    // Image::from_color(gfx, 1, 1, Some(graphics::Color::WHITE))
-                };
+                let material = Material::from_gltf(
+                    &primitive.material(),
+                    buffer_data,
+                    node.index(),
+                    primitive.index(),
+                )?;
                 let mut vertices = Vec::default();
                 if let Some(vertices_read) = reader.read_positions() {
                     vertices = vertices_read
                         .map(|x| {
-                            let pos = Vec4::new(x[0], x[1], x[2], 1.);
-                            let res = transform * pos;
-                            let pos = Vec3::new(res.x / res.w, res.y / res.w, res.z / res.w);
                             Vertex::new(
-                                pos,
+                                Vec3::from(x),
                                 glam::Vec2::ZERO,
-                                Some([1.0, 1.0, 1.0, 0.0]),
+                                Some(material.base_color),
                                 Vec3::new(0.0, 0.0, 0.0),
                             )
                         })
                         .collect();
                 }
 
-                if let Some(tex_coords) = reader.read_tex_coords(0).map(|v| v.into_f32()) {
-                    let mut idx = 0;
-                    tex_coords.for_each(|tex_coord| {
-                        vertices[idx].tex_coord = tex_coord;
+                if let Some(colors) = reader.read_colors(0).map(|v| v.into_rgba_f32()) {
+                    for (vertex, color) in vertices.iter_mut().zip(colors) {
+                        vertex.color = (Vec4::from(vertex.color) * Vec4::from(color)).into();
+                    }
+                }
 
-                        idx += 1;
-                    });
+                if let Some(tex_coords) = reader.read_tex_coords(0).map(|v| v.into_f32()) {
+                    for (vertex, tex_coord) in vertices.iter_mut().zip(tex_coords) {
+                        vertex.tex_coord = tex_coord;
+                    }
                 }
 
                 if let Some(normals) = reader.read_normals() {
-                    let mut idx = 0;
-                    normals.for_each(|normals| {
-                        vertices[idx].normals = normals;
+                    for (vertex, normals) in vertices.iter_mut().zip(normals) {
+                        vertex.normals = normals;
+                    }
+                }
 
-                        idx += 1;
-                    });
+                if skinned {
+                    if let Some(joints) = reader.read_joints(0).map(|v| v.into_u16()) {
+                        for (vertex, joints) in vertices.iter_mut().zip(joints) {
+                            vertex.joints = joints;
+                        }
+                    }
+                    if let Some(weights) = reader.read_weights(0).map(|v| v.into_f32()) {
+                        for (vertex, weights) in vertices.iter_mut().zip(weights) {
+                            vertex.weights = weights;
+                        }
+                    }
                 }
 
                 let mut indices = Vec::new();
@@ -267,96 +797,306 @@ impl Model {
                 let mesh = Mesh {
                     vertices,
                     indices,
-                    texture: Some(image),
+                    material,
                     aabb: None,
                 };
 
+                indices_read.push(meshes.len());
                 meshes.push(mesh);
             }
         }
 
-        Ok(())
+        Ok(indices_read)
+    }
+
+    /// Add `node` and everything under it to `order` with each node's parent index
+    fn collect_nodes<'a>(
+        node: gltf::Node<'a>,
+        parent: Option<usize>,
+        order: &mut Vec<(gltf::Node<'a>, Option<usize>)>,
+    ) {
+        let index = order.len();
+        let children = node.children();
+        order.push((node, parent));
+        for child in children {
+            Model::collect_nodes(child, Some(index), order);
+        }
+    }
+
+    fn read_animation(
+        animation: &gltf::Animation,
+        node_indices: &HashMap<usize, usize>,
+        buffer_data: &[Vec<u8>],
+    ) -> Animation {
+        let mut channels = Vec::new();
+        for channel in animation.channels() {
+            let Some(node) = node_indices.get(&channel.target().node().index()).copied() else {
+                continue;
+            };
+            let reader = channel.reader(|buffer| Some(buffer_data[buffer.index()].as_slice()));
+            let (Some(times), Some(outputs)) = (reader.read_inputs(), reader.read_outputs()) else {
+                continue;
+            };
+            let times: Vec<f32> = times.collect();
+            let (interpolation, cubic) = match channel.sampler().interpolation() {
+                gltf::animation::Interpolation::Linear => (Interpolation::Linear, false),
+                gltf::animation::Interpolation::Step => (Interpolation::Step, false),
+                // Tangents are dropped and the values are blended linearly
+                gltf::animation::Interpolation::CubicSpline => (Interpolation::Linear, true),
+            };
+            let keyframes = match outputs {
+                ReadOutputs::Translations(x) => {
+                    Keyframes::Translation(keyframe_values(x.map(Vec3::from), cubic))
+                }
+                ReadOutputs::Rotations(x) => Keyframes::Rotation(keyframe_values(
+                    x.into_f32().map(|x| Quat::from_array(x).normalize()),
+                    cubic,
+                )),
+                ReadOutputs::Scales(x) => {
+                    Keyframes::Scale(keyframe_values(x.map(Vec3::from), cubic))
+                }
+                ReadOutputs::MorphTargetWeights(_) => continue,
+            };
+            let count = match &keyframes {
+                Keyframes::Translation(x) | Keyframes::Scale(x) => x.len(),
+                Keyframes::Rotation(x) => x.len(),
+            };
+            if count != times.len() {
+                continue;
+            }
+            channels.push(AnimationChannel {
+                node,
+                interpolation,
+                times,
+                keyframes,
+            });
+        }
+        Animation {
+            name: animation
+                .name()
+                .map(str::to_string)
+                .unwrap_or_else(|| animation.index().to_string()),
+            duration: channels
+                .iter()
+                .filter_map(|x| x.times.last().copied())
+                .fold(0.0, f32::max),
+            channels,
+        }
+    }
+
+    fn open_gltf(path: &Path) -> Result<gltf::Gltf, ModelError> {
+        let file = File::open(path).map_err(|source| ModelError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        gltf::Gltf::from_reader(BufReader::new(file)).map_err(|source| ModelError::Gltf {
+            path: Some(path.to_path_buf()),
+            source,
+        })
     }
 
     /// Load gltf from path
     pub fn from_gltf(
         // gfx: &mut impl HasMut<GraphicsContext>,
         path: impl AsRef<Path>,
-    ) -> Result<Self, String> {
+    ) -> Result<Self, ModelError> {
         // let gfx = gfx.retrieve_mut();
         // let file = gfx.fs.open(path)?;
-        let file = BufReader::new(File::open(&path).unwrap());
-        if let Ok(gltf) = gltf::Gltf::from_reader(file) {
-            return Model::from_raw_gltf(gltf);
-        }
-        Err("Failed to load gltf file".to_string())
+        let path = path.as_ref();
+        Model::from_raw_gltf(Model::open_gltf(path)?).map_err(|e| e.with_path(path))
+    }
+
+    /// Load gltf from path as one model per top level node, see `from_raw_gltf_nodes`
+    pub fn from_gltf_nodes(path: impl AsRef<Path>) -> Result<Vec<Self>, ModelError> {
+        let path = path.as_ref();
+        Model::from_raw_gltf_nodes(Model::open_gltf(path)?).map_err(|e| e.with_path(path))
     }
 
     /// Load gltf from bytes
     pub fn from_gltf_bytes(
         // gfx: &mut impl HasMut<GraphicsContext>,
         bytes: &[u8],
-    ) -> Result<Self, String> {
-        if let Ok(gltf) = gltf::Gltf::from_slice(bytes) {
-            return Model::from_raw_gltf(gltf);
-        }
-        Err("Invalid gltf bytes".to_string())
+    ) -> Result<Self, ModelError> {
+        let gltf = gltf::Gltf::from_slice(bytes)
+            .map_err(|source| ModelError::Gltf { path: None, source })?;
+        Model::from_raw_gltf(gltf)
     }
 
-    /// Load gltf file from a GLTF. Keep in mind right now the whole gltf will be loaded as one model. So multiple models won't be made in more complex scenes. This either has to be implemneted yourself or possibly will come later.
+    /// Load every scene of a GLTF as one model
     pub fn from_raw_gltf(
         // gfx: &mut impl HasMut<GraphicsContext>,
         gltf: gltf::Gltf,
-    ) -> Result<Self, String> {
+    ) -> Result<Self, ModelError> {
         // let gfx = gfx.retrieve_mut();
+        let buffer_data = Model::read_buffers(&gltf)?;
+        let roots: Vec<gltf::Node> = gltf.scenes().flat_map(|x| x.nodes()).collect();
+        Model::from_nodes(&gltf, &buffer_data, &roots, None)
+    }
+
+    /// Load each top level node of every scene as its own model named after the node. Lets
+    /// one file hold several props. Animations only keep the channels of their model's nodes
+    pub fn from_raw_gltf_nodes(gltf: gltf::Gltf) -> Result<Vec<Self>, ModelError> {
+        let buffer_data = Model::read_buffers(&gltf)?;
+        gltf.scenes()
+            .flat_map(|x| x.nodes())
+            .map(|root| {
+                let name = root.name().map(str::to_string);
+                Model::from_nodes(&gltf, &buffer_data, &[root], name)
+            })
+            .collect()
+    }
+
+    fn read_buffers(gltf: &gltf::Gltf) -> Result<Vec<Vec<u8>>, ModelError> {
         const VALID_MIME_TYPES: &[&str] = &["application/octet-stream", "application/gltf-buffer"];
-        let mut meshes = Vec::default();
         let mut buffer_data = Vec::new();
         for buffer in gltf.buffers() {
-            match buffer.source() {
+            let bytes = match buffer.source() {
                 gltf::buffer::Source::Uri(uri) => {
-                    let uri = percent_encoding::percent_decode_str(uri)
-                        .decode_utf8()
-                        .unwrap();
-                    let uri = uri.as_ref();
-                    let buffer_bytes = match DataUri::parse(uri) {
-                        Ok(data_uri) if VALID_MIME_TYPES.contains(&data_uri.mime_type) => {
-                            data_uri.decode()?
-                        }
-                        Ok(_) => return Err("Buffer Format Unsupported".to_string()),
-                        Err(()) => return Err("Failed to decode".to_string()),
-                    };
-                    buffer_data.push(buffer_bytes);
+                    let (mime_type, buffer_bytes) = read_data_uri(uri)?;
+                    if !VALID_MIME_TYPES.contains(&mime_type.as_str()) {
+                        return Err(ModelError::UnsupportedBuffer {
+                            path: None,
+                            buffer: buffer.index(),
+                            mime_type,
+                        });
+                    }
+                    buffer_bytes
                 }
                 gltf::buffer::Source::Bin => {
                     if let Some(blob) = gltf.blob.as_deref() {
-                        buffer_data.push(blob.into());
+                        blob.into()
                     } else {
-                        return Err("MissingBlob".to_string());
+                        return Err(ModelError::MissingBlob { path: None });
                     }
                 }
+            };
+            // Views and accessors are only checked against the length the file claims
+            if bytes.len() < buffer.length() {
+                return Err(ModelError::BufferTooShort {
+                    path: None,
+                    buffer: buffer.index(),
+                    expected: buffer.length(),
+                    actual: bytes.len(),
+                });
             }
+            buffer_data.push(bytes);
         }
-        for scene in gltf.scenes() {
-            for node in scene.nodes() {
-                Model::read_node(&mut meshes, &node, Mat4::IDENTITY, &mut buffer_data)?;
-            }
+        Ok(buffer_data)
+    }
+
+    /// Build a model out of `roots` and everything under them
+    fn from_nodes(
+        gltf: &gltf::Gltf,
+        buffer_data: &[Vec<u8>],
+        roots: &[gltf::Node],
+        name: Option<String>,
+    ) -> Result<Self, ModelError> {
+        let mut order = Vec::new();
+        for root in roots {
+            Model::collect_nodes(root.clone(), None, &mut order);
         }
+        let node_indices: HashMap<usize, usize> = order
+            .iter()
+            .enumerate()
+            .map(|(index, (node, _))| (node.index(), index))
+            .collect();
+
+        let mut meshes = Vec::new();
+        let mut nodes: Vec<ModelNode> = Vec::with_capacity(order.len());
+        for (node, parent) in order.iter() {
+            nodes.push(ModelNode {
+                name: node.name().map(str::to_string),
+                parent: *parent,
+                children: node
+                    .children()
+                    .filter_map(|x| node_indices.get(&x.index()).copied())
+                    .collect(),
+                transform: node.transform().into(),
+                meshes: Model::read_node(&mut meshes, node, buffer_data)?,
+                skinned: node.skin().is_some(),
+            });
+        }
+
+        // The first skin that belongs entirely to these nodes
+        let skin = gltf.skins().find_map(|skin| {
+            let joints = skin
+                .joints()
+                .map(|x| node_indices.get(&x.index()).copied())
+                .collect::<Option<Vec<_>>>()?;
+            let inverse_bind_matrices = skin
+                .reader(|buffer| Some(buffer_data[buffer.index()].as_slice()))
+                .read_inverse_bind_matrices()
+                .map(|x| x.map(|x| Mat4::from_cols_array_2d(&x)).collect())
+                .unwrap_or_else(|| vec![Mat4::IDENTITY; joints.len()]);
+            Some(Skin {
+                joints,
+                inverse_bind_matrices,
+            })
+        });
+        let animations = gltf
+            .animations()
+            .map(|x| Model::read_animation(&x, &node_indices, buffer_data))
+            .filter(|x| !x.channels.is_empty())
+            .collect();
+
         let mut model = Model {
+            name,
+            nodes,
             meshes,
             aabb: None,
-            animations: vec![],
+            skin,
+            animations,
         };
         model.calculate_aabb();
 
         Ok(model)
     }
-    /// Generate an aabb for this Model
+
+    /// Local transform of every node with nothing playing
+    pub fn rest_pose(&self) -> Vec<NodeTransform> {
+        rest_pose(&self.nodes)
+    }
+
+    pub fn global_transforms(&self, pose: &[NodeTransform]) -> Vec<Mat4> {
+        global_transforms(&self.nodes, pose)
+    }
+
+    /// Index into `nodes` of the first node called `name`
+    pub fn find_node(&self, name: &str) -> Option<usize> {
+        self.nodes
+            .iter()
+            .position(|x| x.name.as_deref() == Some(name))
+    }
+
+    /// Vertices of every mesh moved into model space by a pose from `global_transforms`
+    pub fn posed_vertices(&self, globals: &[Mat4]) -> Vec<Vec<Vertex>> {
+        let joint_matrices = self
+            .skin
+            .as_ref()
+            .map(|x| x.joint_matrices(globals))
+            .unwrap_or_default();
+        let mut posed: Vec<Vec<Vertex>> = self.meshes.iter().map(|x| x.vertices.clone()).collect();
+        for (node, global) in self.nodes.iter().zip(globals) {
+            for mesh in node.meshes.iter() {
+                for vertex in posed[*mesh].iter_mut() {
+                    *vertex = if node.skinned {
+                        vertex.skinned(&joint_matrices)
+                    } else {
+                        vertex.transformed(*global)
+                    };
+                }
+            }
+        }
+        posed
+    }
+
+    /// Generate an aabb for this Model in its rest pose
     pub fn calculate_aabb(&mut self) {
         let mut minimum = Vec3::MAX;
         let mut maximum = Vec3::MIN;
-        for mesh in self.meshes.iter() {
-            for p in mesh.vertices.iter() {
+        let globals = self.global_transforms(&self.rest_pose());
+        for vertices in self.posed_vertices(&globals) {
+            for p in vertices.iter() {
                 minimum = minimum.min(Vec3::from_array(p.pos));
                 maximum = maximum.max(Vec3::from_array(p.pos));
             }
@@ -374,3 +1114,281 @@ impl Model {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pack a json document and binary chunk into a glb file
+    fn glb(json: &str, bin: &[u8]) -> Vec<u8> {
+        let mut json = json.as_bytes().to_vec();
+        json.resize(json.len().next_multiple_of(4), b' ');
+        let mut bin = bin.to_vec();
+        bin.resize(bin.len().next_multiple_of(4), 0);
+
+        let length = 12 + 8 + json.len() + 8 + bin.len();
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"glTF");
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        bytes.extend_from_slice(&(length as u32).to_le_bytes());
+        bytes.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(b"JSON");
+        bytes.extend_from_slice(&json);
+        bytes.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(b"BIN\0");
+        bytes.extend_from_slice(&bin);
+        bytes
+    }
+
+    #[test]
+    fn truncated_glb() {
+        let bytes = include_bytes!("../../assets/player.glb");
+        assert!(Model::from_gltf_bytes(bytes).is_ok());
+        for length in [12, bytes.len() / 2, bytes.len() - 1] {
+            assert!(Model::from_gltf_bytes(&bytes[..length]).is_err());
+        }
+
+        // The file is intact but its binary chunk is shorter than the buffer claims
+        let bytes = glb(
+            r#"{"asset":{"version":"2.0"},"buffers":[{"byteLength":64}]}"#,
+            &[0; 8],
+        );
+        assert!(matches!(
+            Model::from_gltf_bytes(&bytes),
+            Err(ModelError::BufferTooShort {
+                buffer: 0,
+                expected: 64,
+                actual: 8,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn bad_data_uri() {
+        let gltf = |uri: &str, length: usize| {
+            format!(
+                r#"{{"asset":{{"version":"2.0"}},"buffers":[{{"byteLength":{length},"uri":"{uri}"}}]}}"#
+            )
+        };
+        let load = |json: String| Model::from_gltf_bytes(json.as_bytes());
+
+        assert!(matches!(
+            load(gltf("data:application/octet-stream;base64,@@@@", 3)),
+            Err(ModelError::InvalidUri { .. })
+        ));
+        assert!(matches!(
+            load(gltf("textures/skin.png", 3)),
+            Err(ModelError::InvalidUri { .. })
+        ));
+        // "AAAA" is only 3 bytes
+        assert!(matches!(
+            load(gltf("data:application/octet-stream;base64,AAAA", 16)),
+            Err(ModelError::BufferTooShort {
+                expected: 16,
+                actual: 3,
+                ..
+            })
+        ));
+        assert!(load(gltf("data:application/octet-stream;base64,AAAA", 3)).is_ok());
+    }
+
+    #[test]
+    fn texture_view_past_buffer() {
+        let json = r#"{
+            "asset": {"version": "2.0"},
+            "buffers": [{"byteLength": 16}],
+            "bufferViews": [
+                {"buffer": 0, "byteOffset": 4, "byteLength": 100},
+                {"buffer": 0, "byteLength": 12}
+            ],
+            "images": [{"bufferView": 0, "mimeType": "image/png"}],
+            "textures": [{"source": 0}],
+            "materials": [{"pbrMetallicRoughness": {"baseColorTexture": {"index": 0}}}],
+            "accessors": [{
+                "bufferView": 1, "componentType": 5126, "count": 1, "type": "VEC3",
+                "min": [0, 0, 0], "max": [0, 0, 0]
+            }],
+            "meshes": [{"primitives": [{"attributes": {"POSITION": 0}, "material": 0}]}],
+            "nodes": [{"mesh": 0}],
+            "scenes": [{"nodes": [0]}]
+        }"#;
+        assert!(matches!(
+            Model::from_gltf_bytes(&glb(json, &[0; 16])),
+            Err(ModelError::BufferTooShort {
+                buffer: 0,
+                expected: 104,
+                actual: 16,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn joints_outside_pose() {
+        let skin = Skin {
+            joints: vec![0, 5],
+            inverse_bind_matrices: vec![Mat4::IDENTITY; 2],
+        };
+        let globals = [Mat4::from_translation(Vec3::X)];
+        assert_eq!(
+            skin.joint_matrices(&globals),
+            vec![globals[0], Mat4::IDENTITY]
+        );
+    }
+
+    fn translation(
+        interpolation: Interpolation,
+        times: Vec<f32>,
+        values: Vec<Vec3>,
+    ) -> AnimationChannel {
+        AnimationChannel {
+            node: 0,
+            interpolation,
+            times,
+            keyframes: Keyframes::Translation(values),
+        }
+    }
+
+    fn sample(channel: &AnimationChannel, time: f32) -> NodeTransform {
+        let mut transform = NodeTransform::default();
+        channel.sample(time, &mut transform);
+        transform
+    }
+
+    #[test]
+    fn linear_and_step() {
+        let values = vec![Vec3::ZERO, Vec3::X * 2.0, Vec3::X * 6.0];
+        let linear = translation(Interpolation::Linear, vec![0.0, 1.0, 3.0], values.clone());
+        assert_eq!(sample(&linear, 0.5).translation, Vec3::X);
+        assert_eq!(sample(&linear, 1.0).translation, Vec3::X * 2.0);
+        assert_eq!(sample(&linear, 2.0).translation, Vec3::X * 4.0);
+
+        let step = translation(Interpolation::Step, vec![0.0, 1.0, 3.0], values);
+        assert_eq!(sample(&step, 0.5).translation, Vec3::ZERO);
+        assert_eq!(sample(&step, 1.0).translation, Vec3::X * 2.0);
+        assert_eq!(sample(&step, 2.9).translation, Vec3::X * 2.0);
+
+        let rotation = AnimationChannel {
+            node: 0,
+            interpolation: Interpolation::Linear,
+            times: vec![0.0, 1.0],
+            keyframes: Keyframes::Rotation(vec![
+                Quat::IDENTITY,
+                Quat::from_rotation_y(90f32.to_radians()),
+            ]),
+        };
+        assert!(sample(&rotation, 0.5)
+            .rotation
+            .abs_diff_eq(Quat::from_rotation_y(45f32.to_radians()), 1e-5));
+    }
+
+    #[test]
+    fn clamps_outside_keyframes() {
+        let channel = translation(
+            Interpolation::Linear,
+            vec![1.0, 2.0],
+            vec![Vec3::Y, Vec3::Y * 3.0],
+        );
+        assert_eq!(sample(&channel, 0.0).translation, Vec3::Y);
+        assert_eq!(sample(&channel, -5.0).translation, Vec3::Y);
+        assert_eq!(sample(&channel, 2.0).translation, Vec3::Y * 3.0);
+        assert_eq!(sample(&channel, 10.0).translation, Vec3::Y * 3.0);
+
+        // No keyframes leaves the transform alone
+        let empty = translation(Interpolation::Linear, Vec::new(), Vec::new());
+        assert_eq!(sample(&empty, 1.0), NodeTransform::default());
+    }
+
+    /// A root at x 1 with a child one above it, the root moves 4 along z over 2 seconds
+    fn rig() -> Rig {
+        let node = |parent, translation| ModelNode {
+            name: None,
+            parent,
+            children: Vec::new(),
+            transform: NodeTransform {
+                translation,
+                ..Default::default()
+            },
+            meshes: Vec::new(),
+            skinned: false,
+        };
+        let mut channel = translation(
+            Interpolation::Linear,
+            vec![0.0, 2.0],
+            vec![Vec3::X, Vec3::X + Vec3::Z * 4.0],
+        );
+        channel.node = 0;
+        Rig {
+            nodes: vec![node(None, Vec3::X), node(Some(0), Vec3::Y)],
+            animations: vec![Animation {
+                name: "move".to_string(),
+                duration: 2.0,
+                channels: vec![
+                    channel,
+                    // Channels for nodes the pose doesn't have are skipped
+                    AnimationChannel {
+                        node: 9,
+                        ..translation(Interpolation::Step, vec![0.0], vec![Vec3::ONE])
+                    },
+                ],
+            }],
+        }
+    }
+
+    #[test]
+    fn player_loops_and_clamps() {
+        let rig = rig();
+        let mut player = AnimationPlayer::new("move");
+        player.update(Duration::from_secs_f32(0.5), &rig.animations);
+        assert_eq!(player.time, 0.5);
+        player.update(Duration::from_secs_f32(2.0), &rig.animations);
+        assert_eq!(player.time, 0.5);
+        let globals = player.pose(&rig);
+        assert!(globals[0].abs_diff_eq(Mat4::from_translation(Vec3::X + Vec3::Z), 1e-5));
+        // Children follow their parent
+        assert!(globals[1].abs_diff_eq(Mat4::from_translation(Vec3::ONE), 1e-5));
+
+        player.looping = false;
+        player.update(Duration::from_secs(5), &rig.animations);
+        assert_eq!(player.time, 2.0);
+
+        // Playing the same animation keeps going, another one starts over
+        player.play("move");
+        assert_eq!(player.time, 2.0);
+        player.play("missing");
+        assert_eq!(player.time, 0.0);
+        player.update(Duration::from_secs(1), &rig.animations);
+        assert_eq!(player.time, 0.0);
+        let rest = global_transforms(&rig.nodes, &rest_pose(&rig.nodes));
+        assert_eq!(player.pose(&rig), rest);
+    }
+
+    #[test]
+    fn joint_matrices_from_inverse_bind() {
+        let rig = rig();
+        let rest = global_transforms(&rig.nodes, &rest_pose(&rig.nodes));
+        let skin = Skin {
+            joints: vec![0, 1],
+            inverse_bind_matrices: rest.iter().map(|x| x.inverse()).collect(),
+        };
+        // At rest every joint matrix leaves vertices where they are
+        for matrix in skin.joint_matrices(&rest) {
+            assert!(matrix.abs_diff_eq(Mat4::IDENTITY, 1e-5));
+        }
+
+        let mut player = AnimationPlayer::new("move");
+        player.update(Duration::from_secs(1), &rig.animations);
+        let matrices = skin.joint_matrices(&player.pose(&rig));
+        for matrix in &matrices {
+            assert!(matrix.abs_diff_eq(Mat4::from_translation(Vec3::Z * 2.0), 1e-5));
+        }
+
+        let mut vertex = Vertex::new(Vec3::new(1.0, 1.5, 0.0), Vec2::ZERO, None, Vec3::Y);
+        vertex.joints = [1, 0, 0, 0];
+        vertex.weights = [1.0, 0.0, 0.0, 0.0];
+        let skinned = vertex.skinned(&matrices);
+        assert!(Vec3::from(skinned.pos).abs_diff_eq(Vec3::new(1.0, 1.5, 2.0), 1e-5));
+        assert_eq!(skinned.normals, [0.0, 1.0, 0.0]);
+    }
+}
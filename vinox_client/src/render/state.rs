@@ -1,9 +1,13 @@
-use std::marker::PhantomData;
+use std::{collections::HashMap, marker::PhantomData, time::Duration};
 
 use glam::*;
 use image::ImageBuffer;
+use vinox_common::prelude::ChunkPos;
 
-use super::model::Model;
+use super::{
+    atlas::TextureAnimation,
+    model::{Mesh, Model, Rig},
+};
 
 pub trait ConvertModel<S> {
     fn to_mesh(model: Model, state: &mut S) -> Self;
@@ -12,8 +16,15 @@ pub trait ConvertModel<S> {
 #[derive(Debug)]
 pub struct AssetRegistry<S, M: ConvertModel<S>> {
     pub models: Vec<M>,
-    pub block_atlas: Option<ImageBuffer<image::Rgba<u8>, Vec<u8>>>, // Animated voxels will be done by passing in a framecount and time value to the shader to offset the uvs. This means all animations are the same speed could pass a speed attribute as well
+    /// Animated voxels get their frame count and speed per vertex and the shader offsets the
+    /// uvs by `RenderState::time`
+    pub block_atlas: Option<ImageBuffer<image::Rgba<u8>, Vec<u8>>>,
     pub entity_atlas: Option<ImageBuffer<image::Rgba<u8>, Vec<u8>>>,
+    /// Where each block texture is in `block_atlas` as `[x, y, width, height]` from 0 to 1
+    pub block_uvs: HashMap<String, [f32; 4]>,
+    pub block_animations: HashMap<String, TextureAnimation>,
+    /// Nodes and animations of each animated model by id
+    pub rigs: HashMap<u64, Rig>,
     _phantom_data: PhantomData<S>,
 }
 
@@ -23,23 +34,49 @@ impl<S, M: ConvertModel<S>> Default for AssetRegistry<S, M> {
             models: Vec::with_capacity(100),
             block_atlas: None,
             entity_atlas: None,
+            block_uvs: HashMap::new(),
+            block_animations: HashMap::new(),
+            rigs: HashMap::new(),
             _phantom_data: PhantomData::default(),
         }
     }
 }
 
-impl<S, M: ConvertModel<S>> AssetRegistry<S, M> {}
+impl<S, M: ConvertModel<S>> AssetRegistry<S, M> {
+    /// Convert a model for the renderer, returning the id to draw it with
+    pub fn add_model(&mut self, model: Model, state: &mut S) -> u64 {
+        let id = self.models.len() as u64;
+        if !model.animations.is_empty() {
+            self.rigs.insert(
+                id,
+                Rig {
+                    nodes: model.nodes.clone(),
+                    animations: model.animations.clone(),
+                },
+            );
+        }
+        self.models.push(M::to_mesh(model, state));
+        id
+    }
+}
 
 #[derive(Default, Debug)]
 pub struct Draw {
     pub model_id: u64,
+    /// Transform of every node from `AnimationPlayer::pose`, `None` draws the model as loaded
+    pub pose: Option<Vec<Mat4>>,
 }
 
 #[derive(Debug)]
 pub struct RenderState<S, M: ConvertModel<S>> {
     pub camera: Camera,
     pub draws: Vec<Draw>,
+    /// Chunk meshes for the renderer to upload, `None` drops the chunk's mesh. Chunk meshes
+    /// stay around between frames unlike `draws`
+    pub chunk_uploads: Vec<(ChunkPos, Option<Mesh>)>,
     pub asset_registry: AssetRegistry<S, M>,
+    /// Time since the client started, drives texture animations
+    pub time: Duration,
 }
 
 impl<S, M: ConvertModel<S>> Default for RenderState<S, M> {
@@ -47,7 +84,9 @@ impl<S, M: ConvertModel<S>> Default for RenderState<S, M> {
         Self {
             camera: Camera::default(),
             draws: Vec::default(),
+            chunk_uploads: Vec::default(),
             asset_registry: AssetRegistry::default(),
+            time: Duration::ZERO,
         }
     }
 }
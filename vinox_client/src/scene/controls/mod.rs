@@ -0,0 +1,147 @@
+use std::{marker::PhantomData, path::Path};
+
+use crate::{
+    game::{Context, SharedState},
+    input::{Action, Binding, Bindings, MouseButton, BINDINGS_PATH},
+    render::state::ConvertModel,
+};
+
+use super::{Scene, SceneEvents, SceneSwitch};
+
+/// Lists every action with what triggers it and lets the player rebind them, opened from
+/// the pause menu
+pub struct ControlsScene<S, M: ConvertModel<S>> {
+    _phantom: PhantomData<(S, M)>,
+    /// Action that gets the next button pressed
+    waiting: Option<Action>,
+    back: bool,
+    /// Why the bindings couldn't be saved
+    error: Option<String>,
+}
+
+impl<S, M: ConvertModel<S>> ControlsScene<S, M> {
+    pub fn new() -> Self {
+        Self {
+            _phantom: PhantomData::default(),
+            waiting: None,
+            back: false,
+            error: None,
+        }
+    }
+
+    fn save(&mut self, bindings: &Bindings) {
+        self.error = bindings
+            .save(Path::new(BINDINGS_PATH))
+            .err()
+            .map(|e| format!("Failed to save bindings: {e}"));
+    }
+}
+
+fn describe(binding: &Binding) -> String {
+    match binding {
+        Binding::Key(key) => key.clone(),
+        Binding::Mouse(MouseButton::Other(x)) => format!("Mouse {x}"),
+        Binding::Mouse(button) => format!("Mouse {button:?}"),
+        Binding::Gamepad(button) => format!("Gamepad {button}"),
+    }
+}
+
+impl<S: 'static, M: ConvertModel<S> + 'static> Scene<SharedState, SceneEvents, Context<S, M>>
+    for ControlsScene<S, M>
+{
+    fn update(
+        &mut self,
+        gameworld: &mut SharedState,
+        ctx: &mut Context<S, M>,
+    ) -> super::SceneSwitch<SharedState, SceneEvents, Context<S, M>> {
+        if let Some(action) = self.waiting {
+            match ctx.input_state.pressed_binding().cloned() {
+                // Escape always backs out so a rebind can't get stuck
+                Some(Binding::Key(key)) if key == "Escape" => self.waiting = None,
+                Some(binding) => {
+                    self.waiting = None;
+                    ctx.input_state.bindings.rebind(action, binding);
+                    self.save(&ctx.input_state.bindings);
+                }
+                None => {}
+            }
+            return SceneSwitch::None;
+        }
+        if self.back || ctx.input_state.pressed(Action::Pause) {
+            return SceneSwitch::pop();
+        }
+        SceneSwitch::None
+    }
+
+    fn render(
+        &mut self,
+        gameworld: &mut SharedState,
+        ctx: &mut Context<S, M>,
+    ) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn tick(&mut self, gameworld: &mut SharedState, ctx: &mut Context<S, M>) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn input(
+        &mut self,
+        gameworld: &mut SharedState,
+        event: SceneEvents,
+        ctx: &mut Context<S, M>,
+        started: bool,
+    ) {
+    }
+
+    fn ui(&mut self, gameworld: &mut SharedState, ui: &mut egui::Context, ctx: &mut Context<S, M>) {
+        egui::Window::new("Controls")
+            .collapsible(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ui, |ui| {
+                if let Some(error) = &self.error {
+                    ui.colored_label(egui::Color32::RED, error.as_str());
+                }
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    egui::Grid::new("Bindings").striped(true).show(ui, |ui| {
+                        for (action, bindings) in &ctx.input_state.bindings.actions {
+                            ui.label(format!("{action:?}"));
+                            if self.waiting == Some(*action) {
+                                ui.label("Press a button, Escape to cancel");
+                            } else {
+                                let names: Vec<_> = bindings.iter().map(describe).collect();
+                                ui.label(names.join(", "));
+                            }
+                            if ui.button("Rebind").clicked() {
+                                self.waiting = Some(*action);
+                            }
+                            ui.end_row();
+                        }
+                    });
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("Reset to defaults").clicked() {
+                        self.waiting = None;
+                        let defaults = Bindings::default();
+                        ctx.input_state.bindings.actions = defaults.actions;
+                        self.save(&ctx.input_state.bindings);
+                    }
+                    if ui.button("Back").clicked() {
+                        self.back = true;
+                    }
+                });
+            });
+    }
+
+    fn name(&self) -> &str {
+        "Controls"
+    }
+
+    fn draw_previous(&self) -> bool {
+        true
+    }
+
+    fn tick_previous(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,76 @@
+use std::marker::PhantomData;
+
+use crate::{
+    game::{Context, SharedState},
+    render::state::ConvertModel,
+};
+
+use super::{menu::MenuScene, Scene, SceneEvents, SceneSwitch};
+
+/// Shown in place of the whole stack when a scene fails instead of crashing the client
+pub struct ErrorScene<S, M: ConvertModel<S>> {
+    _phantom: PhantomData<(S, M)>,
+    message: String,
+    switch: bool,
+}
+
+impl<S, M: ConvertModel<S>> ErrorScene<S, M> {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            _phantom: PhantomData::default(),
+            message: message.into(),
+            switch: false,
+        }
+    }
+}
+
+impl<S: 'static, M: ConvertModel<S> + 'static> Scene<SharedState, SceneEvents, Context<S, M>>
+    for ErrorScene<S, M>
+{
+    fn update(
+        &mut self,
+        gameworld: &mut SharedState,
+        ctx: &mut Context<S, M>,
+    ) -> super::SceneSwitch<SharedState, SceneEvents, Context<S, M>> {
+        if self.switch {
+            SceneSwitch::replace(MenuScene::new())
+        } else {
+            SceneSwitch::None
+        }
+    }
+
+    fn render(
+        &mut self,
+        gameworld: &mut SharedState,
+        ctx: &mut Context<S, M>,
+    ) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn tick(&mut self, gameworld: &mut SharedState, ctx: &mut Context<S, M>) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn input(
+        &mut self,
+        gameworld: &mut SharedState,
+        event: SceneEvents,
+        ctx: &mut Context<S, M>,
+        started: bool,
+    ) {
+    }
+
+    fn ui(&mut self, gameworld: &mut SharedState, ui: &mut egui::Context, ctx: &mut Context<S, M>) {
+        egui::CentralPanel::default().show(ui, |ui| {
+            ui.heading("Something went wrong");
+            ui.label(&self.message);
+            if ui.button("Menu").clicked() {
+                self.switch = true;
+            }
+        });
+    }
+
+    fn name(&self) -> &str {
+        "Error"
+    }
+}
@@ -1,15 +1,43 @@
-use std::marker::PhantomData;
+use std::{collections::HashMap, marker::PhantomData, time::Duration};
+
+use glam::{Vec2, Vec3};
+use vinox_common::prelude::{Position, ServerMessage};
 
 use crate::{
     game::{Context, SharedState},
-    render::state::ConvertModel,
+    input::Action,
+    render::{
+        model::AnimationPlayer,
+        state::{ConvertModel, Draw},
+    },
 };
 
-use super::{menu::MenuScene, Scene, SceneEvents, SceneSwitch};
+use super::{menu::MenuScene, pause::PauseScene, Scene, SceneEvents, SceneSwitch};
+
+/// Chunk meshes handed to the renderer each frame. Uploading is the part that can't be moved
+/// off the render thread so spread bursts out over a few frames
+const MAX_UPLOADS_PER_FRAME: usize = 8;
+/// Chat lines kept for the chat box, older ones are dropped
+const CHAT_HISTORY: usize = 100;
+
+/// Another player on the server as of their last update
+pub struct RemotePlayer {
+    pub username: String,
+    pub position: Position,
+    pub yaw: f32,
+    pub pitch: f32,
+}
 
 pub struct GameScene<S, M: ConvertModel<S>> {
     _phantom: PhantomData<(S, M)>,
     switch: bool,
+    player_animation: AnimationPlayer,
+    /// Our id on the server, set by the welcome message
+    client_id: Option<u64>,
+    players: HashMap<u64, RemotePlayer>,
+    chat: Vec<String>,
+    /// Reason from a disconnect message, normally caught by `NetworkState` before we see it
+    kicked: Option<String>,
 }
 
 impl<S, M: ConvertModel<S>> GameScene<S, M> {
@@ -17,6 +45,71 @@ impl<S, M: ConvertModel<S>> GameScene<S, M> {
         Self {
             _phantom: PhantomData::default(),
             switch: false,
+            player_animation: AnimationPlayer::new("idle"),
+            client_id: None,
+            players: HashMap::new(),
+            chat: Vec::new(),
+            kicked: None,
+        }
+    }
+
+    fn add_chat(&mut self, line: String) {
+        if self.chat.len() >= CHAT_HISTORY {
+            self.chat.remove(0);
+        }
+        self.chat.push(line);
+    }
+
+    /// Everything from the server that isn't about chunks
+    fn handle_message(&mut self, message: ServerMessage, ctx: &mut Context<S, M>) {
+        match message {
+            ServerMessage::Welcome {
+                client_id,
+                position,
+                motd,
+            } => {
+                self.client_id = Some(client_id);
+                ctx.render.camera.position = Vec3::from(position);
+                self.add_chat(motd);
+            }
+            ServerMessage::Disconnect { reason } => self.kicked = Some(reason.to_string()),
+            // The server doesn't announce us to ourselves but don't count on it
+            ServerMessage::PlayerJoined { client_id, .. } if Some(client_id) == self.client_id => {}
+            ServerMessage::PlayerJoined {
+                client_id,
+                username,
+                position,
+            } => {
+                self.players.insert(
+                    client_id,
+                    RemotePlayer {
+                        username,
+                        position,
+                        yaw: 0.0,
+                        pitch: 0.0,
+                    },
+                );
+            }
+            ServerMessage::PlayerLeft { client_id } => {
+                self.players.remove(&client_id);
+            }
+            ServerMessage::PlayerMoved {
+                client_id,
+                position,
+                yaw,
+                pitch,
+            } => {
+                if let Some(player) = self.players.get_mut(&client_id) {
+                    player.position = position;
+                    player.yaw = yaw;
+                    player.pitch = pitch;
+                }
+            }
+            ServerMessage::Chat { sender, message } => match sender {
+                Some(sender) => self.add_chat(format!("<{sender}> {message}")),
+                None => self.add_chat(message),
+            },
+            message => println!("Unhandled message from server: {message:?}"),
         }
     }
 }
@@ -31,6 +124,8 @@ impl<S: 'static, M: ConvertModel<S> + 'static> Scene<SharedState, SceneEvents, C
     ) -> super::SceneSwitch<SharedState, SceneEvents, Context<S, M>> {
         if self.switch {
             SceneSwitch::replace(MenuScene::new())
+        } else if ctx.input_state.pressed(Action::Pause) {
+            SceneSwitch::push(PauseScene::new())
         } else {
             SceneSwitch::None
         }
@@ -41,15 +136,59 @@ impl<S: 'static, M: ConvertModel<S> + 'static> Scene<SharedState, SceneEvents, C
         gameworld: &mut SharedState,
         ctx: &mut Context<S, M>,
     ) -> Result<(), String> {
-        ctx.render
-            .draws
-            .push(crate::render::state::Draw { model_id: 0 });
+        if ctx.input_state.movement() == Vec2::ZERO {
+            self.player_animation.play("idle");
+        } else {
+            self.player_animation.play("walk");
+        }
+        let pose = ctx.render.asset_registry.rigs.get(&0).map(|rig| {
+            self.player_animation
+                .update(ctx.last_duration, &rig.animations);
+            self.player_animation.pose(rig)
+        });
+        ctx.render.draws.push(Draw { model_id: 0, pose });
+
+        ctx.meshing.set_focus(ctx.render.camera.position);
+        for pos in ctx.chunks.take_removed() {
+            ctx.meshing.cancel(pos);
+            ctx.render.chunk_uploads.push((pos, None));
+        }
+        for pos in ctx.chunks.take_dirty() {
+            if let Some(snapshot) = ctx.chunks.snapshot(pos) {
+                ctx.meshing.submit(pos, snapshot);
+            }
+        }
+        for (pos, mesh) in ctx.meshing.drain(MAX_UPLOADS_PER_FRAME) {
+            // Chunks that are all air or fully buried have nothing to draw
+            let mesh = (!mesh.indices.is_empty()).then_some(mesh);
+            ctx.render.chunk_uploads.push((pos, mesh));
+        }
 
         Ok(())
     }
 
     fn tick(&mut self, gameworld: &mut SharedState, ctx: &mut Context<S, M>) -> Result<(), String> {
-        Ok(())
+        // Done in the tick so the world stays in sync under overlays like the pause menu
+        let messages = match &mut ctx.network {
+            Some(network) => network.drain_messages(),
+            None => Vec::new(),
+        };
+        for message in messages {
+            if !ctx.chunks.handle_message(&message) {
+                self.handle_message(message, ctx);
+            }
+        }
+
+        // Failing the tick takes down every scene above too, so a disconnect still ends up
+        // on the error screen while paused
+        let disconnected = self
+            .kicked
+            .clone()
+            .or_else(|| ctx.network.as_ref().and_then(|x| x.disconnect_reason()));
+        match disconnected {
+            Some(reason) => Err(format!("Disconnected: {reason}")),
+            None => Ok(()),
+        }
     }
 
     fn input(
@@ -67,9 +206,38 @@ impl<S: 'static, M: ConvertModel<S> + 'static> Scene<SharedState, SceneEvents, C
             if ui.button("Menu").clicked() {
                 self.switch = true;
             }
+
+            ui.separator();
+            ui.label(format!("Players online: {}", self.players.len() + 1));
+            for player in self.players.values() {
+                ui.label(player.username.as_str());
+            }
+
+            ui.separator();
+            egui::ScrollArea::vertical()
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    for line in self.chat.iter() {
+                        ui.label(line.as_str());
+                    }
+                });
         });
     }
 
+    fn on_exit(&mut self, gameworld: &mut SharedState, ctx: &mut Context<S, M>) {
+        if let Some(mut network) = ctx.network.take() {
+            network.exit();
+            // Send the disconnect out now, nothing will update the connection after this
+            network.update(Duration::ZERO).ok();
+        }
+        // Leave nothing of this world behind for the next game to draw
+        ctx.chunks.clear();
+        for pos in ctx.chunks.take_removed() {
+            ctx.meshing.cancel(pos);
+            ctx.render.chunk_uploads.push((pos, None));
+        }
+    }
+
     fn name(&self) -> &str {
         "Game"
     }
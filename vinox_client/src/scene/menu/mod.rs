@@ -1,22 +1,79 @@
-use std::marker::PhantomData;
+use std::{
+    marker::PhantomData,
+    path::{Path, PathBuf},
+};
+
+use vinox_common::prelude::is_valid_username;
 
 use crate::{
     game::{Context, SharedState},
+    network::{
+        discovery::LanSearch,
+        servers::{SavedServer, ServerList, SERVERS_PATH},
+        state::{NetworkError, NetworkState, PendingAddress},
+    },
     render::state::ConvertModel,
 };
 
 use super::{game::GameScene, Scene, SceneEvents, SceneSwitch};
 
+/// Where the player asked to connect to
+enum Target {
+    Address(String),
+    Token(PathBuf),
+}
+
 pub struct MenuScene<S, M: ConvertModel<S>> {
     _phantom: PhantomData<(S, M)>,
-    switch: bool,
+    servers: ServerList,
+    /// `None` if the discovery socket couldn't be opened
+    lan: Option<LanSearch>,
+    /// Direct connect fields
+    name: String,
+    address: String,
+    connect: Option<Target>,
+    /// Address being looked up before connecting
+    resolving: Option<PendingAddress>,
+    /// Why the last connection attempt failed
+    error: Option<String>,
 }
 
 impl<S, M: ConvertModel<S>> MenuScene<S, M> {
     pub fn new() -> Self {
         Self {
             _phantom: PhantomData::default(),
-            switch: false,
+            servers: ServerList::default(),
+            lan: None,
+            name: String::new(),
+            address: String::new(),
+            connect: None,
+            resolving: None,
+            error: None,
+        }
+    }
+
+    fn save_servers(&mut self) {
+        if let Err(e) = self.servers.save(Path::new(SERVERS_PATH)) {
+            self.error = Some(format!("Failed to save servers: {e}"));
+        }
+    }
+
+    fn open(
+        &mut self,
+        network: Result<NetworkState, NetworkError>,
+        ctx: &mut Context<S, M>,
+    ) -> SceneSwitch<SharedState, SceneEvents, Context<S, M>> {
+        match network {
+            Ok(network) => {
+                // Remember the username that worked
+                self.save_servers();
+                ctx.network = Some(network);
+                SceneSwitch::replace(GameScene::new())
+            }
+            Err(e) => {
+                self.error = Some(format!("Failed to connect: {e}"));
+                SceneSwitch::None
+            }
         }
     }
 }
@@ -29,10 +86,35 @@ impl<S: 'static, M: ConvertModel<S> + 'static> Scene<SharedState, SceneEvents, C
         gameworld: &mut SharedState,
         ctx: &mut Context<S, M>,
     ) -> super::SceneSwitch<SharedState, SceneEvents, Context<S, M>> {
-        if self.switch {
-            SceneSwitch::replace(GameScene::new())
-        } else {
-            SceneSwitch::None
+        if let Some(lan) = &mut self.lan {
+            lan.update(ctx.last_duration);
+        }
+        let target = self.connect.take();
+        if let Some(resolving) = &self.resolving {
+            // Anything clicked meanwhile is dropped, one connection at a time
+            let Some(address) = resolving.poll() else {
+                return SceneSwitch::None;
+            };
+            self.resolving = None;
+            let network =
+                address.and_then(|address| NetworkState::new(address, &self.servers.username));
+            return self.open(network, ctx);
+        }
+        match target {
+            Some(Target::Address(_)) if !is_valid_username(&self.servers.username) => {
+                self.error = Some(format!("Invalid username {:?}", self.servers.username));
+                SceneSwitch::None
+            }
+            Some(Target::Address(address)) => {
+                self.error = None;
+                self.resolving = Some(PendingAddress::new(&address));
+                SceneSwitch::None
+            }
+            Some(Target::Token(path)) => {
+                let network = NetworkState::with_token_file(path);
+                self.open(network, ctx)
+            }
+            None => SceneSwitch::None,
         }
     }
 
@@ -41,9 +123,10 @@ impl<S: 'static, M: ConvertModel<S> + 'static> Scene<SharedState, SceneEvents, C
         gameworld: &mut SharedState,
         ctx: &mut Context<S, M>,
     ) -> Result<(), String> {
-        ctx.render
-            .draws
-            .push(crate::render::state::Draw { model_id: 0 });
+        ctx.render.draws.push(crate::render::state::Draw {
+            model_id: 0,
+            pose: None,
+        });
 
         Ok(())
     }
@@ -64,8 +147,109 @@ impl<S: 'static, M: ConvertModel<S> + 'static> Scene<SharedState, SceneEvents, C
     fn ui(&mut self, gameworld: &mut SharedState, ui: &mut egui::Context, ctx: &mut Context<S, M>) {
         egui::SidePanel::left("Menu").show(ui, |ui| {
             ui.heading("Vinox");
-            if ui.button("Game").clicked() {
-                self.switch = true;
+            if let Some(resolving) = &self.resolving {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label(format!("Connecting to {}...", resolving.address()));
+                });
+                if ui.button("Cancel").clicked() {
+                    self.resolving = None;
+                }
+            }
+            if let Some(error) = &self.error {
+                ui.colored_label(egui::Color32::RED, error.as_str());
+            }
+            ui.horizontal(|ui| {
+                ui.label("Username");
+                ui.text_edit_singleline(&mut self.servers.username);
+            });
+
+            ui.separator();
+            ui.label("Direct connect");
+            ui.horizontal(|ui| {
+                ui.label("Address");
+                ui.text_edit_singleline(&mut self.address);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Name");
+                ui.text_edit_singleline(&mut self.name);
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Connect").clicked() {
+                    self.connect = Some(Target::Address(self.address.clone()));
+                }
+                let can_save = !self.address.trim().is_empty();
+                if ui
+                    .add_enabled(can_save, egui::Button::new("Save"))
+                    .clicked()
+                {
+                    let name = match self.name.trim() {
+                        "" => self.address.trim().to_string(),
+                        name => name.to_string(),
+                    };
+                    self.servers.servers.push(SavedServer {
+                        name,
+                        address: self.address.trim().to_string(),
+                    });
+                    self.name.clear();
+                    self.save_servers();
+                }
+            });
+            if let Some(token) = &ctx.token {
+                if ui.button("Connect with token").clicked() {
+                    self.connect = Some(Target::Token(token.clone()));
+                }
+            }
+
+            ui.separator();
+            ui.label("Saved servers");
+            let mut remove = None;
+            for (index, server) in self.servers.servers.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{} ({})", server.name, server.address));
+                    if ui.button("Join").clicked() {
+                        self.connect = Some(Target::Address(server.address.clone()));
+                    }
+                    if ui.button("Remove").clicked() {
+                        remove = Some(index);
+                    }
+                });
+            }
+            if let Some(index) = remove {
+                self.servers.servers.remove(index);
+                self.save_servers();
+            }
+
+            ui.separator();
+            ui.label("LAN");
+            match &self.lan {
+                Some(lan) if lan.servers().is_empty() => {
+                    ui.label("Searching...");
+                }
+                Some(lan) => {
+                    for server in lan.servers() {
+                        ui.horizontal(|ui| {
+                            ui.label(format!(
+                                "{} {}/{} ({})",
+                                server.info.motd,
+                                server.info.players,
+                                server.info.max_players,
+                                server.address
+                            ));
+                            let compatible = server.info.is_compatible();
+                            let join = ui.add_enabled(compatible, egui::Button::new("Join"));
+                            if !compatible {
+                                ui.label(format!("Version {}", server.info.game_version));
+                            }
+                            if join.clicked() {
+                                self.connect = Some(Target::Address(server.address.to_string()));
+                            }
+                        });
+                    }
+                }
+                None => {
+                    ui.label("LAN discovery is unavailable");
+                }
             }
         });
     }
@@ -73,4 +257,23 @@ impl<S: 'static, M: ConvertModel<S> + 'static> Scene<SharedState, SceneEvents, C
     fn name(&self) -> &str {
         "Menu"
     }
+
+    fn on_enter(&mut self, gameworld: &mut SharedState, ctx: &mut Context<S, M>) {
+        self.servers = ServerList::load_or_create(Path::new(SERVERS_PATH)).unwrap_or_else(|e| {
+            println!("Failed to load servers: {e}");
+            ServerList::default()
+        });
+        self.lan = match LanSearch::new() {
+            Ok(lan) => Some(lan),
+            Err(e) => {
+                println!("Failed to start LAN discovery: {e}");
+                None
+            }
+        };
+    }
+
+    fn on_exit(&mut self, gameworld: &mut SharedState, ctx: &mut Context<S, M>) {
+        // Stop pinging the LAN while in game
+        self.lan = None;
+    }
 }
@@ -14,44 +14,126 @@
 //! system, the only difference is the details of how the pieces are put
 //! together.
 
+use std::fmt;
+
+use glam::Vec2;
+
+use crate::input::MouseButton;
+
+pub mod controls;
+pub mod error;
 pub mod game;
 pub mod menu;
+pub mod pause;
+
+/// Modifier keys held during a key event
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    /// The windows or command key
+    pub logo: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TouchPhase {
+    Started,
+    Moved,
+    Ended,
+    Cancelled,
+}
 
+/// Window and device events as the backend reports them. Keys, gamepad buttons and axes are
+/// named the same way as `input::Binding` ie `W`, `LShift` or `South`. Positions are in
+/// window pixels from the top left. Gamepad ids are handed out by the backend in the order
+/// gamepads are first seen.
+#[derive(Clone, Debug, PartialEq)]
 pub enum SceneEvents {
     /// No event
     None,
     /// event originated in `mouse_button_down_event()`
-    MouseButtonDownEvent,
+    MouseButtonDownEvent { button: MouseButton, position: Vec2 },
     /// event originated in `mouse_button_up_event()`
-    MouseButtonUpEvent,
+    MouseButtonUpEvent { button: MouseButton, position: Vec2 },
     /// event originated in `mouse_motion_event()`
-    MouseMotionEvent,
-    /// event originated in `raw_mouse_motion_event()`
-    RawMouseMotionEvent,
+    MouseMotionEvent { position: Vec2, delta: Vec2 },
+    /// event originated in `raw_mouse_motion_event()`, not affected by pointer acceleration
+    RawMouseMotionEvent { delta: Vec2 },
     /// event originated in `mouse_enter_or_leave()`
-    MouseEnterOrLeave,
+    MouseEnterOrLeave { entered: bool },
     /// event originated in `mouse_wheel_event()`
-    MouseWheelEvent,
-    /// event originated in `key_down_event()`
-    KeyDownEvent,
+    MouseWheelEvent { delta: Vec2 },
+    /// event originated in `key_down_event()`. `key` is `None` for keys with no key code
+    KeyDownEvent {
+        key: Option<String>,
+        scancode: u32,
+        modifiers: Modifiers,
+        repeated: bool,
+    },
     /// event originated in `key_up_event()`
-    KeyUpEvent,
+    KeyUpEvent {
+        key: Option<String>,
+        scancode: u32,
+        modifiers: Modifiers,
+    },
     /// event originated in `text_input_event()`
-    TextInputEvent,
+    TextInputEvent { character: char },
     /// event originated in `touch_event()`
-    TouchEvent,
+    TouchEvent { phase: TouchPhase, position: Vec2 },
     /// event originated in `gamepad_button_down_event()`
-    GamepadButtonDownEvent,
+    GamepadButtonDownEvent { button: String, id: usize },
     /// event originated in `gamepad_button_up_event()`
-    GamepadButtonUpEvent,
-    /// event originated in `gamepad_axis_event()`
-    GamepadAxisEvent,
+    GamepadButtonUpEvent { button: String, id: usize },
+    /// event originated in `gamepad_axis_event()`, `value` is from -1 to 1
+    GamepadAxisEvent { axis: String, value: f32, id: usize },
     /// event originated in `focus_event()`
-    FocusEvent,
+    FocusEvent { gained: bool },
     /// event originated in `quit_event()`
     QuitEvent,
     /// event originated in `resize_event()`
-    ResizeEvent,
+    ResizeEvent { width: f32, height: f32 },
+}
+
+impl SceneEvents {
+    /// Whether this is the start of something ie a press rather than a release. This is what
+    /// scenes get as `started` in `Scene::input`
+    pub fn started(&self) -> bool {
+        !matches!(
+            self,
+            SceneEvents::MouseButtonUpEvent { .. }
+                | SceneEvents::KeyUpEvent { .. }
+                | SceneEvents::GamepadButtonUpEvent { .. }
+                | SceneEvents::MouseEnterOrLeave { entered: false }
+                | SceneEvents::FocusEvent { gained: false }
+                | SceneEvents::TouchEvent {
+                    phase: TouchPhase::Ended | TouchPhase::Cancelled,
+                    ..
+                }
+        )
+    }
+
+    /// Mouse and touch events, which egui gets first when the pointer is over it
+    pub fn is_pointer(&self) -> bool {
+        matches!(
+            self,
+            SceneEvents::MouseButtonDownEvent { .. }
+                | SceneEvents::MouseButtonUpEvent { .. }
+                | SceneEvents::MouseMotionEvent { .. }
+                | SceneEvents::MouseWheelEvent { .. }
+                | SceneEvents::TouchEvent { .. }
+        )
+    }
+
+    /// Key and text events, which egui gets first when a text field has focus
+    pub fn is_keyboard(&self) -> bool {
+        matches!(
+            self,
+            SceneEvents::KeyDownEvent { .. }
+                | SceneEvents::KeyUpEvent { .. }
+                | SceneEvents::TextInputEvent { .. }
+        )
+    }
 }
 
 /// A command to change to a new scene, either by pushing a new one,
@@ -61,6 +143,12 @@ pub enum SceneSwitch<S, Ev, C> {
     Push(Box<dyn Scene<S, Ev, C>>),
     Replace(Box<dyn Scene<S, Ev, C>>),
     Pop,
+    /// Pop this many scenes at once, only the one left on top is resumed
+    PopMany(usize),
+    /// Pop everything but the bottom scene
+    PopToRoot,
+    /// Pop every scene and start over with this one
+    ReplaceAll(Box<dyn Scene<S, Ev, C>>),
 }
 
 /// A trait for you to implement on a scene.
@@ -80,6 +168,27 @@ pub trait Scene<S, Ev, C> {
     fn draw_previous(&self) -> bool {
         false
     }
+    /// Whether the next scene down gets to draw its ui under this one's
+    fn ui_previous(&self) -> bool {
+        false
+    }
+    /// Whether input events are passed on to the next scene down after this one
+    fn input_previous(&self) -> bool {
+        false
+    }
+    /// Whether the next scene down keeps ticking, ie a pause menu in multiplayer where the
+    /// world can't stop
+    fn tick_previous(&self) -> bool {
+        false
+    }
+    /// Called once when the scene is pushed or replaces another, before its first update
+    fn on_enter(&mut self, _gameworld: &mut S, _ctx: &mut C) {}
+    /// Called once when the scene is popped or replaced, it is dropped right after
+    fn on_exit(&mut self, _gameworld: &mut S, _ctx: &mut C) {}
+    /// Called when another scene is pushed on top of this one
+    fn on_pause(&mut self, _gameworld: &mut S, _ctx: &mut C) {}
+    /// Called when this scene is back on top after the ones above it were popped
+    fn on_resume(&mut self, _gameworld: &mut S, _ctx: &mut C) {}
 }
 
 impl<S, Ev, C> SceneSwitch<S, Ev, C> {
@@ -103,13 +212,48 @@ impl<S, Ev, C> SceneSwitch<S, Ev, C> {
     }
 
     /// Shortcut for `SceneSwitch::Pop`.
-    ///
-    /// Currently a little redundant but multiple pops might be nice.
     pub fn pop() -> Self {
         SceneSwitch::Pop
     }
+
+    /// Shortcut for `SceneSwitch::PopMany`.
+    pub fn pop_many(count: usize) -> Self {
+        SceneSwitch::PopMany(count)
+    }
+
+    /// Shortcut for `SceneSwitch::PopToRoot`.
+    pub fn pop_to_root() -> Self {
+        SceneSwitch::PopToRoot
+    }
+
+    /// Same as `replace()` but returns SceneSwitch::ReplaceAll
+    pub fn replace_all<N>(scene: N) -> Self
+    where
+        N: Scene<S, Ev, C> + 'static,
+    {
+        SceneSwitch::ReplaceAll(Box::new(scene))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SceneError {
+    /// Every scene has been popped, the game should quit
+    Empty,
+    /// A scene's `render()` or `tick()` returned an error
+    Failed { scene: String, message: String },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Empty => write!(f, "the scene stack is empty"),
+            SceneError::Failed { scene, message } => write!(f, "{scene}: {message}"),
+        }
+    }
 }
 
+impl std::error::Error for SceneError {}
+
 /// A stack of `Scene`'s, together with a context object.
 pub struct SceneStack<S, Ev, C> {
     pub world: S,
@@ -124,112 +268,447 @@ impl<S, Ev, C> SceneStack<S, Ev, C> {
         }
     }
 
-    /// Add a new scene to the top of the stack.
+    /// Whether every scene has been popped, which is the signal to quit
+    pub fn is_empty(&self) -> bool {
+        self.scenes.is_empty()
+    }
+
+    /// Add a new scene to the top of the stack. This doesn't call any lifecycle hooks, go
+    /// through `switch()` for that.
     pub fn push(&mut self, scene: Box<dyn Scene<S, Ev, C>>) {
         self.scenes.push(scene)
     }
 
-    /// Remove the top scene from the stack and returns it;
-    /// panics if there is none.
-    pub fn pop(&mut self) -> Box<dyn Scene<S, Ev, C>> {
-        self.scenes
-            .pop()
-            .expect("ERROR: Popped an empty scene stack.")
+    /// Remove the top scene from the stack and returns it. Like `push()` no hooks are called.
+    pub fn pop(&mut self) -> Result<Box<dyn Scene<S, Ev, C>>, SceneError> {
+        self.scenes.pop().ok_or(SceneError::Empty)
     }
 
-    /// Returns the current scene; panics if there is none.
-    pub fn current(&self) -> &dyn Scene<S, Ev, C> {
-        &**self
-            .scenes
-            .last()
-            .expect("ERROR: Tried to get current scene of an empty scene stack.")
+    /// Returns the current scene.
+    pub fn current(&self) -> Result<&dyn Scene<S, Ev, C>, SceneError> {
+        self.scenes.last().map(|x| &**x).ok_or(SceneError::Empty)
     }
 
-    /// Executes the given SceneSwitch command; if it is a pop or replace
-    /// it returns `Some(old_scene)`, otherwise `None`
+    /// Takes the scenes rather than `self` so the world can be borrowed alongside
+    fn current_mut(
+        scenes: &mut [Box<dyn Scene<S, Ev, C>>],
+    ) -> Result<&mut dyn Scene<S, Ev, C>, SceneError> {
+        match scenes.last_mut() {
+            Some(scene) => Ok(&mut **scene),
+            None => Err(SceneError::Empty),
+        }
+    }
+
+    /// Executes the given SceneSwitch command, calling the lifecycle hooks of every scene
+    /// involved. Returns the scenes that were popped, after their `on_exit()`. Popping more
+    /// scenes than there are empties the stack rather than failing, check `is_empty()`
+    /// afterwards to know whether to quit.
     pub fn switch(
         &mut self,
         next_scene: SceneSwitch<S, Ev, C>,
-    ) -> Option<Box<dyn Scene<S, Ev, C>>> {
-        match next_scene {
-            SceneSwitch::None => None,
-            SceneSwitch::Pop => {
-                let s = self.pop();
-                Some(s)
+        ctx: &mut C,
+    ) -> Vec<Box<dyn Scene<S, Ev, C>>> {
+        let popped = match next_scene {
+            SceneSwitch::None => return Vec::new(),
+            SceneSwitch::Push(mut s) => {
+                if let Some(current) = self.scenes.last_mut() {
+                    current.on_pause(&mut self.world, ctx);
+                }
+                s.on_enter(&mut self.world, ctx);
+                self.push(s);
+                return Vec::new();
             }
-            SceneSwitch::Push(s) => {
+            SceneSwitch::Replace(mut s) => {
+                let popped = self.pop_scenes(1, ctx);
+                s.on_enter(&mut self.world, ctx);
                 self.push(s);
-                None
+                return popped;
             }
-            SceneSwitch::Replace(s) => {
-                let old_scene = self.pop();
+            SceneSwitch::ReplaceAll(mut s) => {
+                let popped = self.pop_scenes(self.scenes.len(), ctx);
+                s.on_enter(&mut self.world, ctx);
                 self.push(s);
-                Some(old_scene)
+                return popped;
+            }
+            SceneSwitch::Pop => self.pop_scenes(1, ctx),
+            SceneSwitch::PopMany(count) => self.pop_scenes(count, ctx),
+            SceneSwitch::PopToRoot => {
+                let count = self.scenes.len().saturating_sub(1);
+                self.pop_scenes(count, ctx)
+            }
+        };
+        if !popped.is_empty() {
+            if let Some(current) = self.scenes.last_mut() {
+                current.on_resume(&mut self.world, ctx);
             }
         }
+        popped
+    }
+
+    /// Pop up to `count` scenes from the top down calling `on_exit()` on each
+    fn pop_scenes(&mut self, count: usize, ctx: &mut C) -> Vec<Box<dyn Scene<S, Ev, C>>> {
+        let mut popped = Vec::new();
+        while popped.len() < count {
+            let Ok(mut s) = self.pop() else {
+                break;
+            };
+            s.on_exit(&mut self.world, ctx);
+            popped.push(s);
+        }
+        popped
     }
 
     /// The update function must be on the SceneStack because otherwise
     /// if you try to get the current scene and the world to call
     /// update() on the current scene it causes a double-borrow.  :/
-    pub fn update(&mut self, ctx: &mut C) {
-        let next_scene = {
-            let current_scene = &mut **self
-                .scenes
-                .last_mut()
-                .expect("Tried to update empty scene stack");
-            current_scene.update(&mut self.world, ctx)
-        };
-        self.switch(next_scene);
-    }
-
-    pub fn tick(&mut self, ctx: &mut C) {
-        let next_scene = {
-            let current_scene = &mut **self
-                .scenes
-                .last_mut()
-                .expect("Tried to update empty scene stack");
-            current_scene.update(&mut self.world, ctx)
-        };
-        self.switch(next_scene);
+    pub fn update(&mut self, ctx: &mut C) -> Result<(), SceneError> {
+        let next_scene = Self::current_mut(&mut self.scenes)?.update(&mut self.world, ctx);
+        self.switch(next_scene, ctx);
+        Ok(())
     }
 
-    /// We walk down the scene stack until we find a scene where we aren't
-    /// supposed to draw the previous one, then draw them from the bottom up.
+    /// We walk down the scene stack from the top for as long as `previous` says the scene
+    /// above wants the one below included, returning the index of the lowest scene reached.
     ///
     /// This allows for layering GUI's and such.
-    fn render_scenes(scenes: &mut [Box<dyn Scene<S, Ev, C>>], world: &mut S, ctx: &mut C) {
-        assert!(!scenes.is_empty());
-        if let Some((current, rest)) = scenes.split_last_mut() {
-            if current.draw_previous() {
-                SceneStack::render_scenes(rest, world, ctx);
+    fn first_layer(
+        scenes: &[Box<dyn Scene<S, Ev, C>>],
+        previous: impl Fn(&dyn Scene<S, Ev, C>) -> bool,
+    ) -> Result<usize, SceneError> {
+        let mut first = scenes.len().checked_sub(1).ok_or(SceneError::Empty)?;
+        while first > 0 && previous(&*scenes[first]) {
+            first -= 1;
+        }
+        Ok(first)
+    }
+
+    /// Run the fixed rate tick of the current scene and any under it that asked to keep
+    /// ticking, from the bottom up
+    pub fn tick(&mut self, ctx: &mut C) -> Result<(), SceneError> {
+        let first = Self::first_layer(&self.scenes, |x| x.tick_previous())?;
+        for scene in &mut self.scenes[first..] {
+            scene
+                .tick(&mut self.world, ctx)
+                .map_err(|message| SceneError::Failed {
+                    scene: scene.name().to_string(),
+                    message,
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Draw the current scene, and the ones under it from the bottom up for overlays.
+    pub fn render(&mut self, ctx: &mut C) -> Result<(), SceneError> {
+        let first = Self::first_layer(&self.scenes, |x| x.draw_previous())?;
+        for scene in &mut self.scenes[first..] {
+            scene
+                .render(&mut self.world, ctx)
+                .map_err(|message| SceneError::Failed {
+                    scene: scene.name().to_string(),
+                    message,
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Ui of the current scene, drawn over the ui of any scenes under it that it lets through
+    pub fn ui(&mut self, gui: &mut egui::Context, ctx: &mut C) -> Result<(), SceneError> {
+        let first = Self::first_layer(&self.scenes, |x| x.ui_previous())?;
+        for scene in &mut self.scenes[first..] {
+            scene.ui(&mut self.world, gui, ctx);
+        }
+        Ok(())
+    }
+
+    /// Feeds the given input event to the current scene, then down the stack for as long as
+    /// each scene passes input through.
+    pub fn input(&mut self, event: Ev, ctx: &mut C, started: bool) -> Result<(), SceneError>
+    where
+        Ev: Clone,
+    {
+        let first = Self::first_layer(&self.scenes, |x| x.input_previous())?;
+        for scene in self.scenes[first..].iter_mut().rev() {
+            scene.input(&mut self.world, event.clone(), ctx, started);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every call a scene gets, in order
+    type Log = Vec<String>;
+
+    /// Which `*_previous` flags a mock scene sets
+    #[derive(Clone, Copy, Default)]
+    struct Layers {
+        draw: bool,
+        ui: bool,
+        input: bool,
+        tick: bool,
+    }
+
+    /// Writes every call it gets into the context
+    struct Mock {
+        name: &'static str,
+        previous: Layers,
+        fail_tick: bool,
+    }
+
+    fn mock(name: &'static str) -> Mock {
+        overlay(name, Layers::default())
+    }
+
+    fn overlay(name: &'static str, previous: Layers) -> Mock {
+        Mock {
+            name,
+            previous,
+            fail_tick: false,
+        }
+    }
+
+    impl Scene<(), u32, Log> for Mock {
+        fn update(&mut self, _gameworld: &mut (), ctx: &mut Log) -> SceneSwitch<(), u32, Log> {
+            ctx.push(format!("{} update", self.name));
+            SceneSwitch::None
+        }
+        fn render(&mut self, _gameworld: &mut (), ctx: &mut Log) -> Result<(), String> {
+            ctx.push(format!("{} render", self.name));
+            Ok(())
+        }
+        fn tick(&mut self, _gameworld: &mut (), ctx: &mut Log) -> Result<(), String> {
+            ctx.push(format!("{} tick", self.name));
+            match self.fail_tick {
+                true => Err("broken".to_string()),
+                false => Ok(()),
             }
-            current
-                .render(world, ctx)
-                .expect("I would hope drawing a scene never fails!");
         }
+        fn input(&mut self, _gameworld: &mut (), event: u32, ctx: &mut Log, started: bool) {
+            ctx.push(format!("{} input {event} {started}", self.name));
+        }
+        fn ui(&mut self, _gameworld: &mut (), _ui: &mut egui::Context, ctx: &mut Log) {
+            ctx.push(format!("{} ui", self.name));
+        }
+        fn name(&self) -> &str {
+            self.name
+        }
+        fn draw_previous(&self) -> bool {
+            self.previous.draw
+        }
+        fn ui_previous(&self) -> bool {
+            self.previous.ui
+        }
+        fn input_previous(&self) -> bool {
+            self.previous.input
+        }
+        fn tick_previous(&self) -> bool {
+            self.previous.tick
+        }
+        fn on_enter(&mut self, _gameworld: &mut (), ctx: &mut Log) {
+            ctx.push(format!("{} enter", self.name));
+        }
+        fn on_exit(&mut self, _gameworld: &mut (), ctx: &mut Log) {
+            ctx.push(format!("{} exit", self.name));
+        }
+        fn on_pause(&mut self, _gameworld: &mut (), ctx: &mut Log) {
+            ctx.push(format!("{} pause", self.name));
+        }
+        fn on_resume(&mut self, _gameworld: &mut (), ctx: &mut Log) {
+            ctx.push(format!("{} resume", self.name));
+        }
+    }
+
+    /// A stack with `scenes` pushed bottom first and an empty log
+    fn stack(scenes: Vec<Mock>) -> (SceneStack<(), u32, Log>, Log) {
+        let mut log = Log::new();
+        let mut stack = SceneStack::new(&mut log, ());
+        for scene in scenes {
+            stack.switch(SceneSwitch::push(scene), &mut log);
+        }
+        log.clear();
+        (stack, log)
+    }
+
+    fn names(scenes: Vec<Box<dyn Scene<(), u32, Log>>>) -> Vec<String> {
+        scenes.iter().map(|x| x.name().to_string()).collect()
     }
 
-    /// Draw the current scene.
-    pub fn render(&mut self, ctx: &mut C) {
-        SceneStack::render_scenes(&mut self.scenes, &mut self.world, ctx)
+    #[test]
+    fn hook_order() {
+        let (mut stack, mut log) = stack(vec![]);
+        stack.switch(SceneSwitch::push(mock("a")), &mut log);
+        assert_eq!(log, ["a enter"]);
+        log.clear();
+
+        stack.switch(SceneSwitch::push(mock("b")), &mut log);
+        assert_eq!(log, ["a pause", "b enter"]);
+        log.clear();
+
+        let popped = stack.switch(SceneSwitch::replace(mock("c")), &mut log);
+        assert_eq!(names(popped), ["b"]);
+        // Replacing doesn't uncover the scene below so it isn't resumed
+        assert_eq!(log, ["b exit", "c enter"]);
+        log.clear();
+
+        stack.switch(SceneSwitch::Pop, &mut log);
+        assert_eq!(log, ["c exit", "a resume"]);
+        log.clear();
+
+        stack.switch(SceneSwitch::Pop, &mut log);
+        assert_eq!(log, ["a exit"]);
+        assert!(stack.is_empty());
+        log.clear();
+
+        assert!(stack.switch(SceneSwitch::Pop, &mut log).is_empty());
+        assert!(log.is_empty());
     }
 
-    /// Ui
-    pub fn ui(&mut self, gui: &mut egui::Context, ctx: &mut C) {
-        let current_scene = &mut **self
-            .scenes
-            .last_mut()
-            .expect("Tried to update empty scene stack");
-        current_scene.ui(&mut self.world, gui, ctx);
+    #[test]
+    fn pop_many() {
+        let (mut stack, mut log) = stack(vec![mock("a"), mock("b"), mock("c"), mock("d")]);
+        let popped = stack.switch(SceneSwitch::PopMany(2), &mut log);
+        assert_eq!(names(popped), ["d", "c"]);
+        assert_eq!(log, ["d exit", "c exit", "b resume"]);
+        log.clear();
+
+        assert!(stack.switch(SceneSwitch::PopMany(0), &mut log).is_empty());
+        assert!(log.is_empty());
+
+        // More than there are empties the stack without resuming anything
+        let popped = stack.switch(SceneSwitch::PopMany(usize::MAX), &mut log);
+        assert_eq!(names(popped), ["b", "a"]);
+        assert_eq!(log, ["b exit", "a exit"]);
+        assert!(stack.is_empty());
     }
 
-    /// Feeds the given input event to the current scene.
-    pub fn input(&mut self, event: Ev, ctx: &mut C, started: bool) {
-        let current_scene = &mut **self
-            .scenes
-            .last_mut()
-            .expect("Tried to do input for empty scene stack");
-        current_scene.input(&mut self.world, event, ctx, started);
+    #[test]
+    fn pop_to_root() {
+        let (mut stack, mut log) = stack(vec![mock("a"), mock("b"), mock("c")]);
+        let popped = stack.switch(SceneSwitch::PopToRoot, &mut log);
+        assert_eq!(names(popped), ["c", "b"]);
+        assert_eq!(log, ["c exit", "b exit", "a resume"]);
+        assert_eq!(stack.current().unwrap().name(), "a");
+        log.clear();
+
+        // Already at the root
+        assert!(stack.switch(SceneSwitch::PopToRoot, &mut log).is_empty());
+        assert!(log.is_empty());
+        assert_eq!(stack.current().unwrap().name(), "a");
+    }
+
+    #[test]
+    fn replace_all() {
+        let (mut stack, mut log) = stack(vec![mock("a"), mock("b")]);
+        let popped = stack.switch(SceneSwitch::replace_all(mock("c")), &mut log);
+        assert_eq!(names(popped), ["b", "a"]);
+        assert_eq!(log, ["b exit", "a exit", "c enter"]);
+        assert_eq!(stack.current().unwrap().name(), "c");
+        stack.pop().unwrap();
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn empty_stack() {
+        let (mut stack, mut log) = stack(vec![]);
+        assert!(matches!(stack.current(), Err(SceneError::Empty)));
+        assert!(matches!(stack.pop(), Err(SceneError::Empty)));
+        assert_eq!(stack.update(&mut log), Err(SceneError::Empty));
+        assert_eq!(stack.tick(&mut log), Err(SceneError::Empty));
+        assert_eq!(stack.render(&mut log), Err(SceneError::Empty));
+        assert_eq!(
+            stack.ui(&mut egui::Context::default(), &mut log),
+            Err(SceneError::Empty)
+        );
+        assert_eq!(stack.input(0, &mut log, true), Err(SceneError::Empty));
+        assert!(log.is_empty());
+    }
+
+    #[test]
+    fn layers() {
+        let every = Layers {
+            draw: true,
+            ui: true,
+            input: true,
+            tick: true,
+        };
+        let draw_only = Layers {
+            draw: true,
+            ..Layers::default()
+        };
+        let (mut stack, mut log) = stack(vec![
+            mock("a"),
+            overlay("b", every),
+            overlay("c", draw_only),
+        ]);
+
+        // Draws walk down through every scene that asks for the one below, bottom up
+        stack.render(&mut log).unwrap();
+        assert_eq!(log, ["a render", "b render", "c render"]);
+        log.clear();
+
+        // The rest stop at the top as it only lets drawing through
+        stack.tick(&mut log).unwrap();
+        stack.ui(&mut egui::Context::default(), &mut log).unwrap();
+        stack.input(7, &mut log, false).unwrap();
+        stack.update(&mut log).unwrap();
+        assert_eq!(log, ["c tick", "c ui", "c input 7 false", "c update"]);
+        log.clear();
+
+        // With an overlay that lets everything through, input goes top down and the rest
+        // bottom up. `b` passes everything on too so `a` is reached
+        stack.switch(SceneSwitch::replace(overlay("c", every)), &mut log);
+        log.clear();
+        stack.tick(&mut log).unwrap();
+        stack.ui(&mut egui::Context::default(), &mut log).unwrap();
+        stack.input(7, &mut log, true).unwrap();
+        assert_eq!(
+            log,
+            [
+                "a tick",
+                "b tick",
+                "c tick",
+                "a ui",
+                "b ui",
+                "c ui",
+                "c input 7 true",
+                "b input 7 true",
+                "a input 7 true",
+            ]
+        );
+        log.clear();
+
+        // Only the top scene updates no matter the layers
+        stack.update(&mut log).unwrap();
+        assert_eq!(log, ["c update"]);
+    }
+
+    #[test]
+    fn failed_tick_names_scene() {
+        let mut broken = overlay(
+            "b",
+            Layers {
+                tick: true,
+                ..Layers::default()
+            },
+        );
+        broken.fail_tick = true;
+        let (mut stack, mut log) = stack(vec![mock("a"), broken, overlay("c", Layers::default())]);
+
+        // Not reached, `c` doesn't tick the scenes below it
+        stack.tick(&mut log).unwrap();
+        stack.switch(SceneSwitch::Pop, &mut log);
+        log.clear();
+
+        assert_eq!(
+            stack.tick(&mut log),
+            Err(SceneError::Failed {
+                scene: "b".to_string(),
+                message: "broken".to_string(),
+            })
+        );
+        // `a` ran first as it is below, `b` failing stops there
+        assert_eq!(log, ["a tick", "b tick"]);
     }
 }
@@ -0,0 +1,108 @@
+use std::marker::PhantomData;
+
+use crate::{
+    game::{Context, SharedState},
+    input::Action,
+    render::state::ConvertModel,
+};
+
+use super::{controls::ControlsScene, menu::MenuScene, Scene, SceneEvents, SceneSwitch};
+
+enum PauseChoice {
+    Resume,
+    Controls,
+    Menu,
+    Quit,
+}
+
+/// Overlay over the game that keeps drawing and ticking it underneath, the game's own ui
+/// and input are held back until it's resumed
+pub struct PauseScene<S, M: ConvertModel<S>> {
+    _phantom: PhantomData<(S, M)>,
+    choice: Option<PauseChoice>,
+}
+
+impl<S, M: ConvertModel<S>> PauseScene<S, M> {
+    pub fn new() -> Self {
+        Self {
+            _phantom: PhantomData::default(),
+            choice: None,
+        }
+    }
+}
+
+impl<S: 'static, M: ConvertModel<S> + 'static> Scene<SharedState, SceneEvents, Context<S, M>>
+    for PauseScene<S, M>
+{
+    fn update(
+        &mut self,
+        gameworld: &mut SharedState,
+        ctx: &mut Context<S, M>,
+    ) -> super::SceneSwitch<SharedState, SceneEvents, Context<S, M>> {
+        if ctx.input_state.pressed(Action::Pause) {
+            self.choice = Some(PauseChoice::Resume);
+        }
+        match self.choice.take() {
+            Some(PauseChoice::Resume) => SceneSwitch::pop(),
+            Some(PauseChoice::Controls) => SceneSwitch::push(ControlsScene::new()),
+            Some(PauseChoice::Menu) => SceneSwitch::replace_all(MenuScene::new()),
+            // Popping everything is how the client is told to quit
+            Some(PauseChoice::Quit) => SceneSwitch::pop_many(usize::MAX),
+            None => SceneSwitch::None,
+        }
+    }
+
+    fn render(
+        &mut self,
+        gameworld: &mut SharedState,
+        ctx: &mut Context<S, M>,
+    ) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn tick(&mut self, gameworld: &mut SharedState, ctx: &mut Context<S, M>) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn input(
+        &mut self,
+        gameworld: &mut SharedState,
+        event: SceneEvents,
+        ctx: &mut Context<S, M>,
+        started: bool,
+    ) {
+    }
+
+    fn ui(&mut self, gameworld: &mut SharedState, ui: &mut egui::Context, ctx: &mut Context<S, M>) {
+        egui::Window::new("Paused")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ui, |ui| {
+                if ui.button("Resume").clicked() {
+                    self.choice = Some(PauseChoice::Resume);
+                }
+                if ui.button("Controls").clicked() {
+                    self.choice = Some(PauseChoice::Controls);
+                }
+                if ui.button("Menu").clicked() {
+                    self.choice = Some(PauseChoice::Menu);
+                }
+                if ui.button("Quit").clicked() {
+                    self.choice = Some(PauseChoice::Quit);
+                }
+            });
+    }
+
+    fn name(&self) -> &str {
+        "Pause"
+    }
+
+    fn draw_previous(&self) -> bool {
+        true
+    }
+
+    fn tick_previous(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,121 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use vinox_common::prelude::{BlockPos, Chunk, ChunkPos, ServerMessage, CHUNK_SIZE};
+
+use crate::render::meshing::ChunkSnapshot;
+
+/// The chunks the server has sent us. Tracks which chunks need their mesh rebuilt so only
+/// the chunks touched by a change are remeshed. Chunks are shared with the meshing threads
+/// and only copied if they change while being meshed.
+#[derive(Default)]
+pub struct ChunkStore {
+    chunks: HashMap<ChunkPos, Arc<Chunk>>,
+    /// Chunks whose mesh is out of date
+    dirty: HashSet<ChunkPos>,
+    /// Chunks that were unloaded and need their mesh dropped
+    removed: Vec<ChunkPos>,
+}
+
+impl ChunkStore {
+    /// Apply a world message from the server. Returns false for messages that aren't about
+    /// chunks so the caller can handle them
+    pub fn handle_message(&mut self, message: &ServerMessage) -> bool {
+        match message {
+            ServerMessage::ChunkData { position, data } => {
+                match Chunk::from_compressed_bytes(data) {
+                    Ok(chunk) => self.insert(*position, chunk),
+                    Err(e) => println!("Bad chunk {position:?} from server: {e}"),
+                }
+            }
+            ServerMessage::UnloadChunk { position } => self.remove(*position),
+            ServerMessage::BlockChanged { position, block } => {
+                self.set_block(*position, block);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn insert(&mut self, pos: ChunkPos, chunk: Chunk) {
+        self.chunks.insert(pos, Arc::new(chunk));
+        self.dirty.insert(pos);
+        // Faces against this chunk can now be hidden
+        self.mark_neighbors(pos);
+    }
+
+    pub fn remove(&mut self, pos: ChunkPos) {
+        if self.chunks.remove(&pos).is_some() {
+            self.dirty.remove(&pos);
+            self.removed.push(pos);
+            self.mark_neighbors(pos);
+        }
+    }
+
+    pub fn get(&self, pos: ChunkPos) -> Option<&Chunk> {
+        self.chunks.get(&pos).map(|x| x.as_ref())
+    }
+
+    /// A chunk and its loaded neighbors for meshing off the main thread
+    pub fn snapshot(&self, pos: ChunkPos) -> Option<ChunkSnapshot> {
+        let chunk = self.chunks.get(&pos)?.clone();
+        let mut neighbors = pos.neighbors();
+        Some(ChunkSnapshot {
+            chunk,
+            neighbors: [(); 6].map(|_| neighbors.next().and_then(|x| self.chunks.get(&x).cloned())),
+        })
+    }
+
+    pub fn get_block(&self, pos: BlockPos) -> Option<&str> {
+        self.chunks.get(&pos.chunk()).map(|x| x.get(pos.local()))
+    }
+
+    /// Change a block in a loaded chunk. Neighboring chunks are only remeshed if the block
+    /// is on the border they share
+    pub fn set_block(&mut self, pos: BlockPos, identifier: &str) {
+        let chunk_pos = pos.chunk();
+        let local = pos.local();
+        let Some(chunk) = self.chunks.get_mut(&chunk_pos) else {
+            return;
+        };
+        if !Arc::make_mut(chunk).set(local, identifier) {
+            return;
+        }
+        self.dirty.insert(chunk_pos);
+        let last = CHUNK_SIZE as u32 - 1;
+        for neighbor in chunk_pos.neighbors() {
+            let offset = *neighbor - *chunk_pos;
+            let on_border = (0..3).any(|axis| {
+                (offset[axis] > 0 && local[axis] == last) || (offset[axis] < 0 && local[axis] == 0)
+            });
+            if on_border && self.chunks.contains_key(&neighbor) {
+                self.dirty.insert(neighbor);
+            }
+        }
+    }
+
+    fn mark_neighbors(&mut self, pos: ChunkPos) {
+        for neighbor in pos.neighbors() {
+            if self.chunks.contains_key(&neighbor) {
+                self.dirty.insert(neighbor);
+            }
+        }
+    }
+
+    /// Chunks that need meshing since the last call
+    pub fn take_dirty(&mut self) -> Vec<ChunkPos> {
+        self.dirty.drain().collect()
+    }
+
+    /// Chunks unloaded since the last call
+    pub fn take_removed(&mut self) -> Vec<ChunkPos> {
+        std::mem::take(&mut self.removed)
+    }
+
+    pub fn clear(&mut self) {
+        self.removed.extend(self.chunks.drain().map(|(pos, _)| pos));
+        self.dirty.clear();
+    }
+}
@@ -1,6 +1,18 @@
+mod components;
 mod protocol;
+mod storage;
+mod world;
 
 pub mod prelude {
-    pub use crate::protocol::Position;
-    pub use crate::protocol::PROTOCOL_ID;
+    pub use crate::components::{Health, NetworkId, Player, Rotation, Velocity};
+    pub use crate::protocol::{
+        connection_config, is_valid_username, Channel, ClientMessage, ConnectionData,
+        DiscoveryPacket, HandshakeError, MessageError, Position, RejectReason, ServerInfo,
+        ServerMessage, DEFAULT_PORT, DISCOVERY_PORT, GAME_VERSION, PROTOCOL_ID,
+    };
+    pub use crate::storage::{PlayerRecord, StorageError, WorldDatabase, WorldMetadata};
+    pub use crate::world::{
+        BlockPos, Chunk, ChunkError, ChunkPos, LocalPos, AIR, CHUNK_SIZE, CHUNK_VOLUME,
+        FACE_NEIGHBORS,
+    };
 }
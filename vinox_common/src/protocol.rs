@@ -1,5 +1,26 @@
+mod channel;
+mod discovery;
+mod handshake;
+mod message;
 mod position;
 
+pub use channel::{connection_config, Channel};
+pub use discovery::{DiscoveryPacket, ServerInfo};
+pub use handshake::{is_valid_username, ConnectionData, HandshakeError, RejectReason};
+pub use message::{ClientMessage, MessageError, ServerMessage};
 pub use position::Position;
 
-pub const PROTOCOL_ID: u64 = 1;
+/// Netcode protocol id. Bump this whenever `ClientMessage` or `ServerMessage`
+/// change shape so that old clients are refused at connect time instead of
+/// failing to decode messages later.
+pub const PROTOCOL_ID: u64 = 2;
+
+/// Version of the game itself. Clients and servers on different versions may agree on the
+/// protocol but still disagree on content so both are checked when joining.
+pub const GAME_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Port servers host on unless configured otherwise
+pub const DEFAULT_PORT: u16 = 56552;
+
+/// Port servers answer LAN discovery pings on
+pub const DISCOVERY_PORT: u16 = 56553;
@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use renet::{ChannelConfig, ConnectionConfig, SendType};
+
+/// The renet channels used by vinox. Both the client and the server have to
+/// agree on these so always build the connection with `connection_config`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Channel {
+    /// Anything that has to arrive and has to arrive in order ie chat, joins, block edits
+    ReliableOrdered = 0,
+    /// State that is resent often enough that losing a packet doesn't matter ie movement
+    Unreliable = 1,
+    /// World data. Kept apart so a burst of chunks doesn't hold up chat and block edits
+    Chunks = 2,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 3] = [
+        Channel::ReliableOrdered,
+        Channel::Unreliable,
+        Channel::Chunks,
+    ];
+
+    pub fn config(self) -> ChannelConfig {
+        let send_type = match self {
+            Channel::ReliableOrdered | Channel::Chunks => SendType::ReliableOrdered {
+                resend_time: Duration::from_millis(200),
+            },
+            Channel::Unreliable => SendType::Unreliable,
+        };
+        let max_memory_usage_bytes = match self {
+            Channel::Chunks => 32 * 1024 * 1024,
+            _ => 5 * 1024 * 1024,
+        };
+        ChannelConfig {
+            channel_id: self.into(),
+            max_memory_usage_bytes,
+            send_type,
+        }
+    }
+}
+
+impl From<Channel> for u8 {
+    fn from(channel: Channel) -> Self {
+        channel as u8
+    }
+}
+
+/// Connection config shared by the client and the server
+pub fn connection_config() -> ConnectionConfig {
+    let channels: Vec<ChannelConfig> = Channel::ALL.iter().map(|x| x.config()).collect();
+    ConnectionConfig {
+        available_bytes_per_tick: 60_000,
+        server_channels_config: channels.clone(),
+        client_channels_config: channels,
+    }
+}
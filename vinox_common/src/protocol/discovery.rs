@@ -0,0 +1,82 @@
+use serde::{Deserialize, Serialize};
+
+use super::{GAME_VERSION, PROTOCOL_ID};
+
+/// Tag at the start of every discovery packet so other traffic on the port is ignored
+const DISCOVERY_MAGIC: &[u8; 4] = b"VNXD";
+
+/// LAN discovery over UDP broadcast. Clients broadcast a `Ping` to `DISCOVERY_PORT` and each
+/// server answers with a `Pong` describing itself. It is kept apart from renet since netcode
+/// only talks to clients that have already connected.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum DiscoveryPacket {
+    Ping,
+    Pong(ServerInfo),
+}
+
+/// What a server tells clients browsing the LAN
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ServerInfo {
+    pub motd: String,
+    pub players: u32,
+    pub max_players: u32,
+    pub protocol_id: u64,
+    pub game_version: String,
+    /// Port of the game socket, replies come from the discovery port instead
+    pub port: u16,
+}
+
+impl ServerInfo {
+    /// Whether a client of this build can join the server
+    pub fn is_compatible(&self) -> bool {
+        self.protocol_id == PROTOCOL_ID && self.game_version == GAME_VERSION
+    }
+}
+
+impl DiscoveryPacket {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = DISCOVERY_MAGIC.to_vec();
+        // Serializing plain data into a vec can't fail
+        bytes.extend(bincode::serialize(self).unwrap());
+        bytes
+    }
+
+    /// `None` for anything that isn't a discovery packet
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes = bytes.strip_prefix(DISCOVERY_MAGIC)?;
+        bincode::deserialize(bytes).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::DEFAULT_PORT;
+
+    #[test]
+    fn packets_round_trip() {
+        let packets = [
+            DiscoveryPacket::Ping,
+            DiscoveryPacket::Pong(ServerInfo {
+                motd: "A Vinox server".to_string(),
+                players: 3,
+                max_players: 64,
+                protocol_id: PROTOCOL_ID,
+                game_version: GAME_VERSION.to_string(),
+                port: DEFAULT_PORT,
+            }),
+        ];
+        for packet in packets {
+            assert_eq!(
+                DiscoveryPacket::from_bytes(&packet.to_bytes()),
+                Some(packet)
+            );
+        }
+    }
+
+    #[test]
+    fn ignores_foreign_packets() {
+        assert_eq!(DiscoveryPacket::from_bytes(b"hello"), None);
+        assert_eq!(DiscoveryPacket::from_bytes(b"VNXD\xff\xff\xff\xff"), None);
+    }
+}
@@ -0,0 +1,186 @@
+use std::fmt;
+
+use renet::transport::NETCODE_USER_DATA_BYTES;
+use serde::{Deserialize, Serialize};
+
+use super::{GAME_VERSION, PROTOCOL_ID};
+
+/// Sent by the client when connecting, inside the netcode user data. In secure mode it is
+/// baked into the connect token instead so it can't be spoofed.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ConnectionData {
+    pub username: String,
+    pub protocol_id: u64,
+    pub game_version: String,
+}
+
+#[derive(Debug)]
+pub enum HandshakeError {
+    /// The encoded data doesn't fit in the netcode user data
+    TooLong,
+    Encoding(bincode::Error),
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandshakeError::TooLong => write!(
+                f,
+                "connection data is longer than {NETCODE_USER_DATA_BYTES} bytes"
+            ),
+            HandshakeError::Encoding(e) => write!(f, "invalid connection data: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for HandshakeError {}
+
+/// Why the server turned a client away, sent to it in `ServerMessage::Disconnect`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum RejectReason {
+    /// Connection data was missing or couldn't be decoded
+    BadConnectionData,
+    BadUsername(String),
+    ProtocolMismatch {
+        server: u64,
+        client: u64,
+    },
+    VersionMismatch {
+        server: String,
+        client: String,
+    },
+    /// Someone with this name is already online
+    DuplicateName(String),
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RejectReason::BadConnectionData => write!(f, "Invalid connection data"),
+            RejectReason::BadUsername(x) => write!(f, "Invalid username {x:?}"),
+            RejectReason::ProtocolMismatch { server, client } => write!(
+                f,
+                "Protocol mismatch: server is on {server}, you are on {client}"
+            ),
+            RejectReason::VersionMismatch { server, client } => write!(
+                f,
+                "Version mismatch: server is on {server}, you are on {client}"
+            ),
+            RejectReason::DuplicateName(x) => write!(f, "{x} is already online"),
+        }
+    }
+}
+
+impl ConnectionData {
+    /// Connection data for this build of the game
+    pub fn new(username: impl Into<String>) -> Self {
+        Self {
+            username: username.into(),
+            protocol_id: PROTOCOL_ID,
+            game_version: GAME_VERSION.to_string(),
+        }
+    }
+
+    /// Check the data against this build returning why it doesn't match
+    pub fn validate(&self) -> Result<(), RejectReason> {
+        if self.protocol_id != PROTOCOL_ID {
+            return Err(RejectReason::ProtocolMismatch {
+                server: PROTOCOL_ID,
+                client: self.protocol_id,
+            });
+        }
+        if self.game_version != GAME_VERSION {
+            return Err(RejectReason::VersionMismatch {
+                server: GAME_VERSION.to_string(),
+                client: self.game_version.clone(),
+            });
+        }
+        if !is_valid_username(&self.username) {
+            return Err(RejectReason::BadUsername(self.username.clone()));
+        }
+        Ok(())
+    }
+
+    pub fn to_user_data(&self) -> Result<[u8; NETCODE_USER_DATA_BYTES], HandshakeError> {
+        let bytes = bincode::serialize(self).map_err(HandshakeError::Encoding)?;
+        if bytes.len() > NETCODE_USER_DATA_BYTES {
+            return Err(HandshakeError::TooLong);
+        }
+        let mut user_data = [0; NETCODE_USER_DATA_BYTES];
+        user_data[..bytes.len()].copy_from_slice(&bytes);
+        Ok(user_data)
+    }
+
+    pub fn from_user_data(
+        user_data: &[u8; NETCODE_USER_DATA_BYTES],
+    ) -> Result<Self, HandshakeError> {
+        // Trailing zeros are just padding and are ignored
+        bincode::deserialize(user_data).map_err(HandshakeError::Encoding)
+    }
+}
+
+/// Usernames are 3 to 16 ascii letters, digits or underscores
+pub fn is_valid_username(username: &str) -> bool {
+    (3..=16).contains(&username.len())
+        && username
+            .chars()
+            .all(|x| x.is_ascii_alphanumeric() || x == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_reasons() {
+        assert_eq!(ConnectionData::new("player_1").validate(), Ok(()));
+        assert_eq!(
+            ConnectionData::new("no").validate(),
+            Err(RejectReason::BadUsername("no".to_string()))
+        );
+        assert_eq!(
+            ConnectionData::new("bad name").validate(),
+            Err(RejectReason::BadUsername("bad name".to_string()))
+        );
+
+        let mut data = ConnectionData::new("player");
+        data.game_version = "0.0.0-old".to_string();
+        assert_eq!(
+            data.validate(),
+            Err(RejectReason::VersionMismatch {
+                server: GAME_VERSION.to_string(),
+                client: "0.0.0-old".to_string(),
+            })
+        );
+        // The protocol is checked first as nothing else can be trusted without it
+        data.protocol_id = PROTOCOL_ID + 1;
+        assert_eq!(
+            data.validate(),
+            Err(RejectReason::ProtocolMismatch {
+                server: PROTOCOL_ID,
+                client: PROTOCOL_ID + 1,
+            })
+        );
+
+        // Protocol 1 clients can't decode `Disconnect` with a `RejectReason` or the newer
+        // messages so they have to be turned away here
+        data.protocol_id = 1;
+        assert_eq!(
+            data.validate(),
+            Err(RejectReason::ProtocolMismatch {
+                server: PROTOCOL_ID,
+                client: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn user_data_round_trip() {
+        let data = ConnectionData::new("player");
+        let user_data = data.to_user_data().unwrap();
+        assert_eq!(ConnectionData::from_user_data(&user_data).unwrap(), data);
+
+        let long = ConnectionData::new("x".repeat(NETCODE_USER_DATA_BYTES));
+        assert!(matches!(long.to_user_data(), Err(HandshakeError::TooLong)));
+    }
+}
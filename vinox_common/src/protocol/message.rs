@@ -0,0 +1,247 @@
+use std::fmt;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{Channel, Position, RejectReason};
+use crate::world::{BlockPos, ChunkPos};
+
+/// Messages sent from the client to the server
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ClientMessage {
+    Join {
+        username: String,
+    },
+    Leave,
+    Movement {
+        position: Position,
+        yaw: f32,
+        pitch: f32,
+    },
+    BreakBlock {
+        position: BlockPos,
+    },
+    PlaceBlock {
+        position: BlockPos,
+        block: String,
+    },
+    Chat {
+        message: String,
+    },
+}
+
+/// Messages sent from the server to the client
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ServerMessage {
+    /// Sent once the server accepted the connection
+    Welcome {
+        client_id: u64,
+        position: Position,
+        motd: String,
+    },
+    /// Sent right before the server drops the client
+    Disconnect {
+        reason: RejectReason,
+    },
+    PlayerJoined {
+        client_id: u64,
+        username: String,
+        position: Position,
+    },
+    PlayerLeft {
+        client_id: u64,
+    },
+    PlayerMoved {
+        client_id: u64,
+        position: Position,
+        yaw: f32,
+        pitch: f32,
+    },
+    BlockChanged {
+        position: BlockPos,
+        block: String,
+    },
+    /// The chunk at `position` from `Chunk::to_compressed_bytes`
+    ChunkData {
+        position: ChunkPos,
+        data: Vec<u8>,
+    },
+    /// The chunk at `position` is out of view distance and can be dropped
+    UnloadChunk {
+        position: ChunkPos,
+    },
+    Chat {
+        /// `None` for messages from the server itself
+        sender: Option<String>,
+        message: String,
+    },
+}
+
+#[derive(Debug)]
+pub enum MessageError {
+    Encode(bincode::Error),
+    Decode(bincode::Error),
+}
+
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessageError::Encode(e) => write!(f, "failed to encode message: {e}"),
+            MessageError::Decode(e) => write!(f, "failed to decode message: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for MessageError {}
+
+fn to_bytes<T: Serialize>(message: &T) -> Result<Vec<u8>, MessageError> {
+    bincode::serialize(message).map_err(MessageError::Encode)
+}
+
+fn from_bytes<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, MessageError> {
+    bincode::deserialize(bytes).map_err(MessageError::Decode)
+}
+
+impl ClientMessage {
+    /// The channel this message should be sent over
+    pub fn channel(&self) -> Channel {
+        match self {
+            ClientMessage::Movement { .. } => Channel::Unreliable,
+            _ => Channel::ReliableOrdered,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, MessageError> {
+        to_bytes(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MessageError> {
+        from_bytes(bytes)
+    }
+}
+
+impl ServerMessage {
+    /// The channel this message should be sent over
+    pub fn channel(&self) -> Channel {
+        match self {
+            ServerMessage::PlayerMoved { .. } => Channel::Unreliable,
+            ServerMessage::ChunkData { .. } | ServerMessage::UnloadChunk { .. } => Channel::Chunks,
+            _ => Channel::ReliableOrdered,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, MessageError> {
+        to_bytes(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MessageError> {
+        from_bytes(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fixed::types::I60F4;
+
+    use super::*;
+
+    fn position() -> Position {
+        Position(mint::Point3 {
+            x: I60F4::from_num(-12.5),
+            y: I60F4::from_num(64),
+            z: I60F4::from_num(3.25),
+        })
+    }
+
+    #[test]
+    fn client_messages_round_trip() {
+        let messages = [
+            ClientMessage::Join {
+                username: "vixeliz".to_string(),
+            },
+            ClientMessage::Leave,
+            ClientMessage::Movement {
+                position: position(),
+                yaw: 1.5,
+                pitch: -0.25,
+            },
+            ClientMessage::BreakBlock {
+                position: BlockPos::new(-1, 2, -3),
+            },
+            ClientMessage::PlaceBlock {
+                position: BlockPos::new(4, 5, 6),
+                block: "vinox:stone".to_string(),
+            },
+            ClientMessage::Chat {
+                message: "hello".to_string(),
+            },
+        ];
+        for message in messages {
+            let bytes = message.to_bytes().unwrap();
+            assert_eq!(ClientMessage::from_bytes(&bytes).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn server_messages_round_trip() {
+        let messages = [
+            ServerMessage::Welcome {
+                client_id: 7,
+                position: position(),
+                motd: "Hi".to_string(),
+            },
+            ServerMessage::Disconnect {
+                reason: RejectReason::VersionMismatch {
+                    server: "0.2.0".to_string(),
+                    client: "0.1.0".to_string(),
+                },
+            },
+            ServerMessage::PlayerJoined {
+                client_id: 7,
+                username: "vixeliz".to_string(),
+                position: position(),
+            },
+            ServerMessage::PlayerLeft { client_id: 7 },
+            ServerMessage::PlayerMoved {
+                client_id: 7,
+                position: position(),
+                yaw: 0.5,
+                pitch: 0.0,
+            },
+            ServerMessage::BlockChanged {
+                position: BlockPos::new(0, -64, 0),
+                block: "vinox:air".to_string(),
+            },
+            ServerMessage::ChunkData {
+                position: ChunkPos::new(1, -1, 0),
+                data: vec![0, 1, 2, 255],
+            },
+            ServerMessage::UnloadChunk {
+                position: ChunkPos::new(1, -1, 0),
+            },
+            ServerMessage::Chat {
+                sender: None,
+                message: "Server restarting".to_string(),
+            },
+        ];
+        for message in messages {
+            let bytes = message.to_bytes().unwrap();
+            assert_eq!(ServerMessage::from_bytes(&bytes).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn movement_is_unreliable() {
+        let movement = ClientMessage::Movement {
+            position: position(),
+            yaw: 0.0,
+            pitch: 0.0,
+        };
+        assert_eq!(movement.channel(), Channel::Unreliable);
+        assert_eq!(ClientMessage::Leave.channel(), Channel::ReliableOrdered);
+    }
+
+    #[test]
+    fn garbage_fails_to_decode() {
+        assert!(ServerMessage::from_bytes(&[255, 255, 255, 255, 1]).is_err());
+    }
+}
@@ -1,6 +1,86 @@
 use derive_more::{Deref, DerefMut};
 use fixed::types::I60F4;
+use glam::Vec3;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Deref, DerefMut)]
+#[derive(Serialize, Deserialize, Deref, DerefMut, Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Position(pub mint::Point3<I60F4>);
+
+impl Position {
+    /// Move by an offset without going through `f32`, which would lose precision far from
+    /// the origin. A NaN or infinite offset is ignored instead of moving anything
+    pub fn translate(&mut self, offset: Vec3) {
+        if !offset.is_finite() {
+            return;
+        }
+        self.x = self.x.saturating_add(to_fixed(offset.x));
+        self.y = self.y.saturating_add(to_fixed(offset.y));
+        self.z = self.z.saturating_add(to_fixed(offset.z));
+    }
+}
+
+/// Like `I60F4::saturating_from_num` but NaN, which it panics on, becomes zero
+fn to_fixed(x: f32) -> I60F4 {
+    if x.is_nan() {
+        I60F4::ZERO
+    } else {
+        I60F4::saturating_from_num(x)
+    }
+}
+
+impl From<Vec3> for Position {
+    fn from(pos: Vec3) -> Self {
+        Self(mint::Point3 {
+            x: to_fixed(pos.x),
+            y: to_fixed(pos.y),
+            z: to_fixed(pos.z),
+        })
+    }
+}
+
+impl From<Position> for Vec3 {
+    fn from(pos: Position) -> Self {
+        Vec3::new(pos.x.to_num(), pos.y.to_num(), pos.z.to_num())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translate_far_from_origin() {
+        // f32 can't tell 2^24 and 2^24 + 0.5 apart
+        let far = I60F4::from_num(1 << 24);
+        let mut position = Position(mint::Point3 {
+            x: far,
+            y: I60F4::ZERO,
+            z: -far,
+        });
+        position.translate(Vec3::new(0.5, -0.25, 0.0));
+        assert_eq!(position.x, far + I60F4::from_num(0.5));
+        assert_eq!(position.y, I60F4::from_num(-0.25));
+        assert_eq!(position.z, -far);
+
+        // Nothing changes for a zero velocity
+        let before = position;
+        position.translate(Vec3::ZERO);
+        assert_eq!(position, before);
+    }
+
+    #[test]
+    fn translate_non_finite() {
+        let mut position = Position::from(Vec3::new(1.0, 2.0, 3.0));
+        let before = position;
+        position.translate(Vec3::new(f32::NAN, 1.0, 0.0));
+        assert_eq!(position, before);
+        position.translate(Vec3::new(0.0, f32::INFINITY, 0.0));
+        assert_eq!(position, before);
+
+        // Converting doesn't panic either, NaN goes to zero and infinity saturates
+        let position = Position::from(Vec3::new(f32::NAN, f32::NEG_INFINITY, 1.5));
+        assert_eq!(position.x, I60F4::ZERO);
+        assert_eq!(position.y, I60F4::MIN);
+        assert_eq!(position.z, I60F4::from_num(1.5));
+    }
+}
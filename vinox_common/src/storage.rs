@@ -0,0 +1,360 @@
+//! World saves. Everything about a world lives in one sqlite database: compressed chunks,
+//! players and world metadata. Only the server should ever open one.
+
+use std::{fmt, path::Path};
+
+use fixed::types::I60F4;
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+
+use crate::{
+    protocol::Position,
+    world::{BlockPos, Chunk, ChunkError, ChunkPos},
+};
+
+/// Each entry moves the schema up one version. Never edit an entry once it has shipped,
+/// add a new one instead.
+const MIGRATIONS: &[&str] = &[
+    // 1: Initial schema
+    "CREATE TABLE chunks (
+        x INTEGER NOT NULL,
+        y INTEGER NOT NULL,
+        z INTEGER NOT NULL,
+        data BLOB NOT NULL,
+        PRIMARY KEY (x, y, z)
+    ) WITHOUT ROWID;
+    CREATE TABLE players (
+        username TEXT PRIMARY KEY NOT NULL,
+        x INTEGER NOT NULL,
+        y INTEGER NOT NULL,
+        z INTEGER NOT NULL,
+        yaw REAL NOT NULL,
+        pitch REAL NOT NULL
+    );
+    CREATE TABLE world (
+        id INTEGER PRIMARY KEY CHECK (id = 0),
+        seed INTEGER NOT NULL,
+        spawn_x INTEGER NOT NULL,
+        spawn_y INTEGER NOT NULL,
+        spawn_z INTEGER NOT NULL,
+        time INTEGER NOT NULL
+    );",
+];
+
+#[derive(Debug)]
+pub enum StorageError {
+    Sqlite(rusqlite::Error),
+    /// A chunk blob failed to (de)compress
+    Chunk {
+        position: ChunkPos,
+        source: ChunkError,
+    },
+    /// The save was written by a newer version of the game
+    UnknownVersion(u32),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Sqlite(e) => write!(f, "database error: {e}"),
+            StorageError::Chunk { position, source } => {
+                write!(f, "chunk {} is corrupt: {source}", position.0)
+            }
+            StorageError::UnknownVersion(x) => write!(
+                f,
+                "world is from a newer version (schema {x}, newest known {})",
+                MIGRATIONS.len()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(e: rusqlite::Error) -> Self {
+        StorageError::Sqlite(e)
+    }
+}
+
+/// Global information about a world
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WorldMetadata {
+    pub seed: u64,
+    pub spawn: BlockPos,
+    /// Ticks since the world was created
+    pub time: u64,
+}
+
+/// Everything saved about a player between sessions
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlayerRecord {
+    pub username: String,
+    pub position: Position,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+pub struct WorldDatabase {
+    connection: Connection,
+}
+
+impl WorldDatabase {
+    /// Open or create a world save and bring its schema up to date
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        WorldDatabase::from_connection(Connection::open(path)?)
+    }
+
+    /// A database that only lives in memory, handy for tests and throwaway worlds
+    pub fn open_in_memory() -> Result<Self, StorageError> {
+        WorldDatabase::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(mut connection: Connection) -> Result<Self, StorageError> {
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.pragma_update(None, "synchronous", "NORMAL")?;
+        migrate(&mut connection)?;
+        Ok(Self { connection })
+    }
+
+    pub fn load_chunk(&self, position: ChunkPos) -> Result<Option<Chunk>, StorageError> {
+        let data: Option<Vec<u8>> = self
+            .connection
+            .prepare_cached("SELECT data FROM chunks WHERE x = ?1 AND y = ?2 AND z = ?3")?
+            .query_row(params![position.x, position.y, position.z], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        data.map(|data| {
+            Chunk::from_compressed_bytes(&data)
+                .map_err(|source| StorageError::Chunk { position, source })
+        })
+        .transpose()
+    }
+
+    pub fn save_chunk(&mut self, position: ChunkPos, chunk: &Chunk) -> Result<(), StorageError> {
+        self.save_chunks([(position, chunk)])
+    }
+
+    /// Write many chunks in a single transaction, much faster than saving them one by one
+    pub fn save_chunks<'a>(
+        &mut self,
+        chunks: impl IntoIterator<Item = (ChunkPos, &'a Chunk)>,
+    ) -> Result<(), StorageError> {
+        let transaction = self.connection.transaction()?;
+        {
+            let mut statement = transaction.prepare_cached(
+                "INSERT OR REPLACE INTO chunks (x, y, z, data) VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            for (position, chunk) in chunks {
+                let data = chunk
+                    .to_compressed_bytes()
+                    .map_err(|source| StorageError::Chunk { position, source })?;
+                statement.execute(params![position.x, position.y, position.z, data])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    pub fn load_player(&self, username: &str) -> Result<Option<PlayerRecord>, StorageError> {
+        Ok(self
+            .connection
+            .prepare_cached(
+                "SELECT username, x, y, z, yaw, pitch FROM players WHERE username = ?1",
+            )?
+            .query_row([username], |row| {
+                Ok(PlayerRecord {
+                    username: row.get(0)?,
+                    position: Position(mint::Point3 {
+                        x: I60F4::from_bits(row.get(1)?),
+                        y: I60F4::from_bits(row.get(2)?),
+                        z: I60F4::from_bits(row.get(3)?),
+                    }),
+                    yaw: row.get(4)?,
+                    pitch: row.get(5)?,
+                })
+            })
+            .optional()?)
+    }
+
+    /// Write many players in a single transaction
+    pub fn save_players<'a>(
+        &mut self,
+        players: impl IntoIterator<Item = &'a PlayerRecord>,
+    ) -> Result<(), StorageError> {
+        let transaction = self.connection.transaction()?;
+        {
+            let mut statement = transaction.prepare_cached(
+                "INSERT OR REPLACE INTO players (username, x, y, z, yaw, pitch)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            for player in players {
+                statement.execute(params![
+                    player.username,
+                    player.position.x.to_bits(),
+                    player.position.y.to_bits(),
+                    player.position.z.to_bits(),
+                    player.yaw,
+                    player.pitch,
+                ])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    pub fn load_metadata(&self) -> Result<Option<WorldMetadata>, StorageError> {
+        Ok(self
+            .connection
+            .query_row(
+                "SELECT seed, spawn_x, spawn_y, spawn_z, time FROM world WHERE id = 0",
+                [],
+                |row| {
+                    Ok(WorldMetadata {
+                        // sqlite only has signed integers so these round trip through i64
+                        seed: row.get::<_, i64>(0)? as u64,
+                        spawn: BlockPos::new(row.get(1)?, row.get(2)?, row.get(3)?),
+                        time: row.get::<_, i64>(4)? as u64,
+                    })
+                },
+            )
+            .optional()?)
+    }
+
+    pub fn save_metadata(&mut self, metadata: &WorldMetadata) -> Result<(), StorageError> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO world (id, seed, spawn_x, spawn_y, spawn_z, time)
+            VALUES (0, ?1, ?2, ?3, ?4, ?5)",
+            params![
+                metadata.seed as i64,
+                metadata.spawn.x,
+                metadata.spawn.y,
+                metadata.spawn.z,
+                metadata.time as i64,
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+/// Run every migration newer than the database's `user_version`
+fn migrate(connection: &mut Connection) -> Result<(), StorageError> {
+    let version: u32 = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version as usize > MIGRATIONS.len() {
+        return Err(StorageError::UnknownVersion(version));
+    }
+    let transaction = connection.transaction()?;
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        apply_migration(&transaction, migration, index as u32 + 1)?;
+    }
+    transaction.commit()?;
+    Ok(())
+}
+
+fn apply_migration(
+    transaction: &Transaction,
+    migration: &str,
+    version: u32,
+) -> Result<(), StorageError> {
+    transaction.execute_batch(migration)?;
+    transaction.pragma_update(None, "user_version", version)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(connection: &Connection) -> u32 {
+        connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn migrations_run_once() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        migrate(&mut connection).unwrap();
+        assert_eq!(version(&connection) as usize, MIGRATIONS.len());
+        // Running them again has to skip everything, otherwise CREATE TABLE fails
+        migrate(&mut connection).unwrap();
+        assert_eq!(version(&connection) as usize, MIGRATIONS.len());
+    }
+
+    #[test]
+    fn newer_schema_is_refused() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        let newer = MIGRATIONS.len() as u32 + 1;
+        connection
+            .pragma_update(None, "user_version", newer)
+            .unwrap();
+        assert!(matches!(
+            migrate(&mut connection),
+            Err(StorageError::UnknownVersion(x)) if x == newer
+        ));
+    }
+
+    #[test]
+    fn chunks_round_trip() {
+        let mut database = WorldDatabase::open_in_memory().unwrap();
+        let position = ChunkPos::new(-3, 2, 7);
+        assert_eq!(database.load_chunk(position).unwrap(), None);
+
+        let mut chunk = Chunk::new();
+        chunk.set(crate::world::LocalPos::new(1, 2, 3).unwrap(), "test:stone");
+        database.save_chunk(position, &chunk).unwrap();
+        assert_eq!(database.load_chunk(position).unwrap(), Some(chunk.clone()));
+
+        // Saving again replaces the old chunk
+        let filled = Chunk::filled("test:dirt");
+        database
+            .save_chunks([(position, &filled), (ChunkPos::new(0, 0, 0), &chunk)])
+            .unwrap();
+        assert_eq!(database.load_chunk(position).unwrap(), Some(filled));
+        assert_eq!(
+            database.load_chunk(ChunkPos::new(0, 0, 0)).unwrap(),
+            Some(chunk)
+        );
+    }
+
+    #[test]
+    fn players_round_trip() {
+        let mut database = WorldDatabase::open_in_memory().unwrap();
+        assert_eq!(database.load_player("someone").unwrap(), None);
+
+        let mut record = PlayerRecord {
+            username: "someone".to_string(),
+            position: Position(mint::Point3 {
+                x: I60F4::from_num(-12.5),
+                y: I60F4::from_num(64),
+                z: I60F4::from_num(1000.0625),
+            }),
+            yaw: 1.5,
+            pitch: -0.25,
+        };
+        database.save_players([&record]).unwrap();
+        assert_eq!(
+            database.load_player("someone").unwrap(),
+            Some(record.clone())
+        );
+
+        record.yaw = 3.0;
+        database.save_players([&record]).unwrap();
+        assert_eq!(database.load_player("someone").unwrap(), Some(record));
+    }
+
+    #[test]
+    fn metadata_round_trip() {
+        let mut database = WorldDatabase::open_in_memory().unwrap();
+        assert_eq!(database.load_metadata().unwrap(), None);
+
+        // Seeds above i64::MAX have to survive the trip through a signed column
+        let metadata = WorldMetadata {
+            seed: u64::MAX - 5,
+            spawn: BlockPos::new(-10, 70, 3),
+            time: 123_456,
+        };
+        database.save_metadata(&metadata).unwrap();
+        assert_eq!(database.load_metadata().unwrap(), Some(metadata));
+    }
+}
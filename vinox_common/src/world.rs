@@ -0,0 +1,5 @@
+mod chunk;
+mod coords;
+
+pub use chunk::{Chunk, ChunkError, AIR, CHUNK_SIZE, CHUNK_VOLUME};
+pub use coords::{BlockPos, ChunkPos, LocalPos, FACE_NEIGHBORS};
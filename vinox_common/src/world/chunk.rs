@@ -0,0 +1,443 @@
+use std::{collections::HashSet, fmt, io::Write};
+
+use bincode::Options;
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use serde::{Deserialize, Serialize};
+
+use super::LocalPos;
+
+/// Edge length of a chunk in blocks
+pub const CHUNK_SIZE: usize = 32;
+/// Amount of blocks in a chunk
+pub const CHUNK_VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+/// Identifier of the empty block. Every chunk starts out filled with it.
+pub const AIR: &str = "vinox:air";
+/// Most bytes a chunk may decode to. Chunks come from saves and the network so a corrupt or
+/// hostile one mustn't be able to allocate without limit
+const MAX_CHUNK_BYTES: u64 = 4 * 1024 * 1024;
+
+#[derive(Debug)]
+pub enum ChunkError {
+    Encode(bincode::Error),
+    Decode(bincode::Error),
+    /// Decoded fine but doesn't describe a valid chunk
+    Invalid(&'static str),
+}
+
+impl fmt::Display for ChunkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChunkError::Encode(e) => write!(f, "failed to encode chunk: {e}"),
+            ChunkError::Decode(e) => write!(f, "failed to decode chunk: {e}"),
+            ChunkError::Invalid(reason) => write!(f, "invalid chunk: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ChunkError {}
+
+/// A fixed size array of indices packed into u64s. Indices never straddle two
+/// words so a few bits per word may go unused.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+struct BitStorage {
+    bits: u32,
+    data: Vec<u64>,
+}
+
+impl BitStorage {
+    fn new(bits: u32) -> Self {
+        let data = if bits == 0 {
+            Vec::new()
+        } else {
+            vec![0; CHUNK_VOLUME.div_ceil(BitStorage::per_word(bits))]
+        };
+        Self { bits, data }
+    }
+
+    fn per_word(bits: u32) -> usize {
+        (u64::BITS / bits) as usize
+    }
+
+    fn get(&self, index: usize) -> usize {
+        if self.bits == 0 {
+            return 0;
+        }
+        let per_word = BitStorage::per_word(self.bits);
+        let shift = (index % per_word) as u32 * self.bits;
+        let mask = (1u64 << self.bits) - 1;
+        ((self.data[index / per_word] >> shift) & mask) as usize
+    }
+
+    fn set(&mut self, index: usize, value: usize) {
+        debug_assert!(value < 1 << self.bits);
+        let per_word = BitStorage::per_word(self.bits);
+        let shift = (index % per_word) as u32 * self.bits;
+        let mask = (1u64 << self.bits) - 1;
+        let word = &mut self.data[index / per_word];
+        *word = (*word & !(mask << shift)) | ((value as u64) << shift);
+    }
+
+    /// Copy every index into a new storage that is `bits` wide, passing them through `remap`
+    fn repack(&self, bits: u32, remap: impl Fn(usize) -> usize) -> Self {
+        let mut storage = BitStorage::new(bits);
+        if bits > 0 {
+            for index in 0..CHUNK_VOLUME {
+                storage.set(index, remap(self.get(index)));
+            }
+        }
+        storage
+    }
+}
+
+/// Bits needed to index a palette of `len` entries
+fn bits_for(len: usize) -> u32 {
+    if len <= 1 {
+        0
+    } else {
+        usize::BITS - (len - 1).leading_zeros()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+struct PaletteEntry {
+    identifier: String,
+    /// How many blocks in the chunk point at this entry. Entries at 0 are free to reuse.
+    count: u32,
+}
+
+/// A cube of `CHUNK_SIZE` blocks. Blocks are stored as indices into a per chunk palette of
+/// block identifiers so a chunk only pays for as many bits per block as it has different blocks.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Chunk {
+    palette: Vec<PaletteEntry>,
+    storage: BitStorage,
+    non_air: u32,
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Chunk::filled(AIR)
+    }
+}
+
+impl Chunk {
+    /// A chunk containing nothing but air
+    pub fn new() -> Self {
+        Chunk::default()
+    }
+
+    /// A chunk where every block is `identifier`
+    pub fn filled(identifier: &str) -> Self {
+        Self {
+            palette: vec![PaletteEntry {
+                identifier: identifier.to_string(),
+                count: CHUNK_VOLUME as u32,
+            }],
+            storage: BitStorage::new(0),
+            non_air: if identifier == AIR {
+                0
+            } else {
+                CHUNK_VOLUME as u32
+            },
+        }
+    }
+
+    fn index(pos: LocalPos) -> usize {
+        let size = CHUNK_SIZE as u32;
+        debug_assert!(pos.x < size && pos.y < size && pos.z < size);
+        ((pos.y * size + pos.z) * size + pos.x) as usize
+    }
+
+    /// Identifier of the block at the given local coordinate
+    pub fn get(&self, pos: LocalPos) -> &str {
+        &self.palette[self.storage.get(Chunk::index(pos))].identifier
+    }
+
+    /// Set the block at the given local coordinate returning whether anything changed
+    pub fn set(&mut self, pos: LocalPos, identifier: &str) -> bool {
+        let index = Chunk::index(pos);
+        let old = self.storage.get(index);
+        if self.palette[old].identifier == identifier {
+            return false;
+        }
+        let new = self.palette_index(identifier);
+
+        if self.palette[old].identifier == AIR {
+            self.non_air += 1;
+        } else if identifier == AIR {
+            self.non_air -= 1;
+        }
+        self.palette[old].count -= 1;
+        self.palette[new].count += 1;
+        self.storage.set(index, new);
+
+        if self.palette[old].count == 0 {
+            self.shrink();
+        }
+        true
+    }
+
+    /// Find or make room for `identifier` in the palette, growing the storage if needed
+    fn palette_index(&mut self, identifier: &str) -> usize {
+        if let Some(index) = self.palette.iter().position(|x| x.identifier == identifier) {
+            return index;
+        }
+        let entry = PaletteEntry {
+            identifier: identifier.to_string(),
+            count: 0,
+        };
+        if let Some(index) = self.palette.iter().position(|x| x.count == 0) {
+            self.palette[index] = entry;
+            return index;
+        }
+        self.palette.push(entry);
+        let bits = bits_for(self.palette.len());
+        if bits > self.storage.bits {
+            self.storage = self.storage.repack(bits, |x| x);
+        }
+        self.palette.len() - 1
+    }
+
+    /// Drop unused palette entries once the remaining ones fit in fewer bits
+    fn shrink(&mut self) {
+        let used = self.palette.iter().filter(|x| x.count > 0).count();
+        let bits = bits_for(used);
+        if bits >= self.storage.bits {
+            return;
+        }
+        let mut remap = vec![0; self.palette.len()];
+        let mut palette = Vec::with_capacity(used);
+        for (old, entry) in self.palette.drain(..).enumerate() {
+            if entry.count > 0 {
+                remap[old] = palette.len();
+                palette.push(entry);
+            }
+        }
+        self.palette = palette;
+        self.storage = self.storage.repack(bits, |x| remap[x]);
+    }
+
+    /// The identifiers currently in use by this chunk
+    pub fn palette(&self) -> impl Iterator<Item = &str> {
+        self.palette
+            .iter()
+            .filter(|x| x.count > 0)
+            .map(|x| x.identifier.as_str())
+    }
+
+    /// Amount of blocks that aren't air
+    pub fn non_air_count(&self) -> usize {
+        self.non_air as usize
+    }
+
+    /// Whether the chunk contains only air
+    pub fn is_empty(&self) -> bool {
+        self.non_air == 0
+    }
+
+    /// Serialize and compress the chunk for saving or sending over the network
+    pub fn to_compressed_bytes(&self) -> Result<Vec<u8>, ChunkError> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::fast());
+        bincode::serialize_into(&mut encoder, self).map_err(ChunkError::Encode)?;
+        encoder.flush().map_err(|e| ChunkError::Encode(e.into()))?;
+        encoder.finish().map_err(|e| ChunkError::Encode(e.into()))
+    }
+
+    /// Decompress and check a chunk from `to_compressed_bytes`. Anything that could make
+    /// `get` or `set` panic later is refused here instead
+    pub fn from_compressed_bytes(bytes: &[u8]) -> Result<Self, ChunkError> {
+        // Same encoding as `bincode::serialize_into` plus a limit
+        let chunk: Chunk = bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .allow_trailing_bytes()
+            .with_limit(MAX_CHUNK_BYTES)
+            .deserialize_from(ZlibDecoder::new(bytes))
+            .map_err(ChunkError::Decode)?;
+        chunk.validate()?;
+        Ok(chunk)
+    }
+
+    fn validate(&self) -> Result<(), ChunkError> {
+        let bits = self.storage.bits;
+        if self.palette.is_empty() || self.palette.len() > CHUNK_VOLUME {
+            return Err(ChunkError::Invalid("palette size out of range"));
+        }
+        if bits < bits_for(self.palette.len()) || bits > bits_for(CHUNK_VOLUME) {
+            return Err(ChunkError::Invalid("bit width doesn't fit the palette"));
+        }
+        if self.storage.data.len() != BitStorage::new(bits).data.len() {
+            return Err(ChunkError::Invalid("wrong amount of storage words"));
+        }
+        let mut identifiers = HashSet::new();
+        if !self
+            .palette
+            .iter()
+            .all(|x| identifiers.insert(x.identifier.as_str()))
+        {
+            return Err(ChunkError::Invalid("palette has duplicate blocks"));
+        }
+
+        let mut counts = vec![0u32; self.palette.len()];
+        for index in 0..CHUNK_VOLUME {
+            let entry = self.storage.get(index);
+            let Some(count) = counts.get_mut(entry) else {
+                return Err(ChunkError::Invalid("block outside of the palette"));
+            };
+            *count += 1;
+        }
+        if counts
+            .iter()
+            .zip(&self.palette)
+            .any(|(count, entry)| *count != entry.count)
+        {
+            return Err(ChunkError::Invalid("palette counts don't match the blocks"));
+        }
+        let non_air: u32 = self
+            .palette
+            .iter()
+            .filter(|x| x.identifier != AIR)
+            .map(|x| x.count)
+            .sum();
+        if non_air != self.non_air {
+            return Err(ChunkError::Invalid("wrong amount of non air blocks"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(index: usize) -> String {
+        format!("test:block_{index}")
+    }
+
+    /// Fill a chunk with `kinds` different blocks spread over every cell
+    fn striped(kinds: usize) -> Chunk {
+        let mut chunk = Chunk::new();
+        for (index, pos) in LocalPos::all().enumerate() {
+            chunk.set(pos, &block(index % kinds));
+        }
+        chunk
+    }
+
+    #[test]
+    fn palette_grows_through_every_width() {
+        // Both sides of each power of two, from one kind that needs no bits at all
+        let widths = [
+            (1, 0),
+            (2, 1),
+            (3, 2),
+            (4, 2),
+            (5, 3),
+            (16, 4),
+            (17, 5),
+            (256, 8),
+            (257, 9),
+            (300, 9),
+        ];
+        for (kinds, bits) in widths {
+            let chunk = striped(kinds);
+            assert_eq!(chunk.storage.bits, bits, "{kinds} kinds");
+            assert_eq!(chunk.palette().count(), kinds, "{kinds} kinds");
+            for (index, pos) in LocalPos::all().enumerate() {
+                assert_eq!(chunk.get(pos), block(index % kinds), "{kinds} kinds");
+            }
+            assert_eq!(chunk.non_air_count(), CHUNK_VOLUME);
+        }
+    }
+
+    #[test]
+    fn palette_shrinks_back_to_air() {
+        for kinds in [1, 2, 3, 4, 5, 16, 17, 256, 257, 300] {
+            let mut chunk = striped(kinds);
+            for pos in LocalPos::all() {
+                chunk.set(pos, AIR);
+            }
+            assert_eq!(chunk.palette.len(), 1, "{kinds} kinds");
+            assert_eq!(chunk.storage.bits, 0);
+            assert_eq!(chunk.non_air_count(), 0);
+            assert!(chunk.is_empty());
+            assert_eq!(chunk, Chunk::new());
+        }
+    }
+
+    #[test]
+    fn set_reports_changes() {
+        let mut chunk = Chunk::new();
+        let pos = LocalPos::new(1, 2, 3).unwrap();
+        assert!(!chunk.set(pos, AIR));
+        assert!(chunk.set(pos, "test:stone"));
+        assert!(!chunk.set(pos, "test:stone"));
+        assert_eq!(chunk.non_air_count(), 1);
+        assert_eq!(chunk.palette().count(), 2);
+    }
+
+    #[test]
+    fn compressed_round_trip() {
+        for chunk in [Chunk::new(), Chunk::filled("test:stone"), striped(17)] {
+            let bytes = chunk.to_compressed_bytes().unwrap();
+            assert_eq!(Chunk::from_compressed_bytes(&bytes).unwrap(), chunk);
+        }
+    }
+
+    fn compress(chunk: &Chunk) -> Vec<u8> {
+        // `to_compressed_bytes` writes whatever it is given, broken or not
+        chunk.to_compressed_bytes().unwrap()
+    }
+
+    #[test]
+    fn refuses_invalid_chunks() {
+        let mut outside = striped(3);
+        outside.palette.pop();
+        let mut counts = striped(3);
+        counts.palette[0].count += 1;
+        let mut words = striped(3);
+        words.storage.data.pop();
+        let mut width = striped(3);
+        width.storage.bits = 40;
+        let mut empty = Chunk::new();
+        empty.palette.clear();
+        let mut duplicate = striped(2);
+        duplicate.palette[1].identifier = duplicate.palette[0].identifier.clone();
+        let mut non_air = striped(2);
+        non_air.non_air = 0;
+        for chunk in [outside, counts, words, width, empty, duplicate, non_air] {
+            assert!(matches!(
+                Chunk::from_compressed_bytes(&compress(&chunk)),
+                Err(ChunkError::Invalid(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn refuses_garbage_and_huge_lengths() {
+        assert!(Chunk::from_compressed_bytes(b"not zlib").is_err());
+
+        // A palette claiming billions of entries has to fail on the limit, not allocate
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(&u64::MAX.to_le_bytes()).unwrap();
+        encoder.write_all(&[0; 64]).unwrap();
+        let bytes = encoder.finish().unwrap();
+        assert!(matches!(
+            Chunk::from_compressed_bytes(&bytes),
+            Err(ChunkError::Decode(_))
+        ));
+
+        // A zlib bomb of zeros is cut off at the limit
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&1u64.to_le_bytes()).unwrap();
+        encoder
+            .write_all(&(MAX_CHUNK_BYTES * 2).to_le_bytes())
+            .unwrap();
+        encoder
+            .write_all(&vec![b'a'; MAX_CHUNK_BYTES as usize * 2])
+            .unwrap();
+        let bytes = encoder.finish().unwrap();
+        assert!(matches!(
+            Chunk::from_compressed_bytes(&bytes),
+            Err(ChunkError::Decode(_))
+        ));
+    }
+}
@@ -0,0 +1,257 @@
+use derive_more::{Deref, DerefMut};
+use glam::{IVec3, UVec3, Vec3};
+use serde::{Deserialize, Serialize};
+
+use super::CHUNK_SIZE;
+use crate::protocol::Position;
+
+const CHUNK_SIZE_I32: i32 = CHUNK_SIZE as i32;
+
+/// Offsets to the six face neighbors of a cell
+pub const FACE_NEIGHBORS: [IVec3; 6] = [
+    IVec3::new(1, 0, 0),
+    IVec3::new(-1, 0, 0),
+    IVec3::new(0, 1, 0),
+    IVec3::new(0, -1, 0),
+    IVec3::new(0, 0, 1),
+    IVec3::new(0, 0, -1),
+];
+
+/// Position of a block in the world
+#[derive(
+    Serialize, Deserialize, Deref, DerefMut, Clone, Copy, Debug, Default, PartialEq, Eq, Hash,
+)]
+pub struct BlockPos(pub IVec3);
+
+/// Position of a chunk in the world, in chunks
+#[derive(
+    Serialize, Deserialize, Deref, DerefMut, Clone, Copy, Debug, Default, PartialEq, Eq, Hash,
+)]
+pub struct ChunkPos(pub IVec3);
+
+/// Position of a block inside of its chunk. Every component is in `0..CHUNK_SIZE`
+#[derive(
+    Serialize, Deserialize, Deref, DerefMut, Clone, Copy, Debug, Default, PartialEq, Eq, Hash,
+)]
+pub struct LocalPos(pub UVec3);
+
+// Shared by block and chunk positions as they are both just integer grids at different scales
+macro_rules! impl_grid_pos {
+    ($pos:ident) => {
+        impl $pos {
+            pub const fn new(x: i32, y: i32, z: i32) -> Self {
+                Self(IVec3::new(x, y, z))
+            }
+
+            /// The six positions sharing a face with this one
+            pub fn neighbors(self) -> impl Iterator<Item = $pos> {
+                FACE_NEIGHBORS.into_iter().map(move |x| $pos(self.0 + x))
+            }
+
+            /// Every position within a chebyshev distance of 1 excluding this one
+            pub fn surrounding(self) -> impl Iterator<Item = $pos> {
+                (-1..=1)
+                    .flat_map(|y| (-1..=1).flat_map(move |z| (-1..=1).map(move |x| (x, y, z))))
+                    .filter(|&offset| offset != (0, 0, 0))
+                    .map(move |(x, y, z)| $pos(self.0 + IVec3::new(x, y, z)))
+            }
+
+            pub fn manhattan_distance(self, other: $pos) -> u32 {
+                let diff = (self.0 - other.0).abs();
+                (diff.x + diff.y + diff.z) as u32
+            }
+
+            pub fn chebyshev_distance(self, other: $pos) -> u32 {
+                (self.0 - other.0).abs().max_element() as u32
+            }
+
+            pub fn as_vec3(self) -> Vec3 {
+                self.0.as_vec3()
+            }
+        }
+
+        impl From<IVec3> for $pos {
+            fn from(pos: IVec3) -> Self {
+                Self(pos)
+            }
+        }
+
+        impl From<$pos> for IVec3 {
+            fn from(pos: $pos) -> Self {
+                pos.0
+            }
+        }
+    };
+}
+
+impl_grid_pos!(BlockPos);
+impl_grid_pos!(ChunkPos);
+
+impl BlockPos {
+    /// Build a world position from a chunk and a position inside of it
+    pub fn from_chunk_local(chunk: ChunkPos, local: LocalPos) -> Self {
+        Self(chunk.0 * CHUNK_SIZE_I32 + local.0.as_ivec3())
+    }
+
+    pub fn chunk(self) -> ChunkPos {
+        ChunkPos(IVec3::new(
+            self.x.div_euclid(CHUNK_SIZE_I32),
+            self.y.div_euclid(CHUNK_SIZE_I32),
+            self.z.div_euclid(CHUNK_SIZE_I32),
+        ))
+    }
+
+    pub fn local(self) -> LocalPos {
+        LocalPos(UVec3::new(
+            self.x.rem_euclid(CHUNK_SIZE_I32) as u32,
+            self.y.rem_euclid(CHUNK_SIZE_I32) as u32,
+            self.z.rem_euclid(CHUNK_SIZE_I32) as u32,
+        ))
+    }
+
+    /// Center of the block in world space
+    pub fn center(self) -> Vec3 {
+        self.as_vec3() + Vec3::splat(0.5)
+    }
+}
+
+impl From<Position> for BlockPos {
+    fn from(pos: Position) -> Self {
+        // floor and not truncate otherwise -0.5 would end up in block 0 instead of -1
+        Self::new(
+            pos.x.floor().saturating_to_num(),
+            pos.y.floor().saturating_to_num(),
+            pos.z.floor().saturating_to_num(),
+        )
+    }
+}
+
+impl From<Vec3> for BlockPos {
+    fn from(pos: Vec3) -> Self {
+        Self(pos.floor().as_ivec3())
+    }
+}
+
+impl ChunkPos {
+    /// The block at the minimum corner of this chunk
+    pub fn origin(self) -> BlockPos {
+        BlockPos(self.0 * CHUNK_SIZE_I32)
+    }
+
+    /// Squared distance between the centers of two chunks, handy for sorting by distance
+    pub fn distance_squared(self, other: ChunkPos) -> i32 {
+        (self.0 - other.0).length_squared()
+    }
+}
+
+impl From<Position> for ChunkPos {
+    fn from(pos: Position) -> Self {
+        BlockPos::from(pos).chunk()
+    }
+}
+
+impl From<Vec3> for ChunkPos {
+    fn from(pos: Vec3) -> Self {
+        BlockPos::from(pos).chunk()
+    }
+}
+
+impl From<BlockPos> for ChunkPos {
+    fn from(pos: BlockPos) -> Self {
+        pos.chunk()
+    }
+}
+
+impl LocalPos {
+    /// `None` if any component is outside of the chunk
+    pub fn new(x: u32, y: u32, z: u32) -> Option<Self> {
+        let size = CHUNK_SIZE as u32;
+        (x < size && y < size && z < size).then(|| Self(UVec3::new(x, y, z)))
+    }
+
+    /// Every position in a chunk
+    pub fn all() -> impl Iterator<Item = LocalPos> {
+        let size = CHUNK_SIZE as u32;
+        (0..size).flat_map(move |y| {
+            (0..size).flat_map(move |z| (0..size).map(move |x| LocalPos(UVec3::new(x, y, z))))
+        })
+    }
+}
+
+impl From<Position> for LocalPos {
+    fn from(pos: Position) -> Self {
+        BlockPos::from(pos).local()
+    }
+}
+
+impl From<BlockPos> for LocalPos {
+    fn from(pos: BlockPos) -> Self {
+        pos.local()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fixed::types::I60F4;
+
+    use super::*;
+
+    fn position(x: f32, y: f32, z: f32) -> Position {
+        Position(mint::Point3 {
+            x: I60F4::from_num(x),
+            y: I60F4::from_num(y),
+            z: I60F4::from_num(z),
+        })
+    }
+
+    #[test]
+    fn negative_positions_floor() {
+        assert_eq!(
+            BlockPos::from(position(-0.5, 0.5, -1.0)),
+            BlockPos::new(-1, 0, -1)
+        );
+        assert_eq!(
+            BlockPos::from(Vec3::new(-0.5, 0.5, -1.0)),
+            BlockPos::new(-1, 0, -1)
+        );
+    }
+
+    #[test]
+    fn negative_blocks_split_into_chunk_and_local() {
+        let cases = [
+            (-1, -1, 31),
+            (-32, -1, 0),
+            (-33, -2, 31),
+            (0, 0, 0),
+            (32, 1, 0),
+        ];
+        for (block, chunk, local) in cases {
+            let pos = BlockPos::new(block, block, block);
+            assert_eq!(
+                pos.chunk(),
+                ChunkPos::new(chunk, chunk, chunk),
+                "block {block}"
+            );
+            assert_eq!(
+                pos.local(),
+                LocalPos::new(local, local, local).unwrap(),
+                "block {block}"
+            );
+        }
+    }
+
+    #[test]
+    fn chunk_local_round_trip() {
+        for x in [-65, -33, -32, -1, 0, 1, 31, 32, 100] {
+            let pos = BlockPos::new(x, -x, x * 3);
+            assert_eq!(BlockPos::from_chunk_local(pos.chunk(), pos.local()), pos);
+        }
+    }
+
+    #[test]
+    fn local_out_of_range() {
+        assert!(LocalPos::new(31, 31, 31).is_some());
+        assert_eq!(LocalPos::new(32, 0, 0), None);
+        assert_eq!(LocalPos::new(0, 0, u32::MAX), None);
+    }
+}
@@ -0,0 +1,568 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Identifier of the builtin empty block. Keep in sync with `vinox_common`'s `AIR`
+pub const AIR: &str = "vinox:air";
+
+/// Numeric id of a block, only valid for the registry that handed it out
+pub type BlockId = u16;
+
+/// The faces of a block in the same order as `vinox_common`'s `FACE_NEIGHBORS`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Face {
+    East,
+    West,
+    Top,
+    Bottom,
+    South,
+    North,
+}
+
+impl Face {
+    pub const ALL: [Face; 6] = [
+        Face::East,
+        Face::West,
+        Face::Top,
+        Face::Bottom,
+        Face::South,
+        Face::North,
+    ];
+
+    /// Direction this face is pointing
+    pub fn normal(self) -> [f32; 3] {
+        match self {
+            Face::East => [1.0, 0.0, 0.0],
+            Face::West => [-1.0, 0.0, 0.0],
+            Face::Top => [0.0, 1.0, 0.0],
+            Face::Bottom => [0.0, -1.0, 0.0],
+            Face::South => [0.0, 0.0, 1.0],
+            Face::North => [0.0, 0.0, -1.0],
+        }
+    }
+
+    /// The corners of this face on the box spanning `min` to `max`. They are ordered
+    /// top left, bottom left, bottom right, top right as seen from outside the box so
+    /// `[0, 1, 2, 0, 2, 3]` gives two counter clockwise triangles.
+    pub fn corners(self, min: [f32; 3], max: [f32; 3]) -> [[f32; 3]; 4] {
+        let [x0, y0, z0] = min;
+        let [x1, y1, z1] = max;
+        match self {
+            Face::East => [[x1, y1, z1], [x1, y0, z1], [x1, y0, z0], [x1, y1, z0]],
+            Face::West => [[x0, y1, z0], [x0, y0, z0], [x0, y0, z1], [x0, y1, z1]],
+            Face::Top => [[x0, y1, z0], [x0, y1, z1], [x1, y1, z1], [x1, y1, z0]],
+            Face::Bottom => [[x0, y0, z1], [x0, y0, z0], [x1, y0, z0], [x1, y0, z1]],
+            Face::South => [[x0, y1, z1], [x0, y0, z1], [x1, y0, z1], [x1, y1, z1]],
+            Face::North => [[x1, y1, z0], [x1, y0, z0], [x0, y0, z0], [x0, y1, z0]],
+        }
+    }
+}
+
+/// Textures for each face of a block. The most specific one set wins
+/// ie `north` over `side` over `all`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct BlockTextures {
+    pub all: Option<String>,
+    pub side: Option<String>,
+    pub top: Option<String>,
+    pub bottom: Option<String>,
+    pub east: Option<String>,
+    pub west: Option<String>,
+    pub south: Option<String>,
+    pub north: Option<String>,
+}
+
+impl BlockTextures {
+    pub fn face(&self, face: Face) -> Option<&str> {
+        let (specific, side) = match face {
+            Face::Top => (&self.top, None),
+            Face::Bottom => (&self.bottom, None),
+            Face::East => (&self.east, Some(&self.side)),
+            Face::West => (&self.west, Some(&self.side)),
+            Face::South => (&self.south, Some(&self.side)),
+            Face::North => (&self.north, Some(&self.side)),
+        };
+        specific
+            .as_deref()
+            .or(side.and_then(|x| x.as_deref()))
+            .or(self.all.as_deref())
+    }
+}
+
+/// An item dropped when a block is broken
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BlockDrop {
+    pub item: String,
+    #[serde(default = "default_one")]
+    pub min: u32,
+    #[serde(default = "default_one")]
+    pub max: u32,
+    /// Chance from 0 to 1 of this drop happening at all
+    #[serde(default = "default_chance")]
+    pub chance: f32,
+}
+
+fn default_one() -> u32 {
+    1
+}
+
+fn default_chance() -> f32 {
+    1.0
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_hardness() -> f32 {
+    1.0
+}
+
+/// A block as described by a `.ron` file ie
+/// ```ron
+/// (
+///     identifier: "vinox:grass",
+///     display_name: "Grass",
+///     textures: (top: Some("grass_top"), bottom: Some("dirt"), side: Some("grass_side")),
+///     hardness: 0.6,
+///     drops: [(item: "vinox:dirt")],
+/// )
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BlockDefinition {
+    /// Namespaced identifier ie `vinox:stone`
+    pub identifier: String,
+    pub display_name: String,
+    #[serde(default)]
+    pub textures: BlockTextures,
+    /// Whether blocks behind this one can be seen through it
+    #[serde(default)]
+    pub transparent: bool,
+    /// Whether entities collide with this block
+    #[serde(default = "default_true")]
+    pub solid: bool,
+    /// Light level emitted from 0 to 15
+    #[serde(default)]
+    pub light: u8,
+    /// How long it takes to break, negative for unbreakable
+    #[serde(default = "default_hardness")]
+    pub hardness: f32,
+    #[serde(default)]
+    pub drops: Vec<BlockDrop>,
+    /// Name of a voxel model to use instead of a full cube ie `slab`
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+impl BlockDefinition {
+    /// The builtin air block
+    pub fn air() -> Self {
+        Self {
+            identifier: AIR.to_string(),
+            display_name: "Air".to_string(),
+            textures: BlockTextures::default(),
+            transparent: true,
+            solid: false,
+            light: 0,
+            hardness: 0.0,
+            drops: Vec::new(),
+            model: None,
+        }
+    }
+
+    pub fn from_ron(source: &str) -> Result<Self, ron::error::SpannedError> {
+        ron::from_str(source)
+    }
+
+    /// The `namespace` and `name` halves of the identifier
+    pub fn split_identifier(&self) -> Option<(&str, &str)> {
+        split_identifier(&self.identifier)
+    }
+}
+
+/// Split `namespace:name` making sure neither half is empty
+pub fn split_identifier(identifier: &str) -> Option<(&str, &str)> {
+    let (namespace, name) = identifier.split_once(':')?;
+    let valid = |x: &str| {
+        !x.is_empty()
+            && x.chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    };
+    (valid(namespace) && valid(name)).then_some((namespace, name))
+}
+
+#[derive(Debug)]
+pub enum BlockError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        source: ron::error::SpannedError,
+    },
+    InvalidIdentifier(String),
+    Duplicate(String),
+    TooManyBlocks,
+    /// An id table that doesn't start with air or lists a block twice
+    InvalidTable(PathBuf),
+    Serialize(ron::Error),
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockError::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            BlockError::Parse { path, source } => write!(f, "{}: {source}", path.display()),
+            BlockError::InvalidIdentifier(x) => {
+                write!(f, "invalid block identifier {x:?}, expected namespace:name")
+            }
+            BlockError::Duplicate(x) => write!(f, "block {x} is defined more than once"),
+            BlockError::TooManyBlocks => write!(f, "more than {} blocks", BlockId::MAX),
+            BlockError::InvalidTable(path) => {
+                write!(f, "{}: block id table is corrupt", path.display())
+            }
+            BlockError::Serialize(e) => write!(f, "failed to write block ids: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for BlockError {}
+
+/// The id handed out to every block so far. Saving it keeps ids the same when blocks are
+/// added or removed, new blocks are only ever appended and removed ones keep their id.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct BlockIdTable {
+    /// Identifier of each id, air is always first
+    identifiers: Vec<String>,
+}
+
+impl Default for BlockIdTable {
+    fn default() -> Self {
+        Self {
+            identifiers: vec![AIR.to_string()],
+        }
+    }
+}
+
+impl BlockIdTable {
+    /// Read a table writing out one with only air first if there is none
+    pub fn load_or_create(path: &Path) -> Result<Self, BlockError> {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let table = BlockIdTable::default();
+                table.save(path)?;
+                return Ok(table);
+            }
+            Err(source) => {
+                return Err(BlockError::Io {
+                    path: path.to_path_buf(),
+                    source,
+                })
+            }
+        };
+        let table: BlockIdTable = ron::from_str(&source).map_err(|source| BlockError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let mut seen = HashSet::new();
+        let unique = table.identifiers.iter().all(|x| seen.insert(x.as_str()));
+        if table.identifiers.first().map(String::as_str) != Some(AIR) || !unique {
+            return Err(BlockError::InvalidTable(path.to_path_buf()));
+        }
+        Ok(table)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), BlockError> {
+        let source = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(BlockError::Serialize)?;
+        fs::write(path, source).map_err(|source| BlockError::Io {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    pub fn id(&self, identifier: &str) -> Option<BlockId> {
+        self.identifiers
+            .iter()
+            .position(|x| x == identifier)
+            .map(|x| x as BlockId)
+    }
+
+    /// The id of `identifier`, giving it the next free one if it doesn't have one yet
+    fn assign(&mut self, identifier: &str) -> Result<BlockId, BlockError> {
+        if let Some(id) = self.id(identifier) {
+            return Ok(id);
+        }
+        let id =
+            BlockId::try_from(self.identifiers.len()).map_err(|_| BlockError::TooManyBlocks)?;
+        self.identifiers.push(identifier.to_string());
+        Ok(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.identifiers.len()
+    }
+
+    /// Never true as air is always in the table
+    pub fn is_empty(&self) -> bool {
+        self.identifiers.is_empty()
+    }
+}
+
+/// Every block known to the game. Air is always id 0. Ids come from a `BlockIdTable` so
+/// they stay the same as blocks are added, ids of removed blocks are left empty.
+#[derive(Debug, Clone)]
+pub struct BlockRegistry {
+    blocks: Vec<Option<BlockDefinition>>,
+    ids: HashMap<String, BlockId>,
+}
+
+impl Default for BlockRegistry {
+    fn default() -> Self {
+        Self {
+            blocks: vec![Some(BlockDefinition::air())],
+            ids: HashMap::from([(AIR.to_string(), 0)]),
+        }
+    }
+}
+
+impl BlockRegistry {
+    /// A registry containing only air
+    pub fn new() -> Self {
+        BlockRegistry::default()
+    }
+
+    /// Build a registry out of a list of definitions with a fresh id table. Ids only match
+    /// between runs with the same definitions, use `with_table` to keep them stable
+    pub fn from_definitions(definitions: Vec<BlockDefinition>) -> Result<Self, BlockError> {
+        BlockRegistry::with_table(definitions, &mut BlockIdTable::default())
+    }
+
+    /// Build a registry taking ids from `table`. Blocks missing from it are appended in
+    /// identifier order
+    pub fn with_table(
+        mut definitions: Vec<BlockDefinition>,
+        table: &mut BlockIdTable,
+    ) -> Result<Self, BlockError> {
+        definitions.sort_by(|a, b| a.identifier.cmp(&b.identifier));
+        let mut registry = BlockRegistry::new();
+        for definition in definitions {
+            registry.check(&definition)?;
+            let id = table.assign(&definition.identifier)?;
+            registry.insert(id, definition);
+        }
+        Ok(registry)
+    }
+
+    /// Load every `.ron` file in a directory, recursing into sub directories
+    pub fn load_dir(path: impl AsRef<Path>) -> Result<Self, BlockError> {
+        let mut definitions = Vec::new();
+        read_definitions(path.as_ref(), &mut definitions)?;
+        BlockRegistry::from_definitions(definitions)
+    }
+
+    /// `load_dir` with ids from the table at `table_path`, saving it if new blocks were added
+    pub fn load_dir_with_table(
+        path: impl AsRef<Path>,
+        table_path: impl AsRef<Path>,
+    ) -> Result<Self, BlockError> {
+        let table_path = table_path.as_ref();
+        let mut definitions = Vec::new();
+        read_definitions(path.as_ref(), &mut definitions)?;
+        let mut table = BlockIdTable::load_or_create(table_path)?;
+        let known = table.len();
+        let registry = BlockRegistry::with_table(definitions, &mut table)?;
+        if table.len() != known {
+            table.save(table_path)?;
+        }
+        Ok(registry)
+    }
+
+    /// Add a block after every other one returning its id. The id isn't recorded in any
+    /// table so it is only good until the game closes
+    pub fn register(&mut self, definition: BlockDefinition) -> Result<BlockId, BlockError> {
+        self.check(&definition)?;
+        let id = BlockId::try_from(self.blocks.len()).map_err(|_| BlockError::TooManyBlocks)?;
+        self.insert(id, definition);
+        Ok(id)
+    }
+
+    fn check(&self, definition: &BlockDefinition) -> Result<(), BlockError> {
+        if definition.split_identifier().is_none() {
+            return Err(BlockError::InvalidIdentifier(definition.identifier.clone()));
+        }
+        if self.ids.contains_key(&definition.identifier) {
+            return Err(BlockError::Duplicate(definition.identifier.clone()));
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, id: BlockId, definition: BlockDefinition) {
+        let index = id as usize;
+        if self.blocks.len() <= index {
+            self.blocks.resize(index + 1, None);
+        }
+        self.ids.insert(definition.identifier.clone(), id);
+        self.blocks[index] = Some(definition);
+    }
+
+    pub fn id(&self, identifier: &str) -> Option<BlockId> {
+        self.ids.get(identifier).copied()
+    }
+
+    pub fn get(&self, id: BlockId) -> Option<&BlockDefinition> {
+        self.blocks.get(id as usize)?.as_ref()
+    }
+
+    pub fn get_by_identifier(&self, identifier: &str) -> Option<&BlockDefinition> {
+        self.get(self.id(identifier)?)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (BlockId, &BlockDefinition)> {
+        self.blocks
+            .iter()
+            .enumerate()
+            .filter_map(|(id, block)| Some((id as BlockId, block.as_ref()?)))
+    }
+
+    /// Number of registered blocks, ids can go higher when blocks were removed
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Never true as air is always registered
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+fn read_definitions(dir: &Path, definitions: &mut Vec<BlockDefinition>) -> Result<(), BlockError> {
+    let io_error = |source| BlockError::Io {
+        path: dir.to_path_buf(),
+        source,
+    };
+    for entry in fs::read_dir(dir).map_err(io_error)? {
+        let path = entry.map_err(io_error)?.path();
+        if path.is_dir() {
+            read_definitions(&path, definitions)?;
+        } else if path.extension().is_some_and(|x| x == "ron") {
+            let source = fs::read_to_string(&path).map_err(|source| BlockError::Io {
+                path: path.clone(),
+                source,
+            })?;
+            let definition = BlockDefinition::from_ron(&source)
+                .map_err(|source| BlockError::Parse { path, source })?;
+            definitions.push(definition);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(identifier: &str) -> BlockDefinition {
+        BlockDefinition {
+            identifier: identifier.to_string(),
+            display_name: identifier.to_string(),
+            ..BlockDefinition::air()
+        }
+    }
+
+    #[test]
+    fn air_is_zero() {
+        let registry = BlockRegistry::from_definitions(vec![block("a:b"), block("a:a")]).unwrap();
+        assert_eq!(registry.id(AIR), Some(0));
+        assert_eq!(registry.get(0).unwrap().identifier, AIR);
+        assert_eq!(registry.id("a:a"), Some(1));
+        assert_eq!(registry.id("a:b"), Some(2));
+        assert_eq!(registry.len(), 3);
+    }
+
+    #[test]
+    fn rejects_duplicates_and_bad_identifiers() {
+        assert!(matches!(
+            BlockRegistry::from_definitions(vec![block("a:a"), block("a:a")]),
+            Err(BlockError::Duplicate(x)) if x == "a:a"
+        ));
+        assert!(matches!(
+            BlockRegistry::from_definitions(vec![block(AIR)]),
+            Err(BlockError::Duplicate(_))
+        ));
+        for identifier in [
+            "stone",
+            ":stone",
+            "vinox:",
+            "Vinox:stone",
+            "vinox:st one",
+            "a:b:c",
+        ] {
+            assert!(matches!(
+                BlockRegistry::from_definitions(vec![block(identifier)]),
+                Err(BlockError::InvalidIdentifier(x)) if x == identifier
+            ));
+        }
+    }
+
+    #[test]
+    fn ids_stay_the_same() {
+        let mut table = BlockIdTable::default();
+        let first =
+            BlockRegistry::with_table(vec![block("vinox:stone"), block("vinox:dirt")], &mut table)
+                .unwrap();
+
+        // A block that sorts before the others mustn't shift them
+        let second = BlockRegistry::with_table(
+            vec![
+                block("vinox:stone"),
+                block("vinox:dirt"),
+                block("vinox:clay"),
+            ],
+            &mut table,
+        )
+        .unwrap();
+        for identifier in ["vinox:stone", "vinox:dirt"] {
+            assert_eq!(first.id(identifier), second.id(identifier));
+        }
+        assert_eq!(second.id("vinox:clay"), Some(3));
+
+        // Removed blocks keep their id free
+        let third = BlockRegistry::with_table(vec![block("vinox:clay")], &mut table).unwrap();
+        assert_eq!(third.id("vinox:clay"), Some(3));
+        assert!(third.get(1).is_none());
+        assert_eq!(third.iter().count(), 2);
+    }
+
+    #[test]
+    fn table_round_trip() {
+        let path = std::env::temp_dir().join(format!("block_ids_{}.ron", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut table = BlockIdTable::load_or_create(&path).unwrap();
+        assert_eq!(table, BlockIdTable::default());
+        BlockRegistry::with_table(vec![block("vinox:stone")], &mut table).unwrap();
+        table.save(&path).unwrap();
+        assert_eq!(BlockIdTable::load_or_create(&path).unwrap(), table);
+
+        fs::write(&path, r#"["vinox:stone", "vinox:air"]"#).unwrap();
+        assert!(matches!(
+            BlockIdTable::load_or_create(&path),
+            Err(BlockError::InvalidTable(_))
+        ));
+        fs::write(&path, r#"["vinox:air", "vinox:stone", "vinox:stone"]"#).unwrap();
+        assert!(matches!(
+            BlockIdTable::load_or_create(&path),
+            Err(BlockError::InvalidTable(_))
+        ));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1 +1,548 @@
+//! Voxel models for blocks and entities that aren't plain cubes. A model is a list of
+//! boxes with a texture per face, written in RON ie a bottom slab:
+//! ```ron
+//! (
+//!     textures: {"all": "stone"},
+//!     elements: [
+//!         (
+//!             from: (0, 0, 0),
+//!             to: (16, 8, 16),
+//!             faces: {
+//!                 Top: (texture: "#all"),
+//!                 Bottom: (texture: "#all", cull: Some(Bottom)),
+//!                 North: (texture: "#all", cull: Some(North)),
+//!                 South: (texture: "#all", cull: Some(South)),
+//!                 East: (texture: "#all", cull: Some(East)),
+//!                 West: (texture: "#all", cull: Some(West)),
+//!             },
+//!         ),
+//!     ],
+//! )
+//! ```
+//! Positions and uvs are in 16ths of a block so a full block goes from 0 to 16.
 
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use glam::{Mat3, Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::block::Face;
+
+/// Units per block used by positions and uvs
+pub const MODEL_UNITS: f32 = 16.0;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+/// Rotation of an element around a point
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct ElementRotation {
+    pub origin: [f32; 3],
+    pub axis: Axis,
+    /// Angle in degrees
+    pub angle: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ModelFace {
+    /// Either a texture name or `#variable` to look up in the model's `textures`
+    pub texture: String,
+    /// `[u0, v0, u1, v1]`. Defaults to the area of the texture the face covers
+    #[serde(default)]
+    pub uv: Option<[f32; 4]>,
+    /// Clockwise rotation of the texture in degrees, a multiple of 90
+    #[serde(default)]
+    pub rotation: u32,
+    /// Skip this face when the neighboring block on this side hides it
+    #[serde(default)]
+    pub cull: Option<Face>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ModelElement {
+    pub from: [f32; 3],
+    pub to: [f32; 3],
+    #[serde(default)]
+    pub rotation: Option<ElementRotation>,
+    pub faces: HashMap<Face, ModelFace>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct VoxelModel {
+    /// Texture variables used by faces as `#name`
+    #[serde(default)]
+    pub textures: HashMap<String, String>,
+    pub elements: Vec<ModelElement>,
+}
+
+/// Vertex of a model mesh. The client copies these into its own vertices, which also carry
+/// texture animation and skinning
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct ModelVertex {
+    pub pos: [f32; 3],
+    pub tex_coord: [f32; 2],
+    pub color: [f32; 4],
+    pub normals: [f32; 3],
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ModelMesh {
+    pub vertices: Vec<ModelVertex>,
+    pub indices: Vec<u32>,
+}
+
+#[derive(Debug)]
+pub enum VoxelModelError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: Option<PathBuf>,
+        source: ron::error::SpannedError,
+    },
+    /// A face uses `#name` but the model has no such texture variable
+    MissingVariable {
+        element: usize,
+        variable: String,
+    },
+    /// The texture lookup passed to `mesh` didn't know this texture
+    UnknownTexture(String),
+    InvalidBounds {
+        element: usize,
+    },
+    InvalidUv {
+        element: usize,
+        face: Face,
+    },
+    InvalidRotation {
+        element: usize,
+    },
+}
+
+impl fmt::Display for VoxelModelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VoxelModelError::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            VoxelModelError::Parse {
+                path: Some(path),
+                source,
+            } => write!(f, "{}: {source}", path.display()),
+            VoxelModelError::Parse { path: None, source } => write!(f, "{source}"),
+            VoxelModelError::MissingVariable { element, variable } => {
+                write!(f, "element {element} uses undefined texture #{variable}")
+            }
+            VoxelModelError::UnknownTexture(x) => write!(f, "unknown texture {x}"),
+            VoxelModelError::InvalidBounds { element } => {
+                write!(
+                    f,
+                    "element {element} has from greater than to or is out of range"
+                )
+            }
+            VoxelModelError::InvalidUv { element, face } => {
+                write!(
+                    f,
+                    "element {element} has an invalid uv or rotation on {face:?}"
+                )
+            }
+            VoxelModelError::InvalidRotation { element } => {
+                write!(f, "element {element} has an invalid rotation")
+            }
+        }
+    }
+}
+
+impl std::error::Error for VoxelModelError {}
+
+/// The uv a face gets when none is given, the part of the texture it would cover on a full block
+fn default_uv(face: Face, from: [f32; 3], to: [f32; 3]) -> [f32; 4] {
+    let [x0, y0, z0] = from;
+    let [x1, y1, z1] = to;
+    let s = MODEL_UNITS;
+    match face {
+        Face::North => [s - x1, s - y1, s - x0, s - y0],
+        Face::South => [x0, s - y1, x1, s - y0],
+        Face::East => [s - z1, s - y1, s - z0, s - y0],
+        Face::West => [z0, s - y1, z1, s - y0],
+        Face::Top => [x0, z0, x1, z1],
+        Face::Bottom => [x0, s - z1, x1, s - z0],
+    }
+}
+
+impl ElementRotation {
+    fn matrix(&self) -> Mat3 {
+        let angle = self.angle.to_radians();
+        match self.axis {
+            Axis::X => Mat3::from_rotation_x(angle),
+            Axis::Y => Mat3::from_rotation_y(angle),
+            Axis::Z => Mat3::from_rotation_z(angle),
+        }
+    }
+}
+
+impl VoxelModel {
+    /// Parse and validate a model
+    pub fn from_ron(source: &str) -> Result<Self, VoxelModelError> {
+        let model: VoxelModel = ron::from_str(source)
+            .map_err(|source| VoxelModelError::Parse { path: None, source })?;
+        model.validate()?;
+        Ok(model)
+    }
+
+    /// Load and validate a model from a file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, VoxelModelError> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|source| VoxelModelError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        VoxelModel::from_ron(&source).map_err(|e| match e {
+            VoxelModelError::Parse { path: None, source } => VoxelModelError::Parse {
+                path: Some(path.to_path_buf()),
+                source,
+            },
+            e => e,
+        })
+    }
+
+    /// Check that every texture variable exists and that every element is in range
+    pub fn validate(&self) -> Result<(), VoxelModelError> {
+        let range = -MODEL_UNITS..=MODEL_UNITS * 2.0;
+        for (index, element) in self.elements.iter().enumerate() {
+            let in_bounds = (0..3).all(|axis| {
+                element.from[axis] <= element.to[axis]
+                    && range.contains(&element.from[axis])
+                    && range.contains(&element.to[axis])
+            });
+            if !in_bounds {
+                return Err(VoxelModelError::InvalidBounds { element: index });
+            }
+            if let Some(rotation) = element.rotation {
+                if !rotation.angle.is_finite() || rotation.origin.iter().any(|x| !x.is_finite()) {
+                    return Err(VoxelModelError::InvalidRotation { element: index });
+                }
+            }
+            for (face, model_face) in element.faces.iter() {
+                self.resolve_texture(index, &model_face.texture)?;
+                let uv_valid = model_face
+                    .uv
+                    .is_none_or(|uv| uv.iter().all(|x| (0.0..=MODEL_UNITS).contains(x)));
+                if !uv_valid || model_face.rotation % 90 != 0 {
+                    return Err(VoxelModelError::InvalidUv {
+                        element: index,
+                        face: *face,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Names of every texture this model uses
+    pub fn texture_names(&self) -> impl Iterator<Item = &str> {
+        self.elements
+            .iter()
+            .enumerate()
+            .flat_map(move |(index, element)| {
+                element
+                    .faces
+                    .values()
+                    .filter_map(move |face| self.resolve_texture(index, &face.texture).ok())
+            })
+    }
+
+    fn resolve_texture<'a>(
+        &'a self,
+        element: usize,
+        texture: &'a str,
+    ) -> Result<&'a str, VoxelModelError> {
+        match texture.strip_prefix('#') {
+            Some(variable) => self.textures.get(variable).map(|x| x.as_str()).ok_or(
+                VoxelModelError::MissingVariable {
+                    element,
+                    variable: variable.to_string(),
+                },
+            ),
+            None => Ok(texture),
+        }
+    }
+
+    /// Build a standalone mesh of this model. `texture_rect` maps a texture name to where it is
+    /// in the texture (or atlas) being drawn with as `[x, y, width, height]` from 0 to 1.
+    pub fn to_mesh(
+        &self,
+        texture_rect: impl Fn(&str) -> Option<[f32; 4]>,
+    ) -> Result<ModelMesh, VoxelModelError> {
+        let mut mesh = ModelMesh::default();
+        self.append_mesh(&mut mesh, [0.0; 3], texture_rect, |_| false)?;
+        Ok(mesh)
+    }
+
+    /// Append this model to `mesh` offset by `offset` blocks. Faces with a `cull` side that
+    /// `culled` returns true for are skipped, which is how the chunk mesher hides faces
+    /// against neighboring blocks.
+    pub fn append_mesh(
+        &self,
+        mesh: &mut ModelMesh,
+        offset: [f32; 3],
+        texture_rect: impl Fn(&str) -> Option<[f32; 4]>,
+        culled: impl Fn(Face) -> bool,
+    ) -> Result<(), VoxelModelError> {
+        let offset = Vec3::from_array(offset);
+        for (index, element) in self.elements.iter().enumerate() {
+            let min = Vec3::from_array(element.from) / MODEL_UNITS;
+            let max = Vec3::from_array(element.to) / MODEL_UNITS;
+            let rotation = element
+                .rotation
+                .map(|x| (x.matrix(), Vec3::from_array(x.origin) / MODEL_UNITS));
+
+            // Iterate in a fixed order so the same model always gives the same mesh
+            for face in Face::ALL {
+                let Some(model_face) = element.faces.get(&face) else {
+                    continue;
+                };
+                if model_face.cull.is_some_and(&culled) {
+                    continue;
+                }
+                let texture = self.resolve_texture(index, &model_face.texture)?;
+                let [rect_x, rect_y, rect_w, rect_h] = texture_rect(texture)
+                    .ok_or_else(|| VoxelModelError::UnknownTexture(texture.to_string()))?;
+
+                let [u0, v0, u1, v1] = model_face
+                    .uv
+                    .unwrap_or_else(|| default_uv(face, element.from, element.to));
+                let mut uvs = [
+                    Vec2::new(u0, v0),
+                    Vec2::new(u0, v1),
+                    Vec2::new(u1, v1),
+                    Vec2::new(u1, v0),
+                ];
+                uvs.rotate_right((model_face.rotation / 90 % 4) as usize);
+
+                let mut normal = Vec3::from_array(face.normal());
+                let mut corners = face
+                    .corners(min.to_array(), max.to_array())
+                    .map(Vec3::from_array);
+                if let Some((matrix, origin)) = rotation {
+                    for corner in corners.iter_mut() {
+                        *corner = matrix * (*corner - origin) + origin;
+                    }
+                    normal = matrix * normal;
+                }
+
+                let start = mesh.vertices.len() as u32;
+                for (corner, uv) in corners.into_iter().zip(uvs) {
+                    let uv = uv / MODEL_UNITS;
+                    mesh.vertices.push(ModelVertex {
+                        pos: (corner + offset).to_array(),
+                        tex_coord: [rect_x + uv.x * rect_w, rect_y + uv.y * rect_h],
+                        color: [1.0, 1.0, 1.0, 1.0],
+                        normals: normal.to_array(),
+                    });
+                }
+                mesh.indices
+                    .extend([0, 1, 2, 0, 2, 3].into_iter().map(|x| start + x));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Every model in a directory keyed by file name without the extension ie `slab`
+pub fn load_models_dir(
+    path: impl AsRef<Path>,
+) -> Result<HashMap<String, VoxelModel>, VoxelModelError> {
+    let path = path.as_ref();
+    let io_error = |source| VoxelModelError::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+    let mut models = HashMap::new();
+    for entry in fs::read_dir(path).map_err(io_error)? {
+        let path = entry.map_err(io_error)?.path();
+        if path.extension().is_some_and(|x| x == "ron") {
+            if let Some(name) = path.file_stem().and_then(|x| x.to_str()) {
+                models.insert(name.to_string(), VoxelModel::load(&path)?);
+            }
+        }
+    }
+    Ok(models)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A bottom slab with every face using `texture`
+    fn slab(texture: &str) -> String {
+        format!(
+            r#"(
+                textures: {{"all": "stone"}},
+                elements: [(
+                    from: (0, 0, 0),
+                    to: (16, 8, 16),
+                    faces: {{
+                        East: (texture: "{texture}", cull: Some(East)),
+                        West: (texture: "{texture}", cull: Some(West)),
+                        Top: (texture: "{texture}"),
+                        Bottom: (texture: "{texture}", cull: Some(Bottom)),
+                        South: (texture: "{texture}", cull: Some(South)),
+                        North: (texture: "{texture}", cull: Some(North)),
+                    }},
+                )],
+            )"#
+        )
+    }
+
+    fn stone(texture: &str) -> Option<[f32; 4]> {
+        (texture == "stone").then_some([0.5, 0.25, 0.25, 0.5])
+    }
+
+    #[test]
+    fn missing_textures() {
+        assert!(matches!(
+            VoxelModel::from_ron(&slab("#missing")),
+            Err(VoxelModelError::MissingVariable { element: 0, variable }) if variable == "missing"
+        ));
+
+        // Names that aren't variables are only checked against the atlas when meshing
+        let model = VoxelModel::from_ron(&slab("dirt")).unwrap();
+        assert!(matches!(
+            model.to_mesh(stone),
+            Err(VoxelModelError::UnknownTexture(x)) if x == "dirt"
+        ));
+        assert_eq!(
+            VoxelModel::from_ron(&slab("#all"))
+                .unwrap()
+                .texture_names()
+                .collect::<Vec<_>>(),
+            ["stone"; 6]
+        );
+
+        assert!(matches!(
+            VoxelModel::from_ron("(elements: [(from: (0, 0, 0))])"),
+            Err(VoxelModelError::Parse { path: None, .. })
+        ));
+    }
+
+    #[test]
+    fn bad_elements() {
+        let mut model: VoxelModel = ron::from_str(&slab("#all")).unwrap();
+        assert!(model.validate().is_ok());
+
+        let mut broken = model.clone();
+        broken.elements[0].from = [0.0, 9.0, 0.0];
+        assert!(matches!(
+            broken.validate(),
+            Err(VoxelModelError::InvalidBounds { element: 0 })
+        ));
+        broken.elements[0].from = [0.0, 0.0, 0.0];
+        broken.elements[0].to = [16.0, 8.0, 40.0];
+        assert!(matches!(
+            broken.validate(),
+            Err(VoxelModelError::InvalidBounds { element: 0 })
+        ));
+
+        let mut broken = model.clone();
+        broken.elements[0].faces.get_mut(&Face::Top).unwrap().uv = Some([0.0, 0.0, 17.0, 16.0]);
+        assert!(matches!(
+            broken.validate(),
+            Err(VoxelModelError::InvalidUv {
+                element: 0,
+                face: Face::Top
+            })
+        ));
+
+        let mut broken = model.clone();
+        broken.elements[0]
+            .faces
+            .get_mut(&Face::Top)
+            .unwrap()
+            .rotation = 45;
+        assert!(matches!(
+            broken.validate(),
+            Err(VoxelModelError::InvalidUv {
+                element: 0,
+                face: Face::Top
+            })
+        ));
+
+        for rotation in [
+            ElementRotation {
+                origin: [8.0; 3],
+                axis: Axis::Y,
+                angle: f32::NAN,
+            },
+            ElementRotation {
+                origin: [8.0, f32::INFINITY, 8.0],
+                axis: Axis::Y,
+                angle: 45.0,
+            },
+        ] {
+            model.elements[0].rotation = Some(rotation);
+            assert!(matches!(
+                model.validate(),
+                Err(VoxelModelError::InvalidRotation { element: 0 })
+            ));
+        }
+    }
+
+    #[test]
+    fn cuboid_quads() {
+        let model = VoxelModel::from_ron(&slab("#all")).unwrap();
+        let mesh = model.to_mesh(stone).unwrap();
+        assert_eq!(mesh.vertices.len(), 24);
+        assert_eq!(mesh.indices.len(), 36);
+        assert_eq!(&mesh.indices[6..12], &[4, 5, 6, 4, 6, 7]);
+
+        // One quad per face in `Face::ALL` order, each on its side of the slab
+        for (face, quad) in Face::ALL.into_iter().zip(mesh.vertices.chunks(4)) {
+            let corners = face.corners([0.0; 3], [1.0, 0.5, 1.0]);
+            for (vertex, corner) in quad.iter().zip(corners) {
+                assert_eq!(vertex.pos, corner, "{face:?}");
+                assert_eq!(vertex.normals, face.normal());
+            }
+        }
+
+        // The top covers the whole texture
+        let top: Vec<_> = mesh.vertices[8..12].iter().map(|x| x.tex_coord).collect();
+        assert_eq!(top, [[0.5, 0.25], [0.5, 0.75], [0.75, 0.75], [0.75, 0.25]]);
+        // The sides only the bottom half of it, the part a full block would show there
+        let south: Vec<_> = mesh.vertices[16..20].iter().map(|x| x.tex_coord).collect();
+        assert_eq!(south, [[0.5, 0.5], [0.5, 0.75], [0.75, 0.75], [0.75, 0.5]]);
+    }
+
+    #[test]
+    fn culling_offset_and_rotation() {
+        let mut model = VoxelModel::from_ron(&slab("#all")).unwrap();
+        model.elements[0]
+            .faces
+            .get_mut(&Face::Top)
+            .unwrap()
+            .rotation = 90;
+
+        let mut mesh = ModelMesh::default();
+        model
+            .append_mesh(&mut mesh, [2.0, 0.0, -1.0], stone, |face| {
+                face != Face::North
+            })
+            .unwrap();
+        // Only the top has no cull side and North isn't hidden
+        assert_eq!(mesh.vertices.len(), 8);
+        let top = &mesh.vertices[..4];
+        assert_eq!(top[0].pos, [2.0, 0.5, -1.0]);
+        // Turned a quarter clockwise the first corner gets the last uv
+        assert_eq!(top[0].tex_coord, [0.75, 0.25]);
+        assert_eq!(top[1].tex_coord, [0.5, 0.25]);
+        assert_eq!(mesh.vertices[4].normals, Face::North.normal());
+    }
+}
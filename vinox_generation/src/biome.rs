@@ -0,0 +1,103 @@
+pub const STONE: &str = "vinox:stone";
+pub const DIRT: &str = "vinox:dirt";
+pub const GRASS: &str = "vinox:grass";
+pub const SAND: &str = "vinox:sand";
+pub const SNOW: &str = "vinox:snow";
+pub const WATER: &str = "vinox:water";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Biome {
+    Ocean,
+    Beach,
+    Plains,
+    Desert,
+    Tundra,
+    Mountains,
+}
+
+impl Biome {
+    /// Pick a biome for a column. `temperature` and `humidity` are roughly -1 to 1
+    /// and `mountains` is 0 to 1 for how much of the mountain noise was applied.
+    pub fn select(
+        height: i32,
+        sea_level: i32,
+        temperature: f64,
+        humidity: f64,
+        mountains: f64,
+    ) -> Self {
+        if height < sea_level - 2 {
+            Biome::Ocean
+        } else if height <= sea_level + 1 && mountains < 0.3 {
+            Biome::Beach
+        } else if mountains > 0.5 {
+            Biome::Mountains
+        } else if temperature < -0.35 {
+            Biome::Tundra
+        } else if temperature > 0.35 && humidity < 0.0 {
+            Biome::Desert
+        } else {
+            Biome::Plains
+        }
+    }
+
+    /// Block on the very top of a column
+    pub fn surface(self) -> &'static str {
+        match self {
+            Biome::Ocean | Biome::Beach | Biome::Desert => SAND,
+            Biome::Plains => GRASS,
+            Biome::Tundra => SNOW,
+            Biome::Mountains => STONE,
+        }
+    }
+
+    /// Blocks just under the surface
+    pub fn filler(self) -> &'static str {
+        match self {
+            Biome::Ocean | Biome::Beach | Biome::Desert => SAND,
+            Biome::Plains | Biome::Tundra => DIRT,
+            Biome::Mountains => STONE,
+        }
+    }
+
+    /// Color used by the preview renderer
+    pub fn color(self) -> [u8; 3] {
+        match self {
+            Biome::Ocean => [40, 70, 160],
+            Biome::Beach => [220, 210, 150],
+            Biome::Plains => [90, 160, 60],
+            Biome::Desert => [230, 200, 110],
+            Biome::Tundra => [235, 240, 245],
+            Biome::Mountains => [130, 130, 130],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEA: i32 = 62;
+
+    #[test]
+    fn select_thresholds() {
+        assert_eq!(Biome::select(SEA - 3, SEA, 0.0, 0.0, 0.0), Biome::Ocean);
+        assert_eq!(Biome::select(SEA - 2, SEA, 0.0, 0.0, 0.0), Biome::Beach);
+        assert_eq!(Biome::select(SEA + 1, SEA, 0.0, 0.0, 0.29), Biome::Beach);
+        // Mountains reaching the sea aren't beaches
+        assert_eq!(
+            Biome::select(SEA + 1, SEA, 0.0, 0.0, 0.51),
+            Biome::Mountains
+        );
+        assert_eq!(
+            Biome::select(SEA + 10, SEA, 0.0, 0.0, 0.51),
+            Biome::Mountains
+        );
+        assert_eq!(Biome::select(SEA + 10, SEA, 0.0, 0.0, 0.5), Biome::Plains);
+        assert_eq!(Biome::select(SEA + 10, SEA, -0.36, 0.0, 0.0), Biome::Tundra);
+        assert_eq!(Biome::select(SEA + 10, SEA, -0.35, 0.0, 0.0), Biome::Plains);
+        assert_eq!(Biome::select(SEA + 10, SEA, 0.36, -0.1, 0.0), Biome::Desert);
+        // Hot but humid stays plains
+        assert_eq!(Biome::select(SEA + 10, SEA, 0.36, 0.0, 0.0), Biome::Plains);
+        assert_eq!(Biome::select(SEA + 10, SEA, 0.35, -0.1, 0.0), Biome::Plains);
+    }
+}
@@ -0,0 +1,180 @@
+//! Deterministic terrain generation. The same seed and chunk position always give the
+//! same chunk so chunks never have to be saved until a player changes them.
+
+pub mod biome;
+pub mod preview;
+
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin, RidgedMulti};
+use vinox_common::prelude::{BlockPos, Chunk, ChunkPos, LocalPos, CHUNK_SIZE};
+
+use biome::{Biome, STONE, WATER};
+
+/// Anything below this that isn't terrain is filled with water
+pub const SEA_LEVEL: i32 = 62;
+/// Blocks of filler between the surface and stone
+const FILLER_DEPTH: i32 = 3;
+/// Caves stop this far below the surface so they don't leave the ground full of holes
+const CAVE_ROOF: i32 = 6;
+
+/// Height and biome of a single column of blocks
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Column {
+    pub height: i32,
+    pub biome: Biome,
+}
+
+pub struct WorldGenerator {
+    seed: u64,
+    continents: Fbm<Perlin>,
+    hills: Fbm<Perlin>,
+    mountains: RidgedMulti<Perlin>,
+    mountain_mask: Fbm<Perlin>,
+    temperature: Fbm<Perlin>,
+    humidity: Fbm<Perlin>,
+    cave_a: Fbm<Perlin>,
+    cave_b: Fbm<Perlin>,
+}
+
+/// Derive a seed for one noise layer from the world seed
+fn layer_seed(seed: u64, layer: u64) -> u32 {
+    // splitmix64 so neighboring layers don't get related seeds
+    let mut x = seed.wrapping_add(layer.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    (x ^ (x >> 31)) as u32
+}
+
+impl WorldGenerator {
+    pub fn new(seed: u64) -> Self {
+        let fbm = |layer, octaves, frequency| {
+            Fbm::<Perlin>::new(layer_seed(seed, layer))
+                .set_octaves(octaves)
+                .set_frequency(frequency)
+        };
+        Self {
+            seed,
+            continents: fbm(0, 4, 0.002),
+            hills: fbm(1, 4, 0.01),
+            mountains: RidgedMulti::<Perlin>::new(layer_seed(seed, 2))
+                .set_octaves(5)
+                .set_frequency(0.004),
+            mountain_mask: fbm(3, 2, 0.0015),
+            temperature: fbm(4, 2, 0.001),
+            humidity: fbm(5, 2, 0.0012),
+            cave_a: fbm(6, 2, 0.02),
+            cave_b: fbm(7, 2, 0.02),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Height of the top block and biome at a world x/z
+    pub fn column(&self, x: i32, z: i32) -> Column {
+        let point = [x as f64, z as f64];
+        let continents = self.continents.get(point);
+        let hills = self.hills.get(point);
+        let mountains = ((self.mountain_mask.get(point) - 0.1) * 3.0).clamp(0.0, 1.0);
+        let ridges = (self.mountains.get(point) + 1.0) * 0.5;
+
+        let height = SEA_LEVEL as f64 + continents * 30.0 + hills * 6.0 + mountains * ridges * 70.0;
+        let height = height.floor() as i32;
+
+        let biome = Biome::select(
+            height,
+            SEA_LEVEL,
+            self.temperature.get(point) * 2.0,
+            self.humidity.get(point) * 2.0,
+            mountains,
+        );
+        Column { height, biome }
+    }
+
+    /// Whether the block at a position is carved out by a cave
+    pub fn is_cave(&self, pos: BlockPos) -> bool {
+        let point = [pos.x as f64, pos.y as f64 * 1.5, pos.z as f64];
+        // Caves are where two noise fields both cross zero which gives long winding tunnels
+        let a = self.cave_a.get(point);
+        let b = self.cave_b.get(point);
+        a * a + b * b < 0.004
+    }
+
+    pub fn generate_chunk(&self, pos: ChunkPos) -> Chunk {
+        let origin = pos.origin();
+        let columns: Vec<Column> = (0..CHUNK_SIZE as i32)
+            .flat_map(|z| (0..CHUNK_SIZE as i32).map(move |x| (x, z)))
+            .map(|(x, z)| self.column(origin.x + x, origin.z + z))
+            .collect();
+
+        let mut chunk = Chunk::new();
+        let top = columns
+            .iter()
+            .map(|x| x.height)
+            .max()
+            .unwrap_or(SEA_LEVEL)
+            .max(SEA_LEVEL);
+        if origin.y > top {
+            return chunk;
+        }
+
+        for local in LocalPos::all() {
+            let block = BlockPos::from_chunk_local(pos, local);
+            let column = columns[local.z as usize * CHUNK_SIZE + local.x as usize];
+            let identifier = if block.y > column.height {
+                if block.y <= SEA_LEVEL {
+                    WATER
+                } else {
+                    continue;
+                }
+            } else if block.y < column.height - CAVE_ROOF && self.is_cave(block) {
+                continue;
+            } else if block.y == column.height {
+                column.biome.surface()
+            } else if block.y > column.height - FILLER_DEPTH {
+                column.biome.filler()
+            } else {
+                STONE
+            };
+            chunk.set(local, identifier);
+        }
+        chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_chunk() {
+        let pos = ChunkPos::new(3, 1, -2);
+        assert_eq!(
+            WorldGenerator::new(42).generate_chunk(pos),
+            WorldGenerator::new(42).generate_chunk(pos)
+        );
+    }
+
+    #[test]
+    fn different_seed_different_chunk() {
+        // Straddles sea level so it has terrain, water and air in it
+        let pos = ChunkPos::new(0, 1, 0);
+        assert_ne!(
+            WorldGenerator::new(1).generate_chunk(pos),
+            WorldGenerator::new(2).generate_chunk(pos)
+        );
+    }
+
+    #[test]
+    fn air_above_highest_column() {
+        // Noise stays within -1..1 so no column reaches this high
+        let highest = SEA_LEVEL + 30 + 6 + 70;
+        let generator = WorldGenerator::new(7);
+        let y = highest / CHUNK_SIZE as i32 + 1;
+        for x in -2..2 {
+            for z in -2..2 {
+                assert!(generator.generate_chunk(ChunkPos::new(x, y, z)).is_empty());
+            }
+        }
+    }
+}
@@ -1,3 +1,64 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use vinox_common::prelude::ChunkPos;
+use vinox_generation::{preview::render_preview, WorldGenerator};
+
+#[derive(Parser)]
+#[command(about = "Vinox terrain generator")]
+struct Cli {
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Render a top down biome and height map of a region to a png
+    Preview {
+        /// Block x of the center of the image
+        #[arg(long, default_value_t = 0, allow_hyphen_values = true)]
+        x: i32,
+        /// Block z of the center of the image
+        #[arg(long, default_value_t = 0, allow_hyphen_values = true)]
+        z: i32,
+        /// Width and height of the image in blocks
+        #[arg(long, default_value_t = 1024)]
+        size: u32,
+        #[arg(long, short, default_value = "preview.png")]
+        output: PathBuf,
+    },
+    /// Generate a single chunk and print what is in it
+    Chunk {
+        #[arg(allow_hyphen_values = true)]
+        x: i32,
+        #[arg(allow_hyphen_values = true)]
+        y: i32,
+        #[arg(allow_hyphen_values = true)]
+        z: i32,
+    },
+}
+
 fn main() {
-    println!("Hello, world!");
+    let cli = Cli::parse();
+    let generator = WorldGenerator::new(cli.seed);
+    match cli.command {
+        Command::Preview { x, z, size, output } => {
+            let half = (size / 2) as i32;
+            let image = render_preview(&generator, x - half, z - half, size, size);
+            if let Err(e) = image.save(&output) {
+                eprintln!("Failed to save {}: {e}", output.display());
+                std::process::exit(1);
+            }
+            println!("Saved preview to {}", output.display());
+        }
+        Command::Chunk { x, y, z } => {
+            let chunk = generator.generate_chunk(ChunkPos::new(x, y, z));
+            println!("Chunk {x} {y} {z}: {} solid blocks", chunk.non_air_count());
+            for identifier in chunk.palette() {
+                println!("  {identifier}");
+            }
+        }
+    }
 }
@@ -0,0 +1,23 @@
+use image::{Rgba, RgbaImage};
+
+use crate::{WorldGenerator, SEA_LEVEL};
+
+/// Render a top down map of the area starting at `min_x`, `min_z`, one pixel per block.
+/// Colors come from the biome and are shaded by height.
+pub fn render_preview(
+    generator: &WorldGenerator,
+    min_x: i32,
+    min_z: i32,
+    width: u32,
+    height: u32,
+) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, z| {
+        let column = generator.column(min_x + x as i32, min_z + z as i32);
+        let shade = (1.0 + (column.height - SEA_LEVEL) as f32 / 128.0).clamp(0.5, 1.5);
+        let [r, g, b] = column
+            .biome
+            .color()
+            .map(|x| (x as f32 * shade).min(255.0) as u8);
+        Rgba([r, g, b, 255])
+    })
+}
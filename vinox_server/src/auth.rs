@@ -0,0 +1,118 @@
+//! Secure mode. The server keeps a private key and only lets in clients holding a connect
+//! token signed with it. Tokens are issued by `vinox_server token <username>` which fills in
+//! a random client id and the username so neither can be picked by the client.
+
+use std::{
+    fmt, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, SystemTimeError},
+};
+
+use renet::transport::{ConnectToken, TokenGenerationError, NETCODE_KEY_BYTES};
+use vinox_common::prelude::{ConnectionData, HandshakeError, PROTOCOL_ID};
+
+use crate::config::Config;
+
+pub type PrivateKey = [u8; NETCODE_KEY_BYTES];
+
+#[derive(Debug)]
+pub enum AuthError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// The key file isn't exactly `NETCODE_KEY_BYTES` long
+    InvalidKey(PathBuf),
+    NoKeyConfigured,
+    Handshake(HandshakeError),
+    Token(TokenGenerationError),
+    /// The system clock is set before 1970
+    Clock(SystemTimeError),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            AuthError::InvalidKey(path) => write!(
+                f,
+                "{} is not a valid key, expected {NETCODE_KEY_BYTES} bytes",
+                path.display()
+            ),
+            AuthError::NoKeyConfigured => {
+                write!(
+                    f,
+                    "no private_key_path set in the config or on the command line"
+                )
+            }
+            AuthError::Handshake(e) => write!(f, "{e}"),
+            AuthError::Token(e) => write!(f, "failed to generate connect token: {e}"),
+            AuthError::Clock(e) => write!(f, "system clock is wrong: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+pub fn load_private_key(path: &Path) -> Result<PrivateKey, AuthError> {
+    let bytes = fs::read(path).map_err(|source| AuthError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    bytes
+        .try_into()
+        .map_err(|_| AuthError::InvalidKey(path.to_path_buf()))
+}
+
+/// Write a new random private key, refusing to overwrite an existing one
+pub fn generate_private_key(path: &Path) -> Result<(), AuthError> {
+    let key: PrivateKey = rand::random();
+    let io_error = |source| AuthError::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .map_err(io_error)?;
+    file.write_all(&key).map_err(io_error)
+}
+
+/// Sign a connect token for `username` to join the server described by `config`
+pub fn issue_token(config: &Config, username: &str) -> Result<ConnectToken, AuthError> {
+    let key_path = config
+        .private_key_path
+        .as_ref()
+        .ok_or(AuthError::NoKeyConfigured)?;
+    let private_key = load_private_key(key_path)?;
+    let user_data = ConnectionData::new(username)
+        .to_user_data()
+        .map_err(AuthError::Handshake)?;
+    let current_time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_err(AuthError::Clock)?;
+
+    ConnectToken::generate(
+        current_time,
+        PROTOCOL_ID,
+        config.token_expire_seconds,
+        rand::random(),
+        config.client_timeout_seconds,
+        vec![config.public_address()],
+        Some(&user_data),
+        &private_key,
+    )
+    .map_err(AuthError::Token)
+}
+
+/// Write a token where a client can pick it up
+pub fn write_token(token: &ConnectToken, path: &Path) -> Result<(), AuthError> {
+    let io_error = |source| AuthError::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+    let mut file = fs::File::create(path).map_err(io_error)?;
+    token.write(&mut file).map_err(io_error)
+}
@@ -0,0 +1,226 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    time::SystemTime,
+};
+
+use vinox_common::prelude::{
+    BlockPos, Chunk, ChunkPos, StorageError, WorldDatabase, WorldMetadata,
+};
+use vinox_generation::{WorldGenerator, SEA_LEVEL};
+
+/// Radius in chunks around spawn that is loaded when the server starts
+const SPAWN_RADIUS: i32 = 2;
+
+/// Keeps the chunks players are near in memory. Chunks come from the save if they have ever
+/// been changed and from the generator otherwise.
+pub struct ChunkManager {
+    pub database: WorldDatabase,
+    pub generator: WorldGenerator,
+    pub metadata: WorldMetadata,
+    chunks: HashMap<ChunkPos, Chunk>,
+    /// Chunks that differ from what is in the database
+    dirty: HashSet<ChunkPos>,
+}
+
+impl ChunkManager {
+    /// Open a world save creating a new world if it doesn't exist yet
+    pub fn open(path: impl AsRef<Path>, seed: Option<u64>) -> Result<Self, StorageError> {
+        let mut database = WorldDatabase::open(path)?;
+        let metadata = match database.load_metadata()? {
+            Some(metadata) => metadata,
+            None => {
+                let seed = seed.unwrap_or_else(|| {
+                    SystemTime::now()
+                        .duration_since(SystemTime::UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_nanos() as u64
+                });
+                let generator = WorldGenerator::new(seed);
+                let metadata = WorldMetadata {
+                    seed,
+                    spawn: BlockPos::new(0, generator.column(0, 0).height.max(SEA_LEVEL) + 1, 0),
+                    time: 0,
+                };
+                database.save_metadata(&metadata)?;
+                metadata
+            }
+        };
+        println!("Loaded world with seed {}", metadata.seed);
+
+        let mut manager = Self {
+            database,
+            generator: WorldGenerator::new(metadata.seed),
+            metadata,
+            chunks: HashMap::new(),
+            dirty: HashSet::new(),
+        };
+        manager.load_spawn()?;
+        Ok(manager)
+    }
+
+    fn load_spawn(&mut self) -> Result<(), StorageError> {
+        let spawn = self.metadata.spawn.chunk();
+        for x in -SPAWN_RADIUS..=SPAWN_RADIUS {
+            for y in -SPAWN_RADIUS..=SPAWN_RADIUS {
+                for z in -SPAWN_RADIUS..=SPAWN_RADIUS {
+                    self.load(ChunkPos::new(spawn.x + x, spawn.y + y, spawn.z + z))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Get a chunk loading or generating it if it isn't in memory yet
+    pub fn load(&mut self, pos: ChunkPos) -> Result<&Chunk, StorageError> {
+        if !self.chunks.contains_key(&pos) {
+            let chunk = match self.database.load_chunk(pos)? {
+                Some(chunk) => chunk,
+                None => self.generator.generate_chunk(pos),
+            };
+            self.chunks.insert(pos, chunk);
+        }
+        Ok(&self.chunks[&pos])
+    }
+
+    /// A chunk only if it is already loaded
+    pub fn get(&self, pos: ChunkPos) -> Option<&Chunk> {
+        self.chunks.get(&pos)
+    }
+
+    /// Mutable access to a loaded chunk. The chunk will be written out on the next save
+    pub fn get_mut(&mut self, pos: ChunkPos) -> Option<&mut Chunk> {
+        let chunk = self.chunks.get_mut(&pos)?;
+        self.dirty.insert(pos);
+        Some(chunk)
+    }
+
+    pub fn is_loaded(&self, pos: ChunkPos) -> bool {
+        self.chunks.contains_key(&pos)
+    }
+
+    /// Unload every chunk `keep` returns false for, except the ones around spawn
+    pub fn retain(&mut self, mut keep: impl FnMut(ChunkPos) -> bool) -> Result<(), StorageError> {
+        let spawn = self.metadata.spawn.chunk();
+        let unused: Vec<ChunkPos> = self
+            .chunks
+            .keys()
+            .copied()
+            .filter(|pos| pos.chebyshev_distance(spawn) > SPAWN_RADIUS as u32 && !keep(*pos))
+            .collect();
+        for pos in unused {
+            self.unload(pos)?;
+        }
+        Ok(())
+    }
+
+    /// Drop a chunk from memory saving it first if it was changed
+    pub fn unload(&mut self, pos: ChunkPos) -> Result<(), StorageError> {
+        if let Some(chunk) = self.chunks.remove(&pos) {
+            if self.dirty.remove(&pos) {
+                self.database.save_chunk(pos, &chunk)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write every changed chunk and the world metadata in one go
+    pub fn save(&mut self) -> Result<(), StorageError> {
+        let chunks = &self.chunks;
+        self.database.save_chunks(
+            self.dirty
+                .iter()
+                .filter_map(|pos| chunks.get(pos).map(|chunk| (*pos, chunk))),
+        )?;
+        self.dirty.clear();
+        self.database.save_metadata(&self.metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vinox_common::prelude::LocalPos;
+
+    use super::*;
+
+    const FAR: ChunkPos = ChunkPos::new(100, 0, 0);
+
+    fn manager() -> ChunkManager {
+        ChunkManager::open(":memory:", Some(7)).unwrap()
+    }
+
+    #[test]
+    fn seed_is_kept() {
+        let path = std::env::temp_dir().join(format!("chunks_{}.db", std::process::id()));
+        let spawn = {
+            let manager = ChunkManager::open(&path, Some(42)).unwrap();
+            assert_eq!(manager.metadata.seed, 42);
+            assert!(manager.is_loaded(manager.metadata.spawn.chunk()));
+            manager.metadata.spawn
+        };
+        // The seed flag only applies to new worlds
+        let manager = ChunkManager::open(&path, Some(1)).unwrap();
+        assert_eq!(manager.metadata.seed, 42);
+        assert_eq!(manager.metadata.spawn, spawn);
+        drop(manager);
+        for suffix in ["", "-wal", "-shm"] {
+            let mut file = path.clone().into_os_string();
+            file.push(suffix);
+            std::fs::remove_file(file).ok();
+        }
+    }
+
+    #[test]
+    fn changes_saved_on_unload() {
+        let mut manager = manager();
+        let generated = manager.load(FAR).unwrap().clone();
+        assert_eq!(generated, manager.generator.generate_chunk(FAR));
+
+        let local = LocalPos::new(1, 2, 3).unwrap();
+        manager.get_mut(FAR).unwrap().set(local, "test:changed");
+        manager.unload(FAR).unwrap();
+        assert!(!manager.is_loaded(FAR));
+        assert_eq!(manager.load(FAR).unwrap().get(local), "test:changed");
+
+        // Unchanged chunks never touch the database
+        let other = ChunkPos::new(-100, 0, 0);
+        manager.load(other).unwrap();
+        manager.unload(other).unwrap();
+        assert_eq!(manager.database.load_chunk(other).unwrap(), None);
+    }
+
+    #[test]
+    fn retain_keeps_spawn() {
+        let mut manager = manager();
+        let spawn = manager.metadata.spawn.chunk();
+        manager.load(FAR).unwrap();
+
+        manager.retain(|pos| pos == FAR).unwrap();
+        assert!(manager.is_loaded(FAR));
+        manager.retain(|_| false).unwrap();
+        assert!(!manager.is_loaded(FAR));
+        assert!(manager.is_loaded(spawn));
+        assert!(manager.get(spawn).is_some());
+    }
+
+    #[test]
+    fn save_writes_everything() {
+        let mut manager = manager();
+        let local = LocalPos::new(0, 0, 0).unwrap();
+        manager.load(FAR).unwrap();
+        manager.get_mut(FAR).unwrap().set(local, "test:changed");
+        manager.metadata.time = 99;
+        manager.save().unwrap();
+
+        assert_eq!(
+            manager
+                .database
+                .load_chunk(FAR)
+                .unwrap()
+                .unwrap()
+                .get(local),
+            "test:changed"
+        );
+        assert_eq!(manager.database.load_metadata().unwrap().unwrap().time, 99);
+    }
+}
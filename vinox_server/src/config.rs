@@ -0,0 +1,212 @@
+use std::{
+    fmt, fs, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use clap::{Parser, Subcommand};
+use serde::{Deserialize, Serialize};
+use vinox_common::prelude::DEFAULT_PORT;
+
+/// Largest view distance accepted, the number of chunks per player grows with its cube
+pub const MAX_VIEW_DISTANCE: u32 = 32;
+
+/// Command line flags. Anything given here overrides the config file
+#[derive(Parser, Debug)]
+#[command(about = "Vinox dedicated server")]
+pub struct Cli {
+    /// Config file to load, created with defaults if it doesn't exist
+    #[arg(long, short, default_value = "server.ron")]
+    pub config: PathBuf,
+    /// Address to bind the server socket to
+    #[arg(long)]
+    pub bind: Option<SocketAddr>,
+    /// Address clients connect to, defaults to the bind address
+    #[arg(long)]
+    pub public: Option<SocketAddr>,
+    #[arg(long)]
+    pub max_players: Option<usize>,
+    /// Path to the world save
+    #[arg(long)]
+    pub world: Option<PathBuf>,
+    /// Seed used when creating a new world
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Ticks per second
+    #[arg(long)]
+    pub tick_rate: Option<u32>,
+    #[arg(long)]
+    pub motd: Option<String>,
+    /// Radius in chunks sent to each player
+    #[arg(long)]
+    pub view_distance: Option<u32>,
+    /// Private key file, turns on secure mode where clients need a connect token
+    #[arg(long)]
+    pub private_key: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Generate a new private key for secure mode at the configured path
+    Keygen,
+    /// Sign a connect token for a player using the configured private key
+    Token {
+        username: String,
+        #[arg(long, short, default_value = "connect_token.bin")]
+        output: PathBuf,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Config {
+    pub bind_address: SocketAddr,
+    pub public_address: Option<SocketAddr>,
+    pub max_players: usize,
+    pub world_path: PathBuf,
+    pub seed: Option<u64>,
+    pub tick_rate: u32,
+    pub motd: String,
+    /// When set only clients with a connect token signed by this key may join
+    pub private_key_path: Option<PathBuf>,
+    /// How long an issued connect token can be used to connect
+    pub token_expire_seconds: u64,
+    /// Seconds without packets before a client using a connect token is dropped
+    pub client_timeout_seconds: i32,
+    /// Radius in chunks sent to each player
+    pub view_distance: u32,
+    /// Compressed chunk bytes queued for each player per tick
+    pub chunk_bytes_per_tick: usize,
+    /// Answer LAN discovery pings so the server shows up in clients' server browsers
+    pub lan_discovery: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind_address: SocketAddr::from(([0, 0, 0, 0], DEFAULT_PORT)),
+            public_address: None,
+            max_players: 64,
+            world_path: PathBuf::from("world.db"),
+            seed: None,
+            tick_rate: 30,
+            motd: "A Vinox server".to_string(),
+            private_key_path: None,
+            token_expire_seconds: 300,
+            client_timeout_seconds: 15,
+            view_distance: 8,
+            chunk_bytes_per_tick: 32 * 1024,
+            lan_discovery: true,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        source: ron::error::SpannedError,
+    },
+    Serialize(ron::Error),
+    InvalidTickRate,
+    InvalidViewDistance(u32),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            ConfigError::Parse { path, source } => write!(f, "{}: {source}", path.display()),
+            ConfigError::Serialize(e) => write!(f, "failed to write config: {e}"),
+            ConfigError::InvalidTickRate => write!(f, "tick rate has to be above 0"),
+            ConfigError::InvalidViewDistance(x) => write!(
+                f,
+                "view distance {x} is too far, it can be at most {MAX_VIEW_DISTANCE}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Load the config file named by the command line and apply the flags on top of it
+    pub fn from_cli(cli: &Cli) -> Result<Self, ConfigError> {
+        let mut config = Config::load_or_create(&cli.config)?;
+        if let Some(bind) = cli.bind {
+            config.bind_address = bind;
+        }
+        if let Some(public) = cli.public {
+            config.public_address = Some(public);
+        }
+        if let Some(max_players) = cli.max_players {
+            config.max_players = max_players;
+        }
+        if let Some(world) = cli.world.clone() {
+            config.world_path = world;
+        }
+        if let Some(seed) = cli.seed {
+            config.seed = Some(seed);
+        }
+        if let Some(tick_rate) = cli.tick_rate {
+            config.tick_rate = tick_rate;
+        }
+        if let Some(motd) = cli.motd.clone() {
+            config.motd = motd;
+        }
+        if let Some(view_distance) = cli.view_distance {
+            config.view_distance = view_distance;
+        }
+        if let Some(private_key) = cli.private_key.clone() {
+            config.private_key_path = Some(private_key);
+        }
+        if config.tick_rate == 0 {
+            return Err(ConfigError::InvalidTickRate);
+        }
+        if config.view_distance > MAX_VIEW_DISTANCE {
+            return Err(ConfigError::InvalidViewDistance(config.view_distance));
+        }
+        Ok(config)
+    }
+
+    /// Read a config file writing out the defaults first if there is none
+    pub fn load_or_create(path: &Path) -> Result<Self, ConfigError> {
+        let io_error = |source| ConfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        };
+        match fs::read_to_string(path) {
+            Ok(source) => ron::from_str(&source).map_err(|source| ConfigError::Parse {
+                path: path.to_path_buf(),
+                source,
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let config = Config::default();
+                let source = ron::ser::to_string_pretty(&config, ron::ser::PrettyConfig::default())
+                    .map_err(ConfigError::Serialize)?;
+                fs::write(path, source).map_err(io_error)?;
+                println!("Wrote default config to {}", path.display());
+                Ok(config)
+            }
+            Err(e) => Err(io_error(e)),
+        }
+    }
+
+    /// The address clients should use to reach this server
+    pub fn public_address(&self) -> SocketAddr {
+        match self.public_address {
+            Some(address) => address,
+            // Can't hand out 0.0.0.0 to clients so fall back to loopback
+            None if self.bind_address.ip().is_unspecified() => {
+                SocketAddr::from(([127, 0, 0, 1], self.bind_address.port()))
+            }
+            None => self.bind_address,
+        }
+    }
+}
@@ -1,28 +1,246 @@
-use std::time::Duration;
+use std::{collections::HashMap, error::Error, time::Duration};
 
-use crate::network::state::NetworkState;
+use hecs::{Entity, World};
+use vinox_common::prelude::{
+    ClientMessage, Health, NetworkId, Player, PlayerRecord, Position, Rotation, ServerInfo,
+    ServerMessage, Velocity, GAME_VERSION, PROTOCOL_ID,
+};
+
+use crate::{
+    chunks::ChunkManager,
+    config::Config,
+    network::{
+        discovery::Discovery,
+        state::{NetworkEvent, NetworkState},
+    },
+    streaming::ChunkView,
+    systems::{MovementInput, Schedule, Synced, TickContext},
+};
+
+/// Save changed chunks every 30 seconds
+const AUTOSAVE_SECONDS: u64 = 30;
+const PLAYER_HEALTH: f32 = 20.0;
 
 pub struct VinoxServer {
+    pub config: Config,
     pub network: NetworkState,
+    pub chunks: ChunkManager,
+    pub world: World,
+    pub schedule: Schedule,
+    /// Player entity of each connected client
+    pub players: HashMap<u64, Entity>,
+    /// `None` when turned off in the config or the port was taken
+    pub discovery: Option<Discovery>,
 }
 
 impl VinoxServer {
-    pub fn new() -> Self {
-        Self {
-            network: NetworkState::new(),
-        }
+    pub fn new(config: Config) -> Result<Self, Box<dyn Error>> {
+        println!("{}", config.motd);
+        let discovery = match config.lan_discovery.then(Discovery::new) {
+            Some(Ok(discovery)) => Some(discovery),
+            Some(Err(e)) => {
+                println!("LAN discovery disabled: {e}");
+                None
+            }
+            None => None,
+        };
+        Ok(Self {
+            network: NetworkState::new(&config)?,
+            chunks: ChunkManager::open(&config.world_path, config.seed)?,
+            world: World::new(),
+            schedule: Schedule::default(),
+            players: HashMap::new(),
+            discovery,
+            config,
+        })
     }
 
     pub fn update(&mut self, duration: Duration) {
         // Uncapped or vsync frame rate
         self.network.update(duration);
+        for event in self.network.drain_events() {
+            match event {
+                NetworkEvent::PlayerJoined {
+                    client_id,
+                    username,
+                } => self.join(client_id, username),
+                NetworkEvent::PlayerLeft {
+                    client_id,
+                    username,
+                } => self.leave(client_id, username),
+                NetworkEvent::Message { client_id, message } => {
+                    self.handle_message(client_id, message)
+                }
+            }
+        }
+        self.network.send_packets();
+
+        if let Some(discovery) = &self.discovery {
+            discovery.respond(|| ServerInfo {
+                motd: self.config.motd.clone(),
+                players: self.network.players.len() as u32,
+                max_players: self.config.max_players as u32,
+                protocol_id: PROTOCOL_ID,
+                game_version: GAME_VERSION.to_string(),
+                port: self.config.public_address().port(),
+            });
+        }
+    }
+
+    fn join(&mut self, client_id: u64, username: String) {
+        let spawn = Position::from(self.chunks.metadata.spawn.center());
+        let (position, rotation) = match self.chunks.database.load_player(&username) {
+            Ok(Some(record)) => (
+                record.position,
+                Rotation {
+                    yaw: record.yaw,
+                    pitch: record.pitch,
+                },
+            ),
+            Ok(None) => (spawn, Rotation::default()),
+            Err(e) => {
+                println!("Failed to load player {username}: {e}");
+                (spawn, Rotation::default())
+            }
+        };
+
+        self.network.send(
+            client_id,
+            &ServerMessage::Welcome {
+                client_id,
+                position,
+                motd: self.config.motd.clone(),
+            },
+        );
+        // Let the new player know who is already here
+        for (_, (player, network_id, position)) in self
+            .world
+            .query::<(&Player, &NetworkId, &Position)>()
+            .iter()
+        {
+            self.network.send(
+                client_id,
+                &ServerMessage::PlayerJoined {
+                    client_id: network_id.0,
+                    username: player.username.clone(),
+                    position: *position,
+                },
+            );
+        }
+        self.network.broadcast_except(
+            Some(client_id),
+            &ServerMessage::PlayerJoined {
+                client_id,
+                username: username.clone(),
+                position,
+            },
+        );
+        self.network.broadcast(&ServerMessage::Chat {
+            sender: None,
+            message: format!("{username} joined the game"),
+        });
+
+        let entity = self.world.spawn((
+            Player { username },
+            NetworkId(client_id),
+            position,
+            rotation,
+            Velocity::default(),
+            Health::new(PLAYER_HEALTH),
+            MovementInput::default(),
+            Synced { position, rotation },
+            ChunkView::default(),
+        ));
+        self.players.insert(client_id, entity);
+    }
+
+    fn leave(&mut self, client_id: u64, username: String) {
+        if let Some(entity) = self.players.remove(&client_id) {
+            if let Ok((position, rotation)) =
+                self.world.query_one_mut::<(&Position, &Rotation)>(entity)
+            {
+                let record = PlayerRecord {
+                    username: username.clone(),
+                    position: *position,
+                    yaw: rotation.yaw,
+                    pitch: rotation.pitch,
+                };
+                if let Err(e) = self.chunks.database.save_players([&record]) {
+                    println!("Failed to save player {username}: {e}");
+                }
+            }
+            self.world.despawn(entity).ok();
+        }
+        self.network
+            .broadcast(&ServerMessage::PlayerLeft { client_id });
+        self.network.broadcast(&ServerMessage::Chat {
+            sender: None,
+            message: format!("{username} left the game"),
+        });
+    }
+
+    fn handle_message(&mut self, client_id: u64, message: ClientMessage) {
+        match message {
+            ClientMessage::Leave => self.network.server.disconnect(client_id),
+            ClientMessage::Chat { message } => {
+                let sender = self.network.players.get(&client_id).cloned();
+                self.network
+                    .broadcast(&ServerMessage::Chat { sender, message });
+            }
+            ClientMessage::Movement {
+                position,
+                yaw,
+                pitch,
+            } => {
+                let Some(&entity) = self.players.get(&client_id) else {
+                    return;
+                };
+                if let Ok(mut input) = self.world.get::<&mut MovementInput>(entity) {
+                    input.0 = Some((position, Rotation { yaw, pitch }));
+                }
+            }
+            _ => {}
+        }
     }
 
     pub fn tick(&mut self) {
-        // Fixed tick update function should be 30ticks per second
+        // Fixed tick update function runs config.tick_rate times per second
+        let mut ctx = TickContext {
+            config: &self.config,
+            network: &mut self.network,
+            chunks: &mut self.chunks,
+            delta: 1.0 / self.config.tick_rate as f32,
+        };
+        self.schedule.run(&mut self.world, &mut ctx);
+
+        self.chunks.metadata.time += 1;
+        if self.chunks.metadata.time % (AUTOSAVE_SECONDS * self.config.tick_rate as u64) == 0 {
+            if let Err(e) = self.chunks.save() {
+                println!("Failed to save world: {e}");
+            }
+        }
     }
 
+    /// Save everything and kick everyone. Players still online never get a leave event so
+    /// they are saved here
     pub fn exit(&mut self) {
+        let records: Vec<PlayerRecord> = self
+            .world
+            .query::<(&Player, &Position, &Rotation)>()
+            .iter()
+            .map(|(_, (player, position, rotation))| PlayerRecord {
+                username: player.username.clone(),
+                position: *position,
+                yaw: rotation.yaw,
+                pitch: rotation.pitch,
+            })
+            .collect();
+        if let Err(e) = self.chunks.database.save_players(&records) {
+            println!("Failed to save players: {e}");
+        }
         self.network.exit();
+        if let Err(e) = self.chunks.save() {
+            println!("Failed to save world: {e}");
+        }
     }
 }
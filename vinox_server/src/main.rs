@@ -1,8 +1,21 @@
+mod auth;
+mod chunks;
+mod config;
 mod game;
 mod network;
+mod streaming;
+mod systems;
 
-use std::time::Duration;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
+use clap::Parser;
+use config::{Cli, Command, Config};
 use game::VinoxServer;
 use game_loop::game_loop;
 
@@ -12,17 +25,75 @@ use game_loop::game_loop;
 //====================================//
 
 fn main() {
-    let vinox = VinoxServer::new();
+    let cli = Cli::parse();
+    let config = match Config::from_cli(&cli) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Failed to load config: {e}");
+            std::process::exit(1);
+        }
+    };
 
-    game_loop(
+    match &cli.command {
+        Some(Command::Keygen) => {
+            let Some(path) = &config.private_key_path else {
+                eprintln!("Set private_key_path in the config or pass --private-key");
+                std::process::exit(1);
+            };
+            match auth::generate_private_key(path) {
+                Ok(()) => println!("Wrote private key to {}", path.display()),
+                Err(e) => {
+                    eprintln!("Failed to generate key: {e}");
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some(Command::Token { username, output }) => {
+            match auth::issue_token(&config, username)
+                .and_then(|token| auth::write_token(&token, output))
+            {
+                Ok(()) => println!("Wrote connect token for {username} to {}", output.display()),
+                Err(e) => {
+                    eprintln!("Failed to issue token: {e}");
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        None => {}
+    }
+
+    let tick_rate = config.tick_rate;
+    let vinox = match VinoxServer::new(config) {
+        Ok(vinox) => vinox,
+        Err(e) => {
+            eprintln!("Failed to start server: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    // Ctrl-C and SIGTERM stop the loop so players and chunks get saved before exiting
+    let running = Arc::new(AtomicBool::new(true));
+    let handler_running = running.clone();
+    if let Err(e) = ctrlc::set_handler(move || handler_running.store(false, Ordering::SeqCst)) {
+        println!("Failed to set shutdown handler: {e}");
+    }
+
+    let mut stopped = game_loop(
         vinox,
-        30,
+        tick_rate,
         0.1,
         |g| {
             g.game.tick();
         },
         |g| {
             g.game.update(Duration::from_secs_f64(g.last_frame_time()));
+            if !running.load(Ordering::SeqCst) {
+                g.exit();
+            }
         },
     );
+    println!("Shutting down");
+    stopped.game.exit();
 }
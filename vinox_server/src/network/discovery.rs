@@ -0,0 +1,74 @@
+use std::{
+    io,
+    net::{SocketAddr, UdpSocket},
+};
+
+use socket2::{Domain, Protocol, Socket, Type};
+use vinox_common::prelude::{DiscoveryPacket, ServerInfo, DISCOVERY_PORT};
+
+/// Answers LAN discovery pings from clients browsing for servers
+pub struct Discovery {
+    socket: UdpSocket,
+}
+
+impl Discovery {
+    pub fn new() -> io::Result<Self> {
+        let socket = bind_shared(SocketAddr::from(([0, 0, 0, 0], DISCOVERY_PORT)))?;
+        socket.set_nonblocking(true)?;
+        Ok(Self { socket })
+    }
+
+    /// Reply to every ping received since the last call. `info` is only built if someone asked
+    pub fn respond(&self, info: impl Fn() -> ServerInfo) {
+        let mut buffer = [0; 64];
+        loop {
+            let from = match self.socket.recv_from(&mut buffer) {
+                Ok((len, from)) => {
+                    if DiscoveryPacket::from_bytes(&buffer[..len]) != Some(DiscoveryPacket::Ping) {
+                        continue;
+                    }
+                    from
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    println!("Discovery error: {e}");
+                    return;
+                }
+            };
+            let pong = DiscoveryPacket::Pong(info()).to_bytes();
+            if let Err(e) = self.socket.send_to(&pong, from) {
+                println!("Failed to answer discovery ping from {from}: {e}");
+            }
+        }
+    }
+}
+
+/// Bind so several servers on one machine can all listen on the discovery port. Pings are
+/// broadcast so each of them gets a copy. Linux only needs `SO_REUSEADDR` for this, macOS
+/// and the BSDs need `SO_REUSEPORT` as well. Windows shares the port with `SO_REUSEADDR`
+fn bind_shared(address: SocketAddr) -> io::Result<UdpSocket> {
+    let socket = Socket::new(
+        Domain::for_address(address),
+        Type::DGRAM,
+        Some(Protocol::UDP),
+    )?;
+    socket.set_reuse_address(true)?;
+    #[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+    socket.set_reuse_port(true)?;
+    socket.bind(&address.into())?;
+    Ok(socket.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn servers_share_the_port() {
+        // Any port will do, it just has to be the same one twice
+        let first = bind_shared(SocketAddr::from(([0, 0, 0, 0], 0))).unwrap();
+        let address = SocketAddr::from(([0, 0, 0, 0], first.local_addr().unwrap().port()));
+        let second = bind_shared(address).unwrap();
+        assert_eq!(first.local_addr().unwrap(), second.local_addr().unwrap());
+    }
+}
@@ -1,61 +1,263 @@
 use renet::{
     transport::{NetcodeServerTransport, ServerAuthentication, ServerConfig},
-    ConnectionConfig, RenetServer, ServerEvent,
+    RenetServer, ServerEvent,
 };
 use std::{
-    net::UdpSocket,
-    time::{Duration, SystemTime},
+    collections::HashMap,
+    fmt, io,
+    net::{SocketAddr, UdpSocket},
+    time::{Duration, SystemTime, SystemTimeError},
 };
-use vinox_common::prelude::PROTOCOL_ID;
+use vinox_common::prelude::{
+    connection_config, Channel, ClientMessage, ConnectionData, RejectReason, ServerMessage,
+    PROTOCOL_ID,
+};
+
+use crate::{
+    auth::{load_private_key, AuthError},
+    config::Config,
+};
+
+#[derive(Debug)]
+pub enum NetworkError {
+    Bind {
+        address: SocketAddr,
+        source: io::Error,
+    },
+    Transport(io::Error),
+    Auth(AuthError),
+    /// The system clock is set before 1970
+    Clock(SystemTimeError),
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkError::Bind { address, source } => {
+                write!(f, "failed to bind to {address}: {source}")
+            }
+            NetworkError::Transport(e) => write!(f, "failed to start transport: {e}"),
+            NetworkError::Auth(e) => write!(f, "{e}"),
+            NetworkError::Clock(e) => write!(f, "system clock is wrong: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for NetworkError {}
+
+/// Something that happened on the network for the game to react to
+#[derive(Debug)]
+pub enum NetworkEvent {
+    /// A client passed the handshake and is now a player
+    PlayerJoined {
+        client_id: u64,
+        username: String,
+    },
+    PlayerLeft {
+        client_id: u64,
+        username: String,
+    },
+    Message {
+        client_id: u64,
+        message: ClientMessage,
+    },
+}
 
 pub struct NetworkState {
     pub server: RenetServer,
     pub transport: NetcodeServerTransport,
+    /// Usernames of every client that passed the handshake
+    pub players: HashMap<u64, String>,
+    events: Vec<NetworkEvent>,
+    /// Clients that were sent a `Disconnect` and get dropped once it has gone out
+    kicks: Vec<u64>,
 }
 
 impl NetworkState {
-    pub fn new() -> Self {
-        let socket = UdpSocket::bind("0.0.0.0:56552").unwrap();
-        let public_addr = "127.0.0.1:56552".parse().unwrap();
+    pub fn new(config: &Config) -> Result<Self, NetworkError> {
+        let socket = UdpSocket::bind(config.bind_address).map_err(|source| NetworkError::Bind {
+            address: config.bind_address,
+            source,
+        })?;
+        let public_addr = config.public_address();
         println!("Hosting server on: {public_addr}");
+        let authentication = match &config.private_key_path {
+            Some(path) => {
+                println!("Secure mode enabled, clients need a connect token");
+                ServerAuthentication::Secure {
+                    private_key: load_private_key(path).map_err(NetworkError::Auth)?,
+                }
+            }
+            None => ServerAuthentication::Unsecure,
+        };
         let server_config = ServerConfig {
-            max_clients: 64,
+            max_clients: config.max_players,
             protocol_id: PROTOCOL_ID,
             public_addr,
-            authentication: ServerAuthentication::Unsecure,
+            authentication,
         };
 
         let current_time = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap();
-        let transport = NetcodeServerTransport::new(current_time, server_config, socket).unwrap();
+            .map_err(NetworkError::Clock)?;
+        let transport = NetcodeServerTransport::new(current_time, server_config, socket)
+            .map_err(NetworkError::Transport)?;
 
-        let server: RenetServer = RenetServer::new(ConnectionConfig::default());
-        Self { server, transport }
+        let server: RenetServer = RenetServer::new(connection_config());
+        Ok(Self {
+            server,
+            transport,
+            players: HashMap::new(),
+            events: Vec::new(),
+            kicks: Vec::new(),
+        })
     }
 
     pub fn update(&mut self, duration: Duration) {
         // Uncapped or vsync frame rate
+        // Kicks queued last update have had their reason sent by now
+        for client_id in self.kicks.drain(..) {
+            self.server.disconnect(client_id);
+        }
+
         self.server.update(duration);
-        self.transport.update(duration, &mut self.server).unwrap();
+        if let Err(e) = self.transport.update(duration, &mut self.server) {
+            println!("Transport error: {e}");
+        }
 
         while let Some(event) = self.server.get_event() {
             match event {
-                ServerEvent::ClientConnected { client_id } => {
-                    // let user_data = self.transport.user_data(client_id).unwrap();
-                    println!("Client {} Connected!", client_id);
+                ServerEvent::ClientConnected { client_id } => self.handshake(client_id),
+                ServerEvent::ClientDisconnected { client_id, reason } => {
+                    println!("Client {client_id} Disconnected: {reason}");
+                    if let Some(username) = self.players.remove(&client_id) {
+                        self.events.push(NetworkEvent::PlayerLeft {
+                            client_id,
+                            username,
+                        });
+                    }
                 }
-                ServerEvent::ClientDisconnected {
-                    client_id,
-                    reason: _,
-                } => {
-                    println!("Client {} Disconnected!", client_id);
+            }
+        }
+
+        for client_id in self.players.keys().copied().collect::<Vec<_>>() {
+            for channel in Channel::ALL {
+                while let Some(bytes) = self.server.receive_message(client_id, channel) {
+                    match ClientMessage::from_bytes(&bytes) {
+                        Ok(message) => self
+                            .events
+                            .push(NetworkEvent::Message { client_id, message }),
+                        Err(e) => println!("Bad message from client {client_id}: {e}"),
+                    }
                 }
             }
         }
     }
 
+    /// Check the username and versions a client sent and either accept or kick it
+    fn handshake(&mut self, client_id: u64) {
+        let data = match self.transport.user_data(client_id) {
+            Some(x) => ConnectionData::from_user_data(&x).map_err(|e| {
+                println!("Client {client_id} sent bad connection data: {e}");
+                RejectReason::BadConnectionData
+            }),
+            None => Err(RejectReason::BadConnectionData),
+        }
+        .and_then(|data| data.validate().map(|_| data));
+        let data = match data {
+            Ok(data) if self.players.values().any(|x| *x == data.username) => {
+                Err(RejectReason::DuplicateName(data.username))
+            }
+            data => data,
+        };
+        match data {
+            Ok(data) => {
+                println!("Client {client_id} Connected as {}", data.username);
+                self.players.insert(client_id, data.username.clone());
+                self.events.push(NetworkEvent::PlayerJoined {
+                    client_id,
+                    username: data.username,
+                });
+            }
+            Err(reason) => {
+                println!("Rejected client {client_id}: {reason}");
+                self.kick(client_id, reason);
+            }
+        }
+    }
+
+    /// Tell a client why and then disconnect it
+    pub fn kick(&mut self, client_id: u64, reason: RejectReason) {
+        self.send(client_id, &ServerMessage::Disconnect { reason });
+        self.kicks.push(client_id);
+    }
+
+    /// Every event since the last call
+    pub fn drain_events(&mut self) -> Vec<NetworkEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    pub fn send(&mut self, client_id: u64, message: &ServerMessage) {
+        match message.to_bytes() {
+            Ok(bytes) => self
+                .server
+                .send_message(client_id, message.channel(), bytes),
+            Err(e) => println!("Failed to send message: {e}"),
+        }
+    }
+
+    /// Send only if the message fits in what's left of its channel. Renet disconnects a
+    /// client whose channel overflows so bulk data has to wait instead. Returns whether the
+    /// message was sent
+    pub fn try_send(&mut self, client_id: u64, message: &ServerMessage) -> bool {
+        let bytes = match message.to_bytes() {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                println!("Failed to send message: {e}");
+                // Trying again won't help
+                return true;
+            }
+        };
+        if !self
+            .server
+            .can_send_message(client_id, message.channel(), bytes.len())
+        {
+            return false;
+        }
+        self.server
+            .send_message(client_id, message.channel(), bytes);
+        true
+    }
+
+    /// Send to every player that finished the handshake
+    pub fn broadcast(&mut self, message: &ServerMessage) {
+        self.broadcast_except(None, message);
+    }
+
+    /// Send to every player except `except`
+    pub fn broadcast_except(&mut self, except: Option<u64>, message: &ServerMessage) {
+        let bytes = match message.to_bytes() {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                println!("Failed to send message: {e}");
+                return;
+            }
+        };
+        for client_id in self.players.keys() {
+            if Some(*client_id) != except {
+                self.server
+                    .send_message(*client_id, message.channel(), bytes.clone());
+            }
+        }
+    }
+
+    /// Flush every queued message to the socket
+    pub fn send_packets(&mut self) {
+        self.transport.send_packets(&mut self.server);
+    }
+
     pub fn exit(&mut self) {
         self.server.disconnect_all();
+        self.send_packets();
     }
 }
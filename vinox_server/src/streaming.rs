@@ -0,0 +1,336 @@
+//! Sends each player the chunks within their view distance, nearest first, and tells them
+//! when chunks go out of range.
+
+use std::collections::HashSet;
+
+use hecs::World;
+use vinox_common::prelude::{ChunkPos, NetworkId, Position, ServerMessage};
+
+use crate::systems::TickContext;
+
+/// How long a chunk that failed to load or encode waits before it is tried again
+const RETRY_SECONDS: u64 = 5;
+
+/// What became of a chunk handed out by `ChunkView::send_queued`
+enum SendOutcome {
+    /// Went out taking this many bytes
+    Sent(usize),
+    /// The channel is full, it waits for the next tick
+    Full,
+    /// Couldn't be loaded or encoded
+    Failed,
+}
+
+/// Chunks a player has been sent and the ones still waiting to go out
+#[derive(Default)]
+pub struct ChunkView {
+    /// Chunk the player was in when the queue was last built
+    center: Option<ChunkPos>,
+    sent: HashSet<ChunkPos>,
+    /// Sorted furthest first so the nearest chunk can be popped off the end
+    queue: Vec<ChunkPos>,
+    /// Unloads that didn't fit in the chunk channel yet
+    unload: Vec<ChunkPos>,
+    /// Chunks that failed to load or encode and the tick to try them again on
+    failed: Vec<(ChunkPos, u64)>,
+}
+
+impl ChunkView {
+    /// Rebuild the queue and work out what to unload after the player changed chunks.
+    /// `view_distance` is at most `MAX_VIEW_DISTANCE` so none of this can overflow
+    fn recenter(&mut self, center: ChunkPos, view_distance: i32) {
+        self.center = Some(center);
+        let in_range = |pos: ChunkPos| pos.distance_squared(center) <= view_distance.pow(2);
+        // Back in range before the unload went out, it gets sent again instead
+        self.unload.retain(|pos| !in_range(*pos));
+        self.failed.retain(|(pos, _)| in_range(*pos));
+
+        self.queue.clear();
+        // Only walk the sphere, not the whole cube around it
+        for x in -view_distance..=view_distance {
+            for y in -view_distance..=view_distance {
+                let rest = view_distance.pow(2) - x * x - y * y;
+                if rest < 0 {
+                    continue;
+                }
+                let depth = (rest as f64).sqrt() as i32;
+                for z in -depth..=depth {
+                    let pos = ChunkPos::new(center.x + x, center.y + y, center.z + z);
+                    if !self.sent.contains(&pos) && !self.failed.iter().any(|(x, _)| *x == pos) {
+                        self.queue.push(pos);
+                    }
+                }
+            }
+        }
+        self.queue
+            .sort_unstable_by_key(|pos| std::cmp::Reverse(pos.distance_squared(center)));
+
+        // One chunk of slack so walking back and forth over a border doesn't resend chunks
+        let keep_distance = (view_distance + 1).pow(2);
+        self.sent.retain(|pos| {
+            let keep = pos.distance_squared(center) <= keep_distance;
+            if !keep {
+                self.unload.push(*pos);
+            }
+            keep
+        });
+    }
+
+    /// Hold back a chunk that couldn't be sent until `retry_tick`
+    fn fail(&mut self, pos: ChunkPos, retry_tick: u64) {
+        self.failed.push((pos, retry_tick));
+    }
+
+    /// Put failed chunks whose wait is over back in the queue
+    fn retry(&mut self, tick: u64) {
+        let Some(center) = self.center else {
+            return;
+        };
+        let before = self.queue.len();
+        self.failed.retain(|&(pos, retry_tick)| {
+            if retry_tick > tick {
+                return true;
+            }
+            self.queue.push(pos);
+            false
+        });
+        if self.queue.len() != before {
+            self.queue
+                .sort_by_key(|pos| std::cmp::Reverse(pos.distance_squared(center)));
+        }
+    }
+
+    /// Hand queued chunks nearest first to `send` until `budget` bytes went out or the
+    /// channel is full. Always sends at least one chunk so a big one can't stall the queue
+    fn send_queued(
+        &mut self,
+        mut budget: usize,
+        retry_tick: u64,
+        mut send: impl FnMut(ChunkPos) -> SendOutcome,
+    ) {
+        while let Some(pos) = self.queue.pop() {
+            match send(pos) {
+                SendOutcome::Sent(size) => {
+                    self.sent.insert(pos);
+                    if size >= budget {
+                        break;
+                    }
+                    budget -= size;
+                }
+                SendOutcome::Full => {
+                    self.queue.push(pos);
+                    break;
+                }
+                SendOutcome::Failed => self.fail(pos, retry_tick),
+            }
+        }
+    }
+
+    /// Is the chunk sent or going to be sent to this player
+    fn wants(&self, pos: ChunkPos, view_distance: i32) -> bool {
+        self.sent.contains(&pos)
+            || self
+                .center
+                .is_some_and(|center| pos.distance_squared(center) <= view_distance.pow(2))
+    }
+}
+
+pub fn stream_chunks(world: &mut World, ctx: &mut TickContext) {
+    let view_distance = ctx.config.view_distance as i32;
+    let mut moved = false;
+
+    for (_, (network_id, position, view)) in
+        world.query_mut::<(&NetworkId, &Position, &mut ChunkView)>()
+    {
+        let center = ChunkPos::from(*position);
+        if view.center != Some(center) {
+            moved = true;
+            view.recenter(center, view_distance);
+        }
+        view.retry(ctx.chunks.metadata.time);
+
+        // Anything that doesn't fit in the channel waits for the next tick
+        while let Some(&position) = view.unload.last() {
+            if !ctx
+                .network
+                .try_send(network_id.0, &ServerMessage::UnloadChunk { position })
+            {
+                break;
+            }
+            view.unload.pop();
+        }
+        if !view.unload.is_empty() {
+            continue;
+        }
+
+        let retry_tick = ctx.chunks.metadata.time + RETRY_SECONDS * ctx.config.tick_rate as u64;
+        let (chunks, network) = (&mut *ctx.chunks, &mut *ctx.network);
+        view.send_queued(ctx.config.chunk_bytes_per_tick, retry_tick, |position| {
+            let data = match chunks.load(position).map(|x| x.to_compressed_bytes()) {
+                Ok(Ok(data)) => data,
+                Ok(Err(e)) => {
+                    println!("Failed to encode chunk {position:?}: {e}");
+                    return SendOutcome::Failed;
+                }
+                Err(e) => {
+                    println!("Failed to load chunk {position:?}: {e}");
+                    return SendOutcome::Failed;
+                }
+            };
+            let size = data.len();
+            if network.try_send(network_id.0, &ServerMessage::ChunkData { position, data }) {
+                SendOutcome::Sent(size)
+            } else {
+                SendOutcome::Full
+            }
+        });
+    }
+
+    // Only worth looking for unused chunks once someone has walked away from some
+    if moved {
+        let views: Vec<&ChunkView> = world
+            .query_mut::<&ChunkView>()
+            .into_iter()
+            .map(|(_, view)| view)
+            .collect();
+        if let Err(e) = ctx
+            .chunks
+            .retain(|pos| views.iter().any(|view| view.wants(pos, view_distance)))
+        {
+            println!("Failed to unload chunks: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGIN: ChunkPos = ChunkPos::new(0, 0, 0);
+
+    /// Send everything queued as if it all fit
+    fn send_all(view: &mut ChunkView) {
+        view.send_queued(usize::MAX, 0, |_| SendOutcome::Sent(1));
+        assert!(view.queue.is_empty());
+    }
+
+    #[test]
+    fn nearest_first() {
+        let mut view = ChunkView::default();
+        view.recenter(ORIGIN, 2);
+        // Every chunk within the sphere and nothing else
+        assert_eq!(view.queue.len(), 33);
+        assert!(view.queue.iter().all(|x| x.distance_squared(ORIGIN) <= 4));
+
+        let mut order = Vec::new();
+        view.send_queued(usize::MAX, 0, |pos| {
+            order.push(pos.distance_squared(ORIGIN));
+            SendOutcome::Sent(1)
+        });
+        assert_eq!(order.len(), 33);
+        assert_eq!(order[0], 0);
+        assert!(order.windows(2).all(|x| x[0] <= x[1]));
+    }
+
+    #[test]
+    fn unload_out_of_range() {
+        let mut view = ChunkView::default();
+        view.recenter(ORIGIN, 2);
+        send_all(&mut view);
+
+        // One chunk over everything sent is still within the slack
+        let step = ChunkPos::new(1, 0, 0);
+        view.recenter(step, 2);
+        assert!(view.unload.is_empty());
+        assert!(view.queue.iter().all(|x| !view.sent.contains(x)));
+        assert!(view.queue.contains(&ChunkPos::new(3, 0, 0)));
+        send_all(&mut view);
+
+        // Two over and the far side is more than a chunk out of range
+        let far = ChunkPos::new(3, 0, 0);
+        view.recenter(far, 2);
+        assert!(view.unload.contains(&ChunkPos::new(-2, 0, 0)));
+        assert!(view.unload.contains(&ChunkPos::new(-1, 0, 0)));
+        assert!(view.unload.iter().all(|x| x.distance_squared(far) > 9));
+        assert!(view.unload.iter().all(|x| !view.sent.contains(x)));
+        assert!(!view.wants(ChunkPos::new(-1, 0, 0), 2));
+
+        // Walking back before the unloads went out cancels the ones back in range
+        view.recenter(ORIGIN, 2);
+        assert!(view.unload.iter().all(|x| x.distance_squared(ORIGIN) > 4));
+        assert!(view.queue.contains(&ChunkPos::new(-1, 0, 0)));
+    }
+
+    #[test]
+    fn wants() {
+        let mut view = ChunkView::default();
+        assert!(!view.wants(ORIGIN, 2));
+        view.recenter(ORIGIN, 2);
+        assert!(view.wants(ChunkPos::new(0, 2, 0), 2));
+        assert!(!view.wants(ChunkPos::new(0, 3, 0), 2));
+        send_all(&mut view);
+
+        // Sent chunks in the slack are still wanted
+        view.recenter(ChunkPos::new(0, -1, 0), 2);
+        assert!(view.wants(ChunkPos::new(0, 2, 0), 2));
+    }
+
+    #[test]
+    fn byte_budget() {
+        let mut view = ChunkView::default();
+        view.recenter(ORIGIN, 2);
+        let mut sent = 0;
+        view.send_queued(25, 0, |_| {
+            sent += 1;
+            SendOutcome::Sent(10)
+        });
+        // 10 + 10 stays under 25, the third goes over and ends the tick
+        assert_eq!(sent, 3);
+        assert_eq!(view.sent.len(), 3);
+
+        // A chunk bigger than the whole budget still goes out alone
+        sent = 0;
+        view.send_queued(5, 0, |_| {
+            sent += 1;
+            SendOutcome::Sent(100)
+        });
+        assert_eq!(sent, 1);
+
+        // A full channel keeps the chunk at the front of the queue
+        let next = *view.queue.last().unwrap();
+        view.send_queued(25, 0, |_| SendOutcome::Full);
+        assert_eq!(view.queue.last(), Some(&next));
+        assert!(!view.sent.contains(&next));
+    }
+
+    #[test]
+    fn failed_chunks_retry() {
+        let mut view = ChunkView::default();
+        view.recenter(ORIGIN, 1);
+        view.send_queued(usize::MAX, 10, |pos| {
+            if pos == ORIGIN {
+                SendOutcome::Failed
+            } else {
+                SendOutcome::Sent(1)
+            }
+        });
+        assert!(view.queue.is_empty());
+        assert!(!view.sent.contains(&ORIGIN));
+
+        // Crossing a border doesn't skip the wait
+        view.recenter(ChunkPos::new(1, 0, 0), 1);
+        assert!(!view.queue.contains(&ORIGIN));
+        view.retry(9);
+        assert!(!view.queue.contains(&ORIGIN));
+        view.retry(10);
+        assert!(view.queue.contains(&ORIGIN));
+        assert!(view.failed.is_empty());
+
+        // Nearest first still holds after it is put back
+        let center = ChunkPos::new(1, 0, 0);
+        assert!(view
+            .queue
+            .windows(2)
+            .all(|x| x[0].distance_squared(center) >= x[1].distance_squared(center)));
+    }
+}
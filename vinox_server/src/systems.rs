@@ -0,0 +1,128 @@
+//! Gameplay systems run in a fixed order every tick. Network events only ever queue
+//! state onto entities (ie `MovementInput`) and the systems here act on it.
+
+use std::time::{Duration, Instant};
+
+use hecs::World;
+use vinox_common::prelude::{
+    Health, NetworkId, Player, Position, Rotation, ServerMessage, Velocity,
+};
+
+use crate::{
+    chunks::ChunkManager, config::Config, network::state::NetworkState, streaming::stream_chunks,
+};
+
+/// Health regained per second by players that are still alive
+const HEALTH_REGEN: f32 = 0.5;
+
+/// Everything a system can touch besides the world
+pub struct TickContext<'a> {
+    pub config: &'a Config,
+    pub network: &'a mut NetworkState,
+    pub chunks: &'a mut ChunkManager,
+    /// Seconds per tick
+    pub delta: f32,
+}
+
+pub type System = fn(&mut World, &mut TickContext);
+
+/// Systems to run each tick, in order
+pub struct Schedule {
+    systems: Vec<(&'static str, System)>,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Schedule::new()
+            .with("player_input", player_input)
+            .with("velocity", velocity)
+            .with("health", health)
+            .with("sync_movement", sync_movement)
+            .with("stream_chunks", stream_chunks)
+    }
+}
+
+impl Schedule {
+    /// An empty schedule
+    pub fn new() -> Self {
+        Self {
+            systems: Vec::new(),
+        }
+    }
+
+    /// Add a system to run after every system added so far
+    pub fn with(mut self, name: &'static str, system: System) -> Self {
+        self.systems.push((name, system));
+        self
+    }
+
+    /// Run every system, naming any that alone took longer than a whole tick
+    pub fn run(&self, world: &mut World, ctx: &mut TickContext) {
+        let budget = Duration::from_secs_f32(ctx.delta);
+        for (name, system) in self.systems.iter() {
+            let start = Instant::now();
+            system(world, ctx);
+            let elapsed = start.elapsed();
+            if elapsed > budget {
+                println!("System {name} took {elapsed:?}, longer than a {budget:?} tick");
+            }
+        }
+    }
+}
+
+/// Latest movement sent by a client, applied on the next tick
+#[derive(Default)]
+pub struct MovementInput(pub Option<(Position, Rotation)>);
+
+/// What was last sent to other clients about an entity
+pub struct Synced {
+    pub position: Position,
+    pub rotation: Rotation,
+}
+
+fn player_input(world: &mut World, _ctx: &mut TickContext) {
+    for (_, (input, position, rotation)) in
+        world.query_mut::<(&mut MovementInput, &mut Position, &mut Rotation)>()
+    {
+        if let Some((new_position, new_rotation)) = input.0.take() {
+            *position = new_position;
+            *rotation = new_rotation;
+        }
+    }
+}
+
+fn velocity(world: &mut World, ctx: &mut TickContext) {
+    for (_, (position, velocity)) in world.query_mut::<(&mut Position, &Velocity)>() {
+        position.translate(velocity.0 * ctx.delta);
+    }
+}
+
+fn health(world: &mut World, ctx: &mut TickContext) {
+    for (_, (health, _)) in world.query_mut::<(&mut Health, &Player)>() {
+        if !health.is_dead() {
+            health.current = (health.current + HEALTH_REGEN * ctx.delta).min(health.max);
+        }
+    }
+}
+
+/// Tell everyone else about entities that moved since they were last sent
+fn sync_movement(world: &mut World, ctx: &mut TickContext) {
+    for (_, (network_id, position, rotation, synced)) in
+        world.query_mut::<(&NetworkId, &Position, &Rotation, &mut Synced)>()
+    {
+        if synced.position == *position && synced.rotation == *rotation {
+            continue;
+        }
+        synced.position = *position;
+        synced.rotation = *rotation;
+        ctx.network.broadcast_except(
+            Some(network_id.0),
+            &ServerMessage::PlayerMoved {
+                client_id: network_id.0,
+                position: *position,
+                yaw: rotation.yaw,
+                pitch: rotation.pitch,
+            },
+        );
+    }
+}